@@ -0,0 +1,9 @@
+#version 450 core
+
+in vec4 VertColor;
+out vec4 Color;
+
+void main()
+{
+    Color = vec4(VertColor);
+}
\ No newline at end of file
@@ -0,0 +1,19 @@
+#version 450 core
+
+layout (location = 0) in vec3 Position;
+layout (location = 1) in vec3 Color;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+
+// rgb replaces the vertex color by the amount in a, so whole meshes can be drawn in one color
+uniform vec4 override_color;
+
+out vec4 VertColor;
+
+void main()
+{
+    gl_Position = projection * view * model * vec4(Position, 1.0);
+    VertColor = vec4(mix(Color, override_color.rgb, override_color.a), 1.0);
+}
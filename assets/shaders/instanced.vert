@@ -0,0 +1,23 @@
+#version 450 core
+
+layout (location = 0) in vec3 Position;
+layout (location = 1) in vec3 Color;
+layout (location = 8) in mat4 InstanceModel;
+layout (location = 12) in vec4 InstanceTint;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+// world space plane (normal, distance) to cut geometry at, see Water; all zero keeps everything
+uniform vec4 clip_plane = vec4(0.0);
+
+out vec4 VertColor;
+
+void main()
+{
+    vec4 world = model * InstanceModel * vec4(Position, 1.0);
+
+    gl_Position = projection * view * world;
+    gl_ClipDistance[0] = dot(world, clip_plane);
+    VertColor = vec4(Color, 1.0) * InstanceTint;
+}
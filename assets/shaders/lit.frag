@@ -0,0 +1,115 @@
+#version 450 core
+
+// MAX_LIGHTS and MAX_CASCADES are defined by the program loader, see LightSet::defines and
+// ShadowMap::defines
+
+struct Light {
+    vec4 position;    // xyz, w = kind: 0 directional, 1 point, 2 spot
+    vec4 direction;   // xyz, w = cos of the inner spot angle
+    vec4 color;       // rgb premultiplied by intensity, w = cos of the outer spot angle
+    vec4 attenuation; // constant, linear, quadratic
+};
+
+layout (std140, binding = 0) uniform Lights {
+    vec4 ambient;
+    int light_count;
+    Light lights[MAX_LIGHTS];
+};
+
+// cascaded shadows for the first light when it is directional, see ShadowMap::bind
+layout (binding = 1) uniform sampler2DArrayShadow shadow_map;
+uniform mat4 light_space[MAX_CASCADES];
+uniform float cascade_splits[MAX_CASCADES]; // far end of each cascade
+uniform int cascade_count = 0;
+uniform float shadow_bias = 0.002;
+
+uniform vec3 camera_position;
+uniform float shininess = 32.0;
+uniform float specular_strength = 0.3;
+
+in vec3 WorldPosition;
+in vec3 WorldNormal;
+in vec4 VertColor;
+in float ViewDepth;
+
+out vec4 Color;
+
+// fraction of the 3x3 texels around the fragment's position in the shadow map that are lit
+float shadow_factor(vec3 normal, vec3 to_light)
+{
+    if (cascade_count == 0) {
+        return 1.0;
+    }
+
+    int cascade = cascade_count - 1;
+    for (int i = 0; i < cascade_count; i++) {
+        if (ViewDepth < cascade_splits[i]) {
+            cascade = i;
+            break;
+        }
+    }
+
+    vec4 clip = light_space[cascade] * vec4(WorldPosition, 1.0);
+    vec3 coords = clip.xyz / clip.w * 0.5 + 0.5;
+
+    if (coords.z > 1.0) {
+        return 1.0;
+    }
+
+    // surfaces at a grazing angle to the light need more bias against acne
+    float bias = max(shadow_bias * (1.0 - dot(normal, to_light)), shadow_bias * 0.1);
+    vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0).xy);
+
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            lit += texture(shadow_map, vec4(coords.xy + vec2(x, y) * texel, cascade, coords.z - bias));
+        }
+    }
+
+    return lit / 9.0;
+}
+
+void main()
+{
+    vec3 normal = normalize(WorldNormal);
+    vec3 to_camera = normalize(camera_position - WorldPosition);
+
+    vec3 result = ambient.rgb * VertColor.rgb;
+
+    for (int i = 0; i < min(light_count, MAX_LIGHTS); i++) {
+        Light light = lights[i];
+
+        vec3 to_light;
+        float falloff = 1.0;
+
+        if (light.position.w == 0.0) {
+            to_light = -normalize(light.direction.xyz);
+        } else {
+            vec3 offset = light.position.xyz - WorldPosition;
+            float distance = length(offset);
+
+            to_light = offset / distance;
+            falloff = 1.0 / (light.attenuation.x + light.attenuation.y * distance + light.attenuation.z * distance * distance);
+
+            if (light.position.w == 2.0) {
+                float angle = dot(-to_light, normalize(light.direction.xyz));
+                falloff *= smoothstep(light.color.w, light.direction.w, angle);
+            }
+        }
+
+        // only the first light has a shadow map, and only when it is directional
+        if (i == 0 && light.position.w == 0.0) {
+            falloff *= shadow_factor(normal, to_light);
+        }
+
+        // Blinn-Phong: specular from the half vector between light and view directions
+        float diffuse = max(dot(normal, to_light), 0.0);
+        vec3 halfway = normalize(to_light + to_camera);
+        float specular = diffuse > 0.0 ? pow(max(dot(normal, halfway), 0.0), shininess) : 0.0;
+
+        result += (diffuse * VertColor.rgb + specular * specular_strength) * light.color.rgb * falloff;
+    }
+
+    Color = vec4(result, VertColor.a);
+}
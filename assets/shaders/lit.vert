@@ -0,0 +1,27 @@
+#version 450 core
+
+layout (location = 0) in vec3 Position;
+layout (location = 1) in vec3 Color;
+layout (location = 2) in vec3 Normal;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+
+out vec3 WorldPosition;
+out vec3 WorldNormal;
+out vec4 VertColor;
+out float ViewDepth;
+
+void main()
+{
+    vec4 world = model * vec4(Position, 1.0);
+
+    vec4 eye = view * world;
+
+    gl_Position = projection * eye;
+    ViewDepth = -eye.z;
+    WorldPosition = world.xyz;
+    WorldNormal = mat3(transpose(inverse(model))) * Normal; // stays perpendicular under non-uniform scale
+    VertColor = vec4(Color, 1.0);
+}
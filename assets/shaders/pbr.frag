@@ -0,0 +1,262 @@
+#version 450 core
+
+// Cook-Torrance metallic/roughness shading for Material.
+// MAX_LIGHTS and MAX_CASCADES are defined by the program loader, see LightSet::defines and
+// ShadowMap::defines
+
+struct Light {
+    vec4 position;    // xyz, w = kind: 0 directional, 1 point, 2 spot
+    vec4 direction;   // xyz, w = cos of the inner spot angle
+    vec4 color;       // rgb premultiplied by intensity, w = cos of the outer spot angle
+    vec4 attenuation; // constant, linear, quadratic
+};
+
+layout (std140, binding = 0) uniform Lights {
+    vec4 ambient;
+    int light_count;
+    Light lights[MAX_LIGHTS];
+};
+
+// cascaded shadows for the first light when it is directional, see ShadowMap::bind
+layout (binding = 1) uniform sampler2DArrayShadow shadow_map;
+uniform mat4 light_space[MAX_CASCADES];
+uniform float cascade_splits[MAX_CASCADES]; // far end of each cascade
+uniform int cascade_count = 0;
+uniform float shadow_bias = 0.002;
+
+// see Material::bind
+uniform vec4 base_color_factor = vec4(1.0);
+uniform float metallic_factor = 0.0;
+uniform float roughness_factor = 1.0;
+uniform vec3 emissive_factor = vec3(0.0);
+uniform float normal_scale = 1.0;
+uniform float occlusion_strength = 1.0;
+uniform int texture_flags = 0;
+
+layout (binding = 2) uniform sampler2D base_color_texture;
+layout (binding = 3) uniform sampler2D metallic_roughness_texture;
+layout (binding = 4) uniform sampler2D normal_texture;
+layout (binding = 5) uniform sampler2D occlusion_texture;
+layout (binding = 6) uniform sampler2D emissive_texture;
+
+// ground textures weighted by the rgba of the splat map, see Splat
+layout (binding = 7) uniform sampler2D splat_map;
+layout (binding = 8) uniform sampler2DArray splat_layers;
+uniform vec4 splat_tiling = vec4(1.0);
+uniform vec4 splat_area = vec4(0.0, 0.0, 1.0, 1.0); // min x, min z, width, depth
+uniform float splat_strength = 1.0;
+
+const int HAS_BASE_COLOR = 1;
+const int HAS_METALLIC_ROUGHNESS = 2;
+const int HAS_NORMAL = 4;
+const int HAS_OCCLUSION = 8;
+const int HAS_EMISSIVE = 16;
+const int HAS_SPLAT = 32;
+
+const float PI = 3.14159265359;
+
+uniform vec3 camera_position;
+
+in vec3 WorldPosition;
+in vec3 WorldNormal;
+in vec4 VertColor;
+in vec2 VertTexCoord;
+in vec4 WorldTangent;
+in float ViewDepth;
+
+out vec4 Color;
+
+// fraction of the 3x3 texels around the fragment's position in the shadow map that are lit
+float shadow_factor(vec3 normal, vec3 to_light)
+{
+    if (cascade_count == 0) {
+        return 1.0;
+    }
+
+    int cascade = cascade_count - 1;
+    for (int i = 0; i < cascade_count; i++) {
+        if (ViewDepth < cascade_splits[i]) {
+            cascade = i;
+            break;
+        }
+    }
+
+    vec4 clip = light_space[cascade] * vec4(WorldPosition, 1.0);
+    vec3 coords = clip.xyz / clip.w * 0.5 + 0.5;
+
+    if (coords.z > 1.0) {
+        return 1.0;
+    }
+
+    // surfaces at a grazing angle to the light need more bias against acne
+    float bias = max(shadow_bias * (1.0 - dot(normal, to_light)), shadow_bias * 0.1);
+    vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0).xy);
+
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            lit += texture(shadow_map, vec4(coords.xy + vec2(x, y) * texel, cascade, coords.z - bias));
+        }
+    }
+
+    return lit / 9.0;
+}
+
+// applies the normal map with the mesh's tangents, or a tangent frame built from screen space
+// derivatives for meshes without them
+vec3 mapped_normal(vec3 normal)
+{
+    if ((texture_flags & HAS_NORMAL) == 0) {
+        return normal;
+    }
+
+    vec3 sampled = texture(normal_texture, VertTexCoord).xyz * 2.0 - 1.0;
+    sampled.xy *= normal_scale;
+
+    if (dot(WorldTangent.xyz, WorldTangent.xyz) > 0.0) {
+        vec3 tangent = normalize(WorldTangent.xyz - normal * dot(normal, WorldTangent.xyz));
+        vec3 bitangent = cross(normal, tangent) * WorldTangent.w;
+
+        return normalize(mat3(tangent, bitangent, normal) * sampled);
+    }
+
+    vec3 dp1 = dFdx(WorldPosition);
+    vec3 dp2 = dFdy(WorldPosition);
+    vec2 duv1 = dFdx(VertTexCoord);
+    vec2 duv2 = dFdy(VertTexCoord);
+
+    vec3 dp2perp = cross(dp2, normal);
+    vec3 dp1perp = cross(normal, dp1);
+    vec3 tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+
+    float scale = inversesqrt(max(dot(tangent, tangent), dot(bitangent, bitangent)));
+    mat3 tbn = mat3(tangent * scale, bitangent * scale, normal);
+
+    return normalize(tbn * sampled);
+}
+
+// GGX / Trowbridge-Reitz normal distribution
+float distribution(float n_dot_h, float roughness)
+{
+    float a2 = roughness * roughness * roughness * roughness;
+    float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+
+    return a2 / (PI * d * d);
+}
+
+// Smith geometry term with Schlick-GGX for both the light and view directions
+float geometry(float n_dot_v, float n_dot_l, float roughness)
+{
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+
+    return n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+}
+
+vec3 fresnel(float cos_theta, vec3 f0)
+{
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// the ground textures at the fragment's position blended by the splat map
+vec4 splat_color()
+{
+    // the first and last texels sit on the edges of the area, on the first and last samples
+    vec2 size = vec2(textureSize(splat_map, 0));
+    vec2 along = (WorldPosition.xz - splat_area.xy) / splat_area.zw;
+    vec4 weights = texture(splat_map, (along * (size - 1.0) + 0.5) / size);
+
+    vec4 color = vec4(0.0);
+    for (int i = 0; i < 4; i++) {
+        color += weights[i] * texture(splat_layers, vec3(WorldPosition.xz / splat_tiling[i], i));
+    }
+
+    return color;
+}
+
+void main()
+{
+    vec4 base_color = base_color_factor * VertColor;
+    if ((texture_flags & HAS_SPLAT) != 0) {
+        base_color = base_color_factor * mix(VertColor, splat_color(), splat_strength);
+    }
+
+    float metallic = metallic_factor;
+    float roughness = roughness_factor;
+    float occlusion = 1.0;
+    vec3 emissive = emissive_factor;
+
+    if ((texture_flags & HAS_BASE_COLOR) != 0) {
+        base_color *= texture(base_color_texture, VertTexCoord);
+    }
+    if ((texture_flags & HAS_METALLIC_ROUGHNESS) != 0) {
+        vec4 sampled = texture(metallic_roughness_texture, VertTexCoord);
+        roughness *= sampled.g;
+        metallic *= sampled.b;
+    }
+    if ((texture_flags & HAS_OCCLUSION) != 0) {
+        occlusion = mix(1.0, texture(occlusion_texture, VertTexCoord).r, occlusion_strength);
+    }
+    if ((texture_flags & HAS_EMISSIVE) != 0) {
+        emissive *= texture(emissive_texture, VertTexCoord).rgb;
+    }
+
+    // fully smooth surfaces turn the highlight into a single infinitely bright point
+    roughness = clamp(roughness, 0.04, 1.0);
+
+    vec3 normal = mapped_normal(normalize(WorldNormal));
+    vec3 to_camera = normalize(camera_position - WorldPosition);
+    float n_dot_v = max(dot(normal, to_camera), 0.0001);
+
+    // dielectrics reflect about 4% head on, metals tint the reflection with their color
+    vec3 f0 = mix(vec3(0.04), base_color.rgb, metallic);
+    vec3 diffuse_color = base_color.rgb * (1.0 - metallic);
+
+    vec3 result = vec3(0.0);
+
+    for (int i = 0; i < min(light_count, MAX_LIGHTS); i++) {
+        Light light = lights[i];
+
+        vec3 to_light;
+        float falloff = 1.0;
+
+        if (light.position.w == 0.0) {
+            to_light = -normalize(light.direction.xyz);
+        } else {
+            vec3 offset = light.position.xyz - WorldPosition;
+            float distance = length(offset);
+
+            to_light = offset / distance;
+            falloff = 1.0 / (light.attenuation.x + light.attenuation.y * distance + light.attenuation.z * distance * distance);
+
+            if (light.position.w == 2.0) {
+                float angle = dot(-to_light, normalize(light.direction.xyz));
+                falloff *= smoothstep(light.color.w, light.direction.w, angle);
+            }
+        }
+
+        if (i == 0 && light.position.w == 0.0) {
+            falloff *= shadow_factor(normal, to_light);
+        }
+
+        float n_dot_l = max(dot(normal, to_light), 0.0);
+        if (n_dot_l == 0.0) {
+            continue;
+        }
+
+        // Cook-Torrance: D * G * F / (4 * n.l * n.v), plus Lambert for what isn't reflected
+        vec3 halfway = normalize(to_light + to_camera);
+        vec3 f = fresnel(max(dot(halfway, to_camera), 0.0), f0);
+        float d = distribution(max(dot(normal, halfway), 0.0), roughness);
+        float g = geometry(n_dot_v, n_dot_l, roughness);
+
+        vec3 specular = d * g * f / (4.0 * n_dot_v * n_dot_l);
+        vec3 diffuse = (1.0 - f) * diffuse_color / PI;
+
+        result += (diffuse + specular) * light.color.rgb * falloff * n_dot_l;
+    }
+
+    result += ambient.rgb * base_color.rgb * occlusion + emissive;
+
+    Color = vec4(result, base_color.a);
+}
@@ -0,0 +1,35 @@
+#version 450 core
+
+layout (location = 0) in vec3 Position;
+layout (location = 1) in vec3 Color;
+layout (location = 2) in vec3 Normal;
+layout (location = 3) in vec2 TexCoord;
+layout (location = 4) in vec4 Tangent; // w is the bitangent's sign, all zero without tangents
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+// world space plane (normal, distance) to cut geometry at, see Water; all zero keeps everything
+uniform vec4 clip_plane = vec4(0.0);
+
+out vec3 WorldPosition;
+out vec3 WorldNormal;
+out vec4 VertColor;
+out vec2 VertTexCoord;
+out vec4 WorldTangent;
+out float ViewDepth;
+
+void main()
+{
+    vec4 world = model * vec4(Position, 1.0);
+    vec4 eye = view * world;
+
+    gl_Position = projection * eye;
+    gl_ClipDistance[0] = dot(world, clip_plane);
+    WorldPosition = world.xyz;
+    WorldNormal = mat3(transpose(inverse(model))) * Normal;
+    VertColor = vec4(Color, 1.0);
+    VertTexCoord = TexCoord;
+    WorldTangent = vec4(mat3(model) * Tangent.xyz, Tangent.w);
+    ViewDepth = -eye.z;
+}
@@ -0,0 +1,21 @@
+#version 450 core
+
+// looks colors up in a 3D table, blended with the original by strength
+layout (binding = 0) uniform sampler2D input_image;
+layout (binding = 1) uniform sampler3D lut;
+uniform float lut_size = 2.0;
+uniform float strength = 1.0;
+
+in vec2 TexCoord;
+out vec4 Color;
+
+void main()
+{
+    vec3 color = texture(input_image, TexCoord).rgb;
+
+    // sample texel centers, so 0.0 and 1.0 hit the first and last entry exactly
+    vec3 coords = color * ((lut_size - 1.0) / lut_size) + 0.5 / lut_size;
+    vec3 graded = texture(lut, coords).rgb;
+
+    Color = vec4(mix(color, graded, strength), 1.0);
+}
@@ -0,0 +1,48 @@
+#version 450 core
+
+// fast approximate anti-aliasing: blurs along edges found from luma contrast. expects gamma
+// corrected colors, so it goes after the gamma pass
+layout (binding = 0) uniform sampler2D input_image;
+uniform vec2 texel_size;
+uniform float span_max = 8.0;
+uniform float reduce_min = 1.0 / 128.0;
+uniform float reduce_mul = 1.0 / 8.0;
+
+in vec2 TexCoord;
+out vec4 Color;
+
+float luma(vec3 color)
+{
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+void main()
+{
+    float nw = luma(texture(input_image, TexCoord + vec2(-1.0, -1.0) * texel_size).rgb);
+    float ne = luma(texture(input_image, TexCoord + vec2(1.0, -1.0) * texel_size).rgb);
+    float sw = luma(texture(input_image, TexCoord + vec2(-1.0, 1.0) * texel_size).rgb);
+    float se = luma(texture(input_image, TexCoord + vec2(1.0, 1.0) * texel_size).rgb);
+    vec3 center = texture(input_image, TexCoord).rgb;
+    float m = luma(center);
+
+    float luma_min = min(m, min(min(nw, ne), min(sw, se)));
+    float luma_max = max(m, max(max(nw, ne), max(sw, se)));
+
+    // the edge runs perpendicular to the luma gradient
+    vec2 direction = vec2(-((nw + ne) - (sw + se)), (nw + sw) - (ne + se));
+
+    float reduce = max((nw + ne + sw + se) * 0.25 * reduce_mul, reduce_min);
+    float scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, vec2(-span_max), vec2(span_max)) * texel_size;
+
+    vec3 near = 0.5 * (
+        texture(input_image, TexCoord + direction * (1.0 / 3.0 - 0.5)).rgb +
+        texture(input_image, TexCoord + direction * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 far = near * 0.5 + 0.25 * (
+        texture(input_image, TexCoord + direction * -0.5).rgb +
+        texture(input_image, TexCoord + direction * 0.5).rgb);
+
+    // the wider sample went past the edge if it leaves the local luma range
+    float far_luma = luma(far);
+    Color = vec4((far_luma < luma_min || far_luma > luma_max) ? near : far, 1.0);
+}
@@ -0,0 +1,15 @@
+#version 450 core
+
+// linear to display colors, the window's framebuffer isn't sRGB
+layout (binding = 0) uniform sampler2D input_image;
+uniform float gamma = 2.2;
+
+in vec2 TexCoord;
+out vec4 Color;
+
+void main()
+{
+    vec3 color = texture(input_image, TexCoord).rgb;
+
+    Color = vec4(pow(color, vec3(1.0 / gamma)), 1.0);
+}
@@ -0,0 +1,16 @@
+#version 450 core
+
+// HDR to 0..1 with the ACES filmic curve (Narkowicz's fit)
+layout (binding = 0) uniform sampler2D input_image;
+uniform float exposure = 1.0;
+
+in vec2 TexCoord;
+out vec4 Color;
+
+void main()
+{
+    vec3 x = texture(input_image, TexCoord).rgb * exposure;
+    vec3 mapped = (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+
+    Color = vec4(clamp(mapped, 0.0, 1.0), 1.0);
+}
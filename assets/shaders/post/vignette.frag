@@ -0,0 +1,18 @@
+#version 450 core
+
+// darkens towards the corners, starting at radius (0.0 center, 1.0 edge midpoints)
+layout (binding = 0) uniform sampler2D input_image;
+uniform float strength = 0.4;
+uniform float radius = 0.75;
+
+in vec2 TexCoord;
+out vec4 Color;
+
+void main()
+{
+    vec3 color = texture(input_image, TexCoord).rgb;
+    float distance = length(TexCoord * 2.0 - 1.0);
+    float shade = 1.0 - strength * smoothstep(radius, 1.5, distance);
+
+    Color = vec4(color * shade, 1.0);
+}
@@ -0,0 +1,11 @@
+#version 450 core
+
+layout (location = 0) in vec3 Position;
+
+uniform mat4 model;
+uniform mat4 light_space;
+
+void main()
+{
+    gl_Position = light_space * model * vec4(Position, 1.0);
+}
@@ -0,0 +1,90 @@
+#version 450 core
+
+// Water surface over the reflection and refraction passes of Water, see Water::draw
+
+layout (binding = 9) uniform sampler2D reflection_texture;
+layout (binding = 10) uniform sampler2D refraction_texture;
+layout (binding = 11) uniform sampler2D refraction_depth;
+
+uniform vec3 camera_position;
+uniform vec2 clip_range; // near and far distance of the camera
+uniform float time;
+
+uniform vec3 water_color = vec3(0.05, 0.2, 0.25);
+uniform float clarity = 8.0;        // depth at which the ground below disappears
+uniform float shore_fade = 1.5;     // depth over which the water fades in at the shore
+uniform float wave_strength = 0.02; // how far waves shift the reflection and refraction
+uniform float wave_scale = 6.0;     // world units across the longest wave
+uniform float wave_speed = 1.0;
+
+uniform vec3 sun_direction; // the way the light travels
+uniform vec3 sun_color;
+
+in vec3 WorldPosition;
+in vec4 ClipPosition;
+
+out vec4 Color;
+
+const float PI = 3.14159265359;
+const int WAVE_COUNT = 4;
+
+// direction, wavelength as a fraction of wave_scale, and speed of every wave
+const vec4 WAVES[WAVE_COUNT] = vec4[](
+    vec4(1.0, 0.3, 1.0, 1.0),
+    vec4(-0.4, 1.0, 0.61, 0.8),
+    vec4(0.7, -0.8, 0.37, 1.3),
+    vec4(-0.9, -0.2, 0.23, 1.7)
+);
+
+// normal of a few sine waves running over the surface, all equally steep
+vec3 wave_normal(vec2 position)
+{
+    vec2 slope = vec2(0.0);
+
+    for (int i = 0; i < WAVE_COUNT; i++) {
+        vec2 direction = normalize(WAVES[i].xy);
+        float frequency = 2.0 * PI / (wave_scale * WAVES[i].z);
+        float phase = dot(direction, position) * frequency + time * wave_speed * WAVES[i].w;
+
+        slope += direction * cos(phase) * 0.05;
+    }
+
+    return normalize(vec3(-slope.x, 1.0, -slope.y));
+}
+
+// distance from the camera of a depth buffer value
+float linear_depth(float depth)
+{
+    float near = clip_range.x;
+    float far = clip_range.y;
+    float z = depth * 2.0 - 1.0;
+
+    return 2.0 * near * far / (far + near - z * (far - near));
+}
+
+void main()
+{
+    vec2 screen = ClipPosition.xy / ClipPosition.w * 0.5 + 0.5;
+    vec3 normal = wave_normal(WorldPosition.xz);
+
+    // how much water the view ray goes through before it hits the ground
+    float depth = linear_depth(texture(refraction_depth, screen).r) - linear_depth(gl_FragCoord.z);
+    float shore = clamp(depth / shore_fade, 0.0, 1.0);
+
+    // calmer at the shore, so the waves don't pull in what is above the water
+    vec2 offset = normal.xz * wave_strength * shore;
+    vec3 reflection = texture(reflection_texture, clamp(screen + offset, 0.001, 0.999)).rgb;
+    vec3 refraction = texture(refraction_texture, clamp(screen + offset, 0.001, 0.999)).rgb;
+    refraction = mix(refraction, water_color, clamp(depth / clarity, 0.0, 1.0));
+
+    // Schlick's approximation, water reflects about 2% straight on
+    vec3 to_eye = normalize(camera_position - WorldPosition);
+    float fresnel = 0.02 + 0.98 * pow(1.0 - max(dot(normal, to_eye), 0.0), 5.0);
+
+    vec3 color = mix(refraction, reflection, fresnel);
+
+    vec3 half_vector = normalize(to_eye - normalize(sun_direction));
+    color += sun_color * pow(max(dot(normal, half_vector), 0.0), 300.0) * shore;
+
+    Color = vec4(color, shore);
+}
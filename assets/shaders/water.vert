@@ -0,0 +1,19 @@
+#version 450 core
+
+layout (location = 0) in vec3 Position;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+
+out vec3 WorldPosition;
+out vec4 ClipPosition;
+
+void main()
+{
+    vec4 world = model * vec4(Position, 1.0);
+
+    WorldPosition = world.xyz;
+    ClipPosition = projection * view * world;
+    gl_Position = ClipPosition;
+}
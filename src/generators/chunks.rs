@@ -0,0 +1,406 @@
+extern crate nalgebra_glm as glm;
+
+use crate::generators::heightfield::{Heightfield, heightfield_mesh_shaded};
+use crate::render_gl::mesh::{Mesh, Shading};
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "A chunk layout needs at least one level of detail")]
+    NoLevels,
+    #[fail(display = "Chunk resolution {} is not a power of two", resolution)]
+    Resolution { resolution: u32 },
+}
+
+/// Which chunk, counted in chunks from the one whose corner is at the origin.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkCoord {
+    pub x: i32,
+    pub z: i32,
+}
+
+/// How a terrain is split into square chunks, and which level of detail each chunk gets.
+/// Level 0 is the finest, every level after it has half the cells along each side.
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkLayout {
+    pub chunk_size: f32,
+    /// cells along each side of a chunk at level 0, a power of two so every level lines up
+    pub resolution: u32,
+    /// at least 1
+    pub lod_count: u32,
+    /// chunks closer than this get level 0, each level after that starts twice as far out
+    pub lod_distance: f32,
+    /// chunks further away than this are left out
+    pub view_distance: f32,
+    /// smooth normals stop at the edges of a chunk, so the light may not quite match across
+    pub shading: Shading,
+}
+
+impl Default for ChunkLayout {
+    fn default() -> Self {
+        ChunkLayout {
+            chunk_size: 64.0,
+            resolution: 32,
+            lod_count: 4,
+            lod_distance: 96.0,
+            view_distance: 512.0,
+            shading: Shading::Flat,
+        }
+    }
+}
+
+impl ChunkLayout {
+    /// Whether the layout can be built: every level has to take whole steps of level 0 samples
+    /// across a chunk, or its edges would end short of the neighbours'.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.lod_count == 0 {
+            return Err(Error::NoLevels);
+        }
+
+        if !self.resolution.is_power_of_two() {
+            return Err(Error::Resolution { resolution: self.resolution });
+        }
+
+        Ok(())
+    }
+
+    pub fn chunk_at(&self, x: f32, z: f32) -> ChunkCoord {
+        ChunkCoord { x: (x / self.chunk_size).floor() as i32, z: (z / self.chunk_size).floor() as i32 }
+    }
+
+    /// World (x, z) of the middle of a chunk.
+    pub fn center(&self, coord: ChunkCoord) -> (f32, f32) {
+        ((coord.x as f32 + 0.5) * self.chunk_size, (coord.z as f32 + 0.5) * self.chunk_size)
+    }
+
+    /// Cells along each side of a chunk at `lod`.
+    pub fn cells(&self, lod: u32) -> u32 {
+        (self.resolution >> lod).max(1)
+    }
+
+    /// Distance along the ground from `position` to the nearest point of a chunk, 0.0 when
+    /// it is above the chunk.
+    pub fn distance(&self, coord: ChunkCoord, position: &glm::Vec3) -> f32 {
+        let (center_x, center_z) = self.center(coord);
+        let half = self.chunk_size / 2.0;
+
+        let dx = ((position.x - center_x).abs() - half).max(0.0);
+        let dz = ((position.z - center_z).abs() - half).max(0.0);
+
+        (dx * dx + dz * dz).sqrt()
+    }
+
+    pub fn lod_at(&self, distance: f32) -> u32 {
+        if distance < self.lod_distance {
+            return 0;
+        }
+
+        let lod = (distance / self.lod_distance).log2().floor() as u32 + 1;
+        lod.min(self.lod_count.max(1) - 1)
+    }
+
+    /// Every chunk in view of `position` with its level of detail, nearest first. Nothing is in
+    /// view of a position that isn't finite.
+    pub fn select(&self, position: &glm::Vec3) -> Vec<(ChunkCoord, u32)> {
+        if !(position.x.is_finite() && position.y.is_finite() && position.z.is_finite()) {
+            return vec![];
+        }
+
+        let reach = (self.view_distance / self.chunk_size).ceil() as i32 + 1;
+        let center = self.chunk_at(position.x, position.z);
+
+        let mut selected: Vec<(f32, ChunkCoord)> = Vec::new();
+
+        for z in center.z - reach..=center.z + reach {
+            for x in center.x - reach..=center.x + reach {
+                let coord = ChunkCoord { x, z };
+                let distance = self.distance(coord, position);
+
+                if distance <= self.view_distance {
+                    selected.push((distance, coord));
+                }
+            }
+        }
+
+        // every distance is finite from a finite position
+        selected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        selected.into_iter().map(|(distance, coord)| (coord, self.lod_at(distance))).collect()
+    }
+
+    /// World position of level 0 sample `i` counted from world 0.0.
+    fn sample_position(&self, i: i64) -> f32 {
+        // from whole sample counts, so neighbouring chunks land on exactly the same positions
+        i as f32 * (self.chunk_size / self.resolution as f32)
+    }
+}
+
+/// The heights of a chunk at `lod`, centered on the origin.
+pub fn chunk_heightfield<F: Fn(f32, f32) -> f32>(layout: &ChunkLayout, coord: ChunkCoord, lod: u32, height_at: &F) -> Heightfield {
+    debug_assert!(layout.validate().is_ok(), "invalid chunk layout {:?}", layout);
+
+    let cells = layout.cells(lod) as i64;
+    let step = layout.resolution as i64 / cells;
+    let first_x = coord.x as i64 * layout.resolution as i64;
+    let first_z = coord.z as i64 * layout.resolution as i64;
+
+    let mut heights = Vec::with_capacity(((cells + 1) * (cells + 1)) as usize);
+
+    for row in 0..=cells {
+        for column in 0..=cells {
+            heights.push(height_at(
+                layout.sample_position(first_x + column * step),
+                layout.sample_position(first_z + row * step),
+            ));
+        }
+    }
+
+    Heightfield::from_heights(cells as usize + 1, cells as usize + 1, layout.chunk_size, layout.chunk_size, heights)
+}
+
+/// How far the skirts of a chunk have to hang down. Along an edge every level differs from
+/// level 0 by at most the worst error, so two neighbours differ by at most twice that.
+pub fn skirt_depth<F: Fn(f32, f32) -> f32>(layout: &ChunkLayout, coord: ChunkCoord, height_at: &F) -> f32 {
+    let resolution = layout.resolution as i64;
+    let first_x = coord.x as i64 * resolution;
+    let first_z = coord.z as i64 * resolution;
+
+    let edges: Vec<Vec<f32>> = [
+        (first_x, first_z, 1, 0),
+        (first_x, first_z + resolution, 1, 0),
+        (first_x, first_z, 0, 1),
+        (first_x + resolution, first_z, 0, 1),
+    ].iter().map(|&(x, z, step_x, step_z)| {
+        (0..=resolution).map(|i| height_at(
+            layout.sample_position(x + i * step_x),
+            layout.sample_position(z + i * step_z),
+        )).collect()
+    }).collect();
+
+    let mut worst: f32 = 0.0;
+
+    for lod in 1..layout.lod_count {
+        let step = (resolution / layout.cells(lod) as i64) as usize;
+
+        for edge in edges.iter() {
+            for (i, &height) in edge.iter().enumerate() {
+                let start = (i / step * step).min(edge.len() - 1 - step);
+                let t = (i - start) as f32 / step as f32;
+                let coarse = edge[start] + (edge[start + step] - edge[start]) * t;
+
+                worst = worst.max((height - coarse).abs());
+            }
+        }
+    }
+
+    // a little extra for rounding
+    2.0 * worst + layout.chunk_size * 0.001
+}
+
+/// A chunk's surface with a skirt hanging `skirt_depth` down from every edge, centered on the
+/// origin. Skirts are always flat shaded.
+pub fn chunk_mesh(field: &Heightfield, skirt_depth: f32, shade_range: (f32, f32), shading: Shading) -> Mesh {
+    let mut mesh = heightfield_mesh_shaded(field, shade_range, shading);
+    let mut skirts = Mesh::default();
+    let (min, max) = shade_range;
+    let span = if max > min { max - min } else { 1.0 };
+
+    let (columns, rows) = (field.columns(), field.rows());
+    let last_column = columns - 1;
+    let last_row = rows - 1;
+
+    // samples along every edge, with the way out of the chunk
+    let edges: [(Vec<(usize, usize)>, glm::Vec3); 4] = [
+        ((0..columns).map(|c| (c, 0)).collect(), glm::vec3(0.0, 0.0, -1.0)),
+        ((0..columns).map(|c| (c, last_row)).collect(), glm::vec3(0.0, 0.0, 1.0)),
+        ((0..rows).map(|r| (0, r)).collect(), glm::vec3(-1.0, 0.0, 0.0)),
+        ((0..rows).map(|r| (last_column, r)).collect(), glm::vec3(1.0, 0.0, 0.0)),
+    ];
+
+    for (samples, outward) in edges.iter() {
+        for pair in samples.windows(2) {
+            let top = |(column, row): (usize, usize)| {
+                let (x, z) = field.position(column, row);
+                glm::vec3(x, field.get(column, row), z)
+            };
+
+            let (a, b) = (top(pair[0]), top(pair[1]));
+            let down = glm::vec3(0.0, skirt_depth, 0.0);
+            let (a_bottom, b_bottom) = (a - down, b - down);
+
+            // the skirt faces out of the chunk
+            let normal = glm::cross::<f32, glm::U3>(&(b_bottom - a), &(a_bottom - a));
+            let corners = if glm::dot(&normal, outward) > 0.0 {
+                [a, b_bottom, a_bottom, a, b, b_bottom]
+            } else {
+                [a, a_bottom, b_bottom, a, b_bottom, b]
+            };
+
+            let shade = ((a.y + b.y) / 2.0 - min) / span;
+
+            for corner in corners.iter() {
+                skirts.positions.push((corner.x, corner.y, corner.z));
+                skirts.colors.push((shade, shade, shade));
+            }
+        }
+    }
+
+    skirts.compute_normals();
+    mesh.append(&skirts);
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::terrain::{Terrain, TerrainSettings};
+
+    fn layout() -> ChunkLayout {
+        ChunkLayout { chunk_size: 32.0, resolution: 16, lod_count: 4, lod_distance: 40.0, view_distance: 200.0, shading: Shading::Flat }
+    }
+
+    fn terrain() -> Terrain {
+        // rough enough that coarse levels miss a lot
+        Terrain::new(TerrainSettings { seed: 8, scale: 20.0, ..TerrainSettings::default() })
+    }
+
+    #[test]
+    fn chunks_tile_the_plane() {
+        let layout = layout();
+
+        assert_eq!(layout.chunk_at(0.0, 0.0), ChunkCoord { x: 0, z: 0 });
+        assert_eq!(layout.chunk_at(-0.1, 31.9), ChunkCoord { x: -1, z: 0 });
+        assert_eq!(layout.chunk_at(64.0, -64.1), ChunkCoord { x: 2, z: -3 });
+        assert_eq!(layout.center(ChunkCoord { x: -1, z: 2 }), (-16.0, 80.0));
+    }
+
+    #[test]
+    fn detail_drops_with_distance() {
+        let layout = layout();
+
+        assert_eq!(layout.lod_at(0.0), 0);
+        assert_eq!(layout.lod_at(39.0), 0);
+        assert_eq!(layout.lod_at(40.0), 1);
+        assert_eq!(layout.lod_at(80.0), 2);
+        assert_eq!(layout.lod_at(1000.0), 3);
+
+        let single = ChunkLayout { lod_count: 1, ..layout };
+        assert_eq!(single.lod_at(1000.0), 0);
+    }
+
+    #[test]
+    fn layouts_need_a_level_and_a_power_of_two() {
+        assert!(layout().validate().is_ok());
+
+        match (ChunkLayout { lod_count: 0, ..layout() }).validate() {
+            Err(Error::NoLevels) => {},
+            other => panic!("{:?}", other),
+        }
+
+        match (ChunkLayout { resolution: 24, ..layout() }).validate() {
+            Err(Error::Resolution { resolution: 24 }) => {},
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn nothing_is_in_view_of_a_nan_position() {
+        assert!(layout().select(&glm::vec3(std::f32::NAN, 0.0, 0.0)).is_empty());
+        assert!(layout().select(&glm::vec3(0.0, 0.0, std::f32::INFINITY)).is_empty());
+    }
+
+    #[test]
+    fn selection_covers_the_view_nearest_first() {
+        let layout = layout();
+        let position = glm::vec3(10.0, 50.0, -5.0);
+        let selected = layout.select(&position);
+
+        assert_eq!(selected[0], (ChunkCoord { x: 0, z: -1 }, 0));
+
+        let distances: Vec<f32> = selected.iter().map(|&(coord, _)| layout.distance(coord, &position)).collect();
+        assert!(distances.windows(2).all(|d| d[0] <= d[1]));
+        assert!(distances.iter().all(|&d| d <= layout.view_distance));
+
+        for &(coord, lod) in selected.iter() {
+            assert_eq!(lod, layout.lod_at(layout.distance(coord, &position)));
+        }
+
+        // nothing in view is missing
+        let (x, z) = (position.x + 190.0, position.z);
+        assert!(selected.iter().any(|&(coord, _)| coord == layout.chunk_at(x, z)));
+        assert!(selected.iter().any(|&(_, lod)| lod == 3));
+    }
+
+    #[test]
+    fn levels_halve_the_cells() {
+        let layout = layout();
+        let terrain = terrain();
+        let height_at = |x, z| terrain.height_at(x, z);
+        let coord = ChunkCoord { x: 3, z: -2 };
+
+        for lod in 0..layout.lod_count {
+            let cells = (16 >> lod) as usize;
+            let field = chunk_heightfield(&layout, coord, lod, &height_at);
+            let mesh = chunk_mesh(&field, 1.0, (0.0, 45.0), Shading::Flat);
+
+            assert_eq!((field.columns(), field.rows()), (cells + 1, cells + 1));
+            // the surface plus a skirt along each edge
+            assert_eq!(mesh.positions.len(), cells * cells * 6 + 4 * cells * 6);
+        }
+    }
+
+    #[test]
+    fn skirts_close_the_seams_between_levels() {
+        let layout = layout();
+        let terrain = terrain();
+        let height_at = |x, z| terrain.height_at(x, z);
+        let left = ChunkCoord { x: -1, z: 4 };
+        let right = ChunkCoord { x: 0, z: 4 };
+
+        for &(left_lod, right_lod) in [(0, 0), (0, 1), (0, 3), (2, 1), (3, 0)].iter() {
+            let left_field = chunk_heightfield(&layout, left, left_lod, &height_at);
+            let right_field = chunk_heightfield(&layout, right, right_lod, &height_at);
+            let left_skirt = skirt_depth(&layout, left, &height_at);
+            let right_skirt = skirt_depth(&layout, right, &height_at);
+
+            for i in 0..=64 {
+                let z = -16.0 + i as f32 * 0.5;
+                let left_height = left_field.height_at(16.0, z);
+                let right_height = right_field.height_at(-16.0, z);
+
+                // the higher edge's skirt has to reach down to the lower one
+                let (gap, skirt) = if left_height > right_height {
+                    (left_height - right_height, left_skirt)
+                } else {
+                    (right_height - left_height, right_skirt)
+                };
+
+                assert!(gap <= skirt, "{:?}: gap of {} at z {} under a skirt of {}", (left_lod, right_lod), gap, z, skirt);
+            }
+
+            if left_lod == right_lod {
+                assert_eq!(left_field.height_at(16.0, 3.0), right_field.height_at(-16.0, 3.0));
+            }
+        }
+    }
+
+    #[test]
+    fn skirts_hang_below_the_edges_facing_out() {
+        let layout = layout();
+        let terrain = terrain();
+        let field = chunk_heightfield(&layout, ChunkCoord { x: 0, z: 0 }, 2, &|x, z| terrain.height_at(x, z));
+        let mesh = chunk_mesh(&field, 5.0, (0.0, 45.0), Shading::Flat);
+
+        let (min, _) = field.range();
+        let (low, _) = mesh.bounds();
+        assert!((low.y - (min - 5.0)).abs() < 1e-4);
+
+        // past the surface, all vertices belong to skirts, which face away from the middle
+        let surface = 4 * 4 * 6;
+        for i in surface..mesh.positions.len() {
+            let (x, _, z) = mesh.positions[i];
+            let (nx, ny, nz) = mesh.normals[i];
+
+            assert!(ny.abs() < 1e-4);
+            assert!(nx * x + nz * z > 0.0);
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use crate::generators::heightfield::{Heightfield};
+use crate::generators::random::{Rng};
+
+/// Rain drops rolling downhill one after another, picking up material where they speed up and
+/// dropping it where they slow down or fill a pit. Carves gullies and fills valleys. Distances
+/// are in samples, not world units, so the same settings work for any spacing.
+#[derive(Clone, Copy, Debug)]
+pub struct HydraulicErosion {
+    pub seed: u32,
+    pub droplets: u32,
+    /// steps a drop takes before it is dropped
+    pub lifetime: u32,
+    /// 0.0 turns straight down the slope, 1.0 ignores the slope
+    pub inertia: f32,
+    /// how much a drop can carry per unit of speed, water and drop in height
+    pub sediment_capacity: f32,
+    /// what a drop can always carry, so it keeps eroding on gentle slopes
+    pub min_capacity: f32,
+    /// part of the sediment over capacity dropped per step
+    pub deposition: f32,
+    /// part of the free capacity picked up per step
+    pub erosion: f32,
+    /// part of the water lost per step
+    pub evaporation: f32,
+    pub gravity: f32,
+}
+
+impl Default for HydraulicErosion {
+    fn default() -> Self {
+        HydraulicErosion {
+            seed: 0,
+            droplets: 20000,
+            lifetime: 30,
+            inertia: 0.05,
+            sediment_capacity: 4.0,
+            min_capacity: 0.01,
+            deposition: 0.3,
+            erosion: 0.3,
+            evaporation: 0.01,
+            gravity: 4.0,
+        }
+    }
+}
+
+impl HydraulicErosion {
+    /// Erodes `field`. Material only moves around, the sum of all heights stays the same.
+    #[allow(dead_code)]
+    pub fn apply(&self, field: &mut Heightfield) {
+        let mut rng = Rng::new(self.seed as u64);
+        let (max_x, max_y) = ((field.columns() - 1) as f32, (field.rows() - 1) as f32);
+
+        for _ in 0..self.droplets {
+            let mut position = (rng.next_f32() * max_x, rng.next_f32() * max_y);
+            let mut direction = (0.0, 0.0);
+            let mut speed = 1.0;
+            let mut water = 1.0;
+            let mut sediment = 0.0;
+
+            for _ in 0..self.lifetime {
+                let (height, gradient) = height_and_gradient(field, position);
+
+                direction = (
+                    direction.0 * self.inertia - gradient.0 * (1.0 - self.inertia),
+                    direction.1 * self.inertia - gradient.1 * (1.0 - self.inertia),
+                );
+
+                let length = (direction.0 * direction.0 + direction.1 * direction.1).sqrt();
+
+                // stuck in a flat spot
+                if length == 0.0 {
+                    break;
+                }
+
+                direction = (direction.0 / length, direction.1 / length);
+                let next = (position.0 + direction.0, position.1 + direction.1);
+
+                // the sediment would leave the map with the drop
+                if next.0 < 0.0 || next.0 > max_x || next.1 < 0.0 || next.1 > max_y {
+                    break;
+                }
+
+                let height_change = height_and_gradient(field, next).0 - height;
+                let capacity = (-height_change * speed * water * self.sediment_capacity).max(self.min_capacity);
+
+                if height_change > 0.0 || sediment > capacity {
+                    // uphill the drop fills the pit behind it, never more than it is deep
+                    let amount = if height_change > 0.0 {
+                        height_change.min(sediment)
+                    } else {
+                        (sediment - capacity) * self.deposition
+                    };
+
+                    sediment -= amount;
+                    spread(field, position, amount);
+                } else {
+                    // digging deeper than the drop falls would leave a pit behind
+                    let amount = ((capacity - sediment) * self.erosion).min(-height_change);
+
+                    sediment += amount;
+                    spread(field, position, -amount);
+                }
+
+                speed = (speed * speed - height_change * self.gravity).max(0.0).sqrt();
+                water *= 1.0 - self.evaporation;
+                position = next;
+            }
+
+            // whatever the drop still carries stays where it ended
+            spread(field, position, sediment);
+        }
+    }
+}
+
+/// Material sliding off slopes steeper than the talus angle onto lower neighbours, until
+/// scree slopes are left. Rounds off cliffs and spikes.
+#[derive(Clone, Copy, Debug)]
+pub struct ThermalErosion {
+    /// steepest stable slope, in radians
+    pub talus_angle: f32,
+    pub iterations: u32,
+    /// part of the unstable material moved per iteration, 0.0..=1.0
+    pub rate: f32,
+}
+
+impl Default for ThermalErosion {
+    fn default() -> Self {
+        ThermalErosion { talus_angle: 35.0f32.to_radians(), iterations: 50, rate: 0.5 }
+    }
+}
+
+impl ThermalErosion {
+    /// Erodes `field`. Material only moves around, the sum of all heights stays the same.
+    #[allow(dead_code)]
+    pub fn apply(&self, field: &mut Heightfield) {
+        let (columns, rows) = (field.columns(), field.rows());
+        let (dx, dz) = field.spacing();
+        let talus = self.talus_angle.tan();
+
+        // the four direct neighbours, and the height difference each can take
+        let neighbours: [(isize, isize, f32); 4] = [
+            (-1, 0, talus * dx),
+            (1, 0, talus * dx),
+            (0, -1, talus * dz),
+            (0, 1, talus * dz),
+        ];
+
+        let mut changes = vec![0.0; columns * rows];
+
+        for _ in 0..self.iterations {
+            for change in changes.iter_mut() {
+                *change = 0.0;
+            }
+
+            for row in 0..rows {
+                for column in 0..columns {
+                    let height = field.get(column, row);
+                    let mut excess = [0.0; 4];
+
+                    for (i, &(step_x, step_z, stable)) in neighbours.iter().enumerate() {
+                        let (x, z) = (column as isize + step_x, row as isize + step_z);
+
+                        if x >= 0 && z >= 0 && (x as usize) < columns && (z as usize) < rows {
+                            excess[i] = (height - field.get(x as usize, z as usize) - stable).max(0.0);
+                        }
+                    }
+
+                    let total: f32 = excess.iter().sum();
+                    if total == 0.0 {
+                        continue;
+                    }
+
+                    // half of the steepest drop at most, so the two never swap places
+                    let moved = self.rate * 0.5 * excess.iter().cloned().fold(0.0, f32::max);
+                    changes[row * columns + column] -= moved;
+
+                    for (i, &(step_x, step_z, _)) in neighbours.iter().enumerate() {
+                        if excess[i] > 0.0 {
+                            let (x, z) = ((column as isize + step_x) as usize, (row as isize + step_z) as usize);
+                            changes[z * columns + x] += moved * excess[i] / total;
+                        }
+                    }
+                }
+            }
+
+            for (height, change) in field.heights_mut().iter_mut().zip(changes.iter()) {
+                *height += change;
+            }
+        }
+    }
+}
+
+/// Height at a position in samples, and how it changes per sample along both axes.
+fn height_and_gradient(field: &Heightfield, (x, y): (f32, f32)) -> (f32, (f32, f32)) {
+    let (column, row) = cell(field, x, y);
+    let (fx, fy) = (x - column as f32, y - row as f32);
+
+    let top_left = field.get(column, row);
+    let top_right = field.get(column + 1, row);
+    let bottom_left = field.get(column, row + 1);
+    let bottom_right = field.get(column + 1, row + 1);
+
+    let height = top_left * (1.0 - fx) * (1.0 - fy)
+        + top_right * fx * (1.0 - fy)
+        + bottom_left * (1.0 - fx) * fy
+        + bottom_right * fx * fy;
+
+    let gradient = (
+        (top_right - top_left) * (1.0 - fy) + (bottom_right - bottom_left) * fy,
+        (bottom_left - top_left) * (1.0 - fx) + (bottom_right - top_right) * fx,
+    );
+
+    (height, gradient)
+}
+
+/// Adds `amount` around a position in samples, split over the four corners of its cell by
+/// how close they are.
+fn spread(field: &mut Heightfield, (x, y): (f32, f32), amount: f32) {
+    let (column, row) = cell(field, x, y);
+    let (fx, fy) = (x - column as f32, y - row as f32);
+
+    let corners = [
+        (column, row, (1.0 - fx) * (1.0 - fy)),
+        (column + 1, row, fx * (1.0 - fy)),
+        (column, row + 1, (1.0 - fx) * fy),
+        (column + 1, row + 1, fx * fy),
+    ];
+
+    for &(c, r, weight) in corners.iter() {
+        let height = field.get(c, r);
+        field.set(c, r, height + amount * weight);
+    }
+}
+
+fn cell(field: &Heightfield, x: f32, y: f32) -> (usize, usize) {
+    // the last samples belong to the cells before them
+    ((x as usize).min(field.columns() - 2), (y as usize).min(field.rows() - 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::noise::{FractalKind};
+    use crate::generators::terrain::{Terrain, TerrainSettings};
+
+    fn terrain() -> Heightfield {
+        let mut settings = TerrainSettings { seed: 3, ..TerrainSettings::default() };
+        settings.fractal.kind = FractalKind::Ridged;
+
+        Terrain::new(settings).heightfield(100.0, 100.0, 63)
+    }
+
+    fn total(field: &Heightfield) -> f64 {
+        field.heights().iter().map(|&h| h as f64).sum()
+    }
+
+    fn steepest(field: &Heightfield) -> f32 {
+        let (dx, dz) = field.spacing();
+        let mut steepest: f32 = 0.0;
+
+        for row in 0..field.rows() {
+            for column in 0..field.columns() {
+                if column + 1 < field.columns() {
+                    steepest = steepest.max((field.get(column, row) - field.get(column + 1, row)).abs() / dx);
+                }
+                if row + 1 < field.rows() {
+                    steepest = steepest.max((field.get(column, row) - field.get(column, row + 1)).abs() / dz);
+                }
+            }
+        }
+
+        steepest
+    }
+
+    #[test]
+    fn hydraulic_erosion_conserves_mass() {
+        let mut field = terrain();
+        let before = total(&field);
+
+        HydraulicErosion { droplets: 5000, ..HydraulicErosion::default() }.apply(&mut field);
+
+        assert_ne!(field, terrain());
+        assert!((total(&field) - before).abs() < before * 1e-4, "{} became {}", before, total(&field));
+    }
+
+    #[test]
+    fn hydraulic_erosion_is_reproducible_from_its_seed() {
+        let erode = |seed| {
+            let mut field = terrain();
+            HydraulicErosion { seed, droplets: 2000, ..HydraulicErosion::default() }.apply(&mut field);
+            field
+        };
+
+        assert_eq!(erode(1), erode(1));
+        assert_ne!(erode(1), erode(2));
+    }
+
+    #[test]
+    fn rain_on_flat_ground_changes_nothing() {
+        let mut field = Heightfield::from_fn(16, 16, 10.0, 10.0, |_, _| 2.0);
+
+        HydraulicErosion { droplets: 500, ..HydraulicErosion::default() }.apply(&mut field);
+
+        assert!(field.heights().iter().all(|&h| h == 2.0));
+    }
+
+    #[test]
+    fn thermal_erosion_conserves_mass() {
+        let mut field = terrain();
+        let before = total(&field);
+
+        ThermalErosion::default().apply(&mut field);
+
+        assert!((total(&field) - before).abs() < before * 1e-5, "{} became {}", before, total(&field));
+    }
+
+    #[test]
+    fn thermal_erosion_flattens_slopes_to_the_talus_angle() {
+        // a single spike, far steeper than any scree would stay
+        let mut field = Heightfield::from_fn(21, 21, 20.0, 20.0, |x, z| if x.abs() < 0.5 && z.abs() < 0.5 { 20.0 } else { 0.0 });
+        let erosion = ThermalErosion { talus_angle: 30.0f32.to_radians(), iterations: 2000, rate: 0.5 };
+
+        erosion.apply(&mut field);
+
+        assert!(steepest(&field) < erosion.talus_angle.tan() * 1.01, "slope is still {}", steepest(&field));
+    }
+
+    #[test]
+    fn slopes_below_the_talus_angle_stay() {
+        let mut field = Heightfield::from_fn(8, 8, 7.0, 7.0, |x, _| x * 0.5);
+        let original = field.clone();
+
+        ThermalErosion { talus_angle: 30.0f32.to_radians(), ..ThermalErosion::default() }.apply(&mut field);
+
+        assert_eq!(field, original);
+    }
+}
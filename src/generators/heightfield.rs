@@ -0,0 +1,388 @@
+extern crate nalgebra_glm as glm;
+
+use std::rc::Rc;
+
+use crate::render_gl::buffer::{BufferUsage};
+use crate::render_gl::device::{Device};
+use crate::render_gl::material::{Material};
+use crate::render_gl::mesh::{Mesh, Shading};
+use crate::render_gl::object::{Object};
+
+// corners of the two triangles in a grid cell, as (column, row) steps. counter-clockwise seen
+// from above, so their normals point up
+const CELL_TRIANGLES: [[(usize, usize); 3]; 2] = [
+    [(0, 0), (1, 1), (1, 0)],
+    [(1, 1), (0, 0), (0, 1)],
+];
+
+/// Heights sampled on a regular grid covering `width` x `depth` world units, centered on the
+/// origin. Columns run along x, rows along z; the first and last ones lie on the edges.
+/// Queries outside the grid see the height of the nearest edge.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heightfield {
+    columns: usize,
+    rows: usize,
+    pub width: f32,
+    pub depth: f32,
+    // row by row along z
+    heights: Vec<f32>,
+}
+
+#[allow(dead_code)]
+impl Heightfield {
+    /// A flat field at height 0.0. Needs at least two columns and rows.
+    pub fn new(columns: usize, rows: usize, width: f32, depth: f32) -> Heightfield {
+        Heightfield::from_heights(columns, rows, width, depth, vec![0.0; columns * rows])
+    }
+
+    pub fn from_heights(columns: usize, rows: usize, width: f32, depth: f32, heights: Vec<f32>) -> Heightfield {
+        assert!(columns >= 2 && rows >= 2, "a heightfield needs at least 2x2 samples");
+        assert_eq!(Some(heights.len()), columns.checked_mul(rows));
+
+        Heightfield { columns, rows, width, depth, heights }
+    }
+
+    /// A field with every sample at `height_at(x, z)` of its world position.
+    pub fn from_fn<F: Fn(f32, f32) -> f32>(columns: usize, rows: usize, width: f32, depth: f32, height_at: F) -> Heightfield {
+        let mut field = Heightfield::new(columns, rows, width, depth);
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let (x, z) = field.position(column, row);
+                field.set(column, row, height_at(x, z));
+            }
+        }
+
+        field
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn heights(&self) -> &[f32] {
+        &self.heights
+    }
+
+    pub fn heights_mut(&mut self) -> &mut [f32] {
+        &mut self.heights
+    }
+
+    pub fn get(&self, column: usize, row: usize) -> f32 {
+        self.heights[row * self.columns + column]
+    }
+
+    pub fn set(&mut self, column: usize, row: usize, height: f32) {
+        self.heights[row * self.columns + column] = height;
+    }
+
+    /// World units between neighbouring samples along x and z.
+    pub fn spacing(&self) -> (f32, f32) {
+        (self.width / (self.columns - 1) as f32, self.depth / (self.rows - 1) as f32)
+    }
+
+    /// World (x, z) of a sample.
+    pub fn position(&self, column: usize, row: usize) -> (f32, f32) {
+        let (dx, dz) = self.spacing();
+        (column as f32 * dx - self.width / 2.0, row as f32 * dz - self.depth / 2.0)
+    }
+
+    /// Lowest and highest sample.
+    pub fn range(&self) -> (f32, f32) {
+        self.heights.iter().fold((std::f32::MAX, std::f32::MIN), |(min, max), &h| (min.min(h), max.max(h)))
+    }
+
+    /// Height at world (x, z), interpolated linearly between the four surrounding samples.
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        let (column, row, fx, fz) = self.cell(x, z);
+
+        let top = lerp(self.get(column, row), self.get(column + 1, row), fx);
+        let bottom = lerp(self.get(column, row + 1), self.get(column + 1, row + 1), fx);
+
+        lerp(top, bottom, fz)
+    }
+
+    /// Height at world (x, z) through a Catmull-Rom spline over the 4x4 surrounding samples.
+    /// Smoother than `height_at` and still passes through every sample, but can overshoot
+    /// them a little next to sharp steps.
+    pub fn height_at_bicubic(&self, x: f32, z: f32) -> f32 {
+        let (column, row, fx, fz) = self.cell(x, z);
+
+        let (column, row) = (column as isize, row as isize);
+        let mut rows = [0.0; 4];
+
+        for (i, r) in (row - 1..=row + 2).enumerate() {
+            rows[i] = catmull_rom(
+                self.extrapolated(column - 1, r),
+                self.extrapolated(column, r),
+                self.extrapolated(column + 1, r),
+                self.extrapolated(column + 2, r),
+                fx,
+            );
+        }
+
+        catmull_rom(rows[0], rows[1], rows[2], rows[3], fz)
+    }
+
+    /// How fast the height changes along x and z at world (x, z), from central differences
+    /// one sample apart.
+    pub fn gradient(&self, x: f32, z: f32) -> glm::Vec2 {
+        let (dx, dz) = self.spacing();
+
+        glm::vec2(
+            (self.height_at(x + dx, z) - self.height_at(x - dx, z)) / (2.0 * dx),
+            (self.height_at(x, z + dz) - self.height_at(x, z - dz)) / (2.0 * dz),
+        )
+    }
+
+    /// Unit surface normal at world (x, z).
+    pub fn normal(&self, x: f32, z: f32) -> glm::Vec3 {
+        let gradient = self.gradient(x, z);
+        glm::normalize(&glm::vec3(-gradient.x, 1.0, -gradient.y))
+    }
+
+    /// The sample at (column, row), continued in a straight line one sample past the edges so
+    /// the spline doesn't flatten out there.
+    fn extrapolated(&self, column: isize, row: isize) -> f32 {
+        let last_column = self.columns as isize - 1;
+        let last_row = self.rows as isize - 1;
+
+        if column < 0 {
+            2.0 * self.extrapolated(0, row) - self.extrapolated(1, row)
+        } else if column > last_column {
+            2.0 * self.extrapolated(last_column, row) - self.extrapolated(last_column - 1, row)
+        } else if row < 0 {
+            2.0 * self.get(column as usize, 0) - self.get(column as usize, 1)
+        } else if row > last_row {
+            2.0 * self.get(column as usize, last_row as usize) - self.get(column as usize, last_row as usize - 1)
+        } else {
+            self.get(column as usize, row as usize)
+        }
+    }
+
+    /// The cell around world (x, z) as its first column and row, plus how far into the cell
+    /// the point is in 0.0..1.0.
+    fn cell(&self, x: f32, z: f32) -> (usize, usize, f32, f32) {
+        let (dx, dz) = self.spacing();
+
+        let u = ((x + self.width / 2.0) / dx).max(0.0).min((self.columns - 1) as f32);
+        let v = ((z + self.depth / 2.0) / dz).max(0.0).min((self.rows - 1) as f32);
+
+        // the last sample belongs to the cell before it
+        let column = (u.floor() as usize).min(self.columns - 2);
+        let row = (v.floor() as usize).min(self.rows - 2);
+
+        (column, row, u - column as f32, v - row as f32)
+    }
+}
+
+/// The field as two triangles per cell, shaded grey between the lowest and highest sample.
+/// Flat shading gives every triangle its own vertices and one shade from its average height,
+/// smooth shading shares the vertices between cells and shades each by its own height.
+/// Texture coordinates run from 0.0 to 1.0 across the field, tangents along them.
+pub fn heightfield_mesh(field: &Heightfield, shading: Shading) -> Mesh {
+    heightfield_mesh_shaded(field, field.range(), shading)
+}
+
+/// `heightfield_mesh` shaded between heights other than the field's own, so pieces of a
+/// larger terrain match up.
+pub fn heightfield_mesh_shaded(field: &Heightfield, (min, max): (f32, f32), shading: Shading) -> Mesh {
+    let span = if max > min { max - min } else { 1.0 };
+    let shade = |y: f32| {
+        let shade = (y - min) / span;
+        (shade, shade, shade)
+    };
+
+    let tex_coord = |column: usize, row: usize| {
+        (column as f32 / (field.columns() - 1) as f32, row as f32 / (field.rows() - 1) as f32)
+    };
+
+    let mut mesh = match shading {
+        Shading::Flat => {
+            let mut points: Vec<(f32, f32, f32)> = Vec::new();
+            let mut colors: Vec<(f32, f32, f32)> = Vec::new();
+            let mut tex_coords: Vec<(f32, f32)> = Vec::new();
+
+            for column in 0..field.columns() - 1 {
+                for row in 0..field.rows() - 1 {
+                    for triangle in CELL_TRIANGLES.iter() {
+                        let mut total_y = 0.0;
+
+                        for &(corner_column, corner_row) in triangle.iter() {
+                            let (x, z) = field.position(column + corner_column, row + corner_row);
+                            let y = field.get(column + corner_column, row + corner_row);
+
+                            total_y += y;
+                            points.push((x, y, z));
+                            tex_coords.push(tex_coord(column + corner_column, row + corner_row));
+                        }
+
+                        let color = shade(total_y / 3.0);
+
+                        colors.push(color);
+                        colors.push(color);
+                        colors.push(color);
+                    }
+                }
+            }
+
+            let mut mesh = Mesh::new(points, colors, vec![]);
+            mesh.tex_coords = tex_coords;
+            mesh
+        },
+        Shading::Smooth => {
+            let mut points: Vec<(f32, f32, f32)> = Vec::new();
+            let mut colors: Vec<(f32, f32, f32)> = Vec::new();
+            let mut tex_coords: Vec<(f32, f32)> = Vec::new();
+            let mut indices: Vec<u32> = Vec::new();
+
+            for row in 0..field.rows() {
+                for column in 0..field.columns() {
+                    let (x, z) = field.position(column, row);
+                    let y = field.get(column, row);
+
+                    points.push((x, y, z));
+                    colors.push(shade(y));
+                    tex_coords.push(tex_coord(column, row));
+                }
+            }
+
+            for column in 0..field.columns() - 1 {
+                for row in 0..field.rows() - 1 {
+                    for triangle in CELL_TRIANGLES.iter() {
+                        for &(corner_column, corner_row) in triangle.iter() {
+                            indices.push(((row + corner_row) * field.columns() + column + corner_column) as u32);
+                        }
+                    }
+                }
+            }
+
+            let mut mesh = Mesh::new(points, colors, indices);
+            mesh.tex_coords = tex_coords;
+            mesh.shade(Shading::Smooth);
+            mesh
+        },
+    };
+
+    mesh.compute_tangents();
+    mesh
+}
+
+pub fn make_heightfield(device: &Device, material: Rc<Material>, field: &Heightfield, shading: Shading) -> Object {
+    let mut object = Object::from_mesh(device, material, heightfield_mesh(field, shading), BufferUsage::Static);
+    object.shading = Some(shading);
+    object
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn catmull_rom(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+    0.5 * (
+        2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t * t
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t * t * t
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a tilted plane, h = 2x + z / 2 + 3
+    fn slope() -> Heightfield {
+        Heightfield::from_fn(5, 9, 8.0, 16.0, |x, z| 2.0 * x + 0.5 * z + 3.0)
+    }
+
+    #[test]
+    fn samples_cover_the_extents() {
+        let field = slope();
+
+        assert_eq!(field.position(0, 0), (-4.0, -8.0));
+        assert_eq!(field.position(4, 8), (4.0, 8.0));
+        assert_eq!(field.get(4, 8), 2.0 * 4.0 + 0.5 * 8.0 + 3.0);
+        assert_eq!(field.range(), (-9.0, 15.0));
+    }
+
+    #[test]
+    fn sampling_reproduces_a_plane() {
+        let field = slope();
+
+        for &(x, z) in [(0.0, 0.0), (1.3, -2.7), (-3.9, 7.9), (4.0, 8.0), (-4.0, -8.0)].iter() {
+            let expected = 2.0 * x + 0.5 * z + 3.0;
+
+            assert!((field.height_at(x, z) - expected).abs() < 1e-4);
+            assert!((field.height_at_bicubic(x, z) - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn bicubic_passes_through_the_samples() {
+        let field = Heightfield::from_fn(6, 6, 10.0, 10.0, |x, z| (x * 0.7).sin() * (z * 0.3).cos() * 4.0);
+
+        for row in 0..6 {
+            for column in 0..6 {
+                let (x, z) = field.position(column, row);
+                assert!((field.height_at_bicubic(x, z) - field.get(column, row)).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn outside_the_field_the_edge_continues() {
+        let field = slope();
+
+        assert_eq!(field.height_at(-100.0, 0.0), field.height_at(-4.0, 0.0));
+        assert_eq!(field.height_at(2.0, 100.0), field.height_at(2.0, 8.0));
+    }
+
+    #[test]
+    fn gradient_and_normal_follow_the_slope() {
+        let field = slope();
+        let gradient = field.gradient(0.5, 1.0);
+
+        assert!((gradient.x - 2.0).abs() < 1e-4);
+        assert!((gradient.y - 0.5).abs() < 1e-4);
+
+        let normal = field.normal(0.5, 1.0);
+        let expected = glm::normalize(&glm::vec3(-2.0, 1.0, -0.5));
+
+        assert!(glm::distance(&normal, &expected) < 1e-4);
+    }
+
+    #[test]
+    fn mesh_has_two_upward_triangles_per_cell() {
+        let field = slope();
+        let mesh = heightfield_mesh(&field, Shading::Flat);
+
+        assert_eq!(mesh.positions.len(), 4 * 8 * 6);
+        assert!(mesh.normals.iter().all(|n| n.1 > 0.0));
+
+        for &(x, y, z) in mesh.positions.iter() {
+            assert!((field.height_at(x, z) - y).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn smooth_mesh_shares_vertices_and_follows_the_field() {
+        let field = slope();
+        let mesh = heightfield_mesh(&field, Shading::Smooth);
+
+        assert_eq!(mesh.positions.len(), field.columns() * field.rows());
+        assert_eq!(mesh.indices.len(), 4 * 8 * 6);
+
+        // a plane, so every normal is the plane's
+        let expected = field.normal(0.0, 0.0);
+
+        for (&(x, y, z), &(tx, ty, tz, _)) in mesh.normals.iter().zip(mesh.tangents.iter()) {
+            assert!(glm::distance(&glm::vec3(x, y, z), &expected) < 1e-4);
+            assert!((tx * x + ty * y + tz * z).abs() < 1e-4);
+        }
+    }
+}
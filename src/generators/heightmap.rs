@@ -0,0 +1,395 @@
+extern crate png;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::generators::heightfield::{Heightfield};
+use crate::resources::{self, Resources};
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load heightmap {}", name)]
+    ResourceLoad { name: String, #[cause] inner: resources::Error },
+    #[fail(display = "Failed to write heightmap {}", name)]
+    Io { name: String, #[cause] inner: io::Error },
+    #[fail(display = "Failed to decode heightmap {} as PNG", name)]
+    Decoding { name: String, #[cause] inner: png::DecodingError },
+    #[fail(display = "Failed to encode heightmap {} as PNG", name)]
+    Encoding { name: String, #[cause] inner: png::EncodingError },
+    #[fail(display = "Heightmap {} is not a grayscale image", name)]
+    NotGrayscale { name: String },
+    #[fail(display = "Heightmap {} is not a binary PGM file: {}", name, reason)]
+    InvalidPgm { name: String, reason: String },
+    #[fail(display = "Heightmap {} has {} bytes of samples, {}x{} need {}", name, actual, columns, rows, expected)]
+    WrongSize { name: String, columns: usize, rows: usize, expected: usize, actual: usize },
+    #[fail(display = "Heightmap {} is {}x{}, it needs at least 2x2 samples", name, columns, rows)]
+    TooSmall { name: String, columns: usize, rows: usize },
+}
+
+/// How heights are stored in a file. PNG and PGM files are loaded at whatever depth they were
+/// written with, the depth here only matters when saving. Raw files have no header, so their
+/// size has to be known up front.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeightmapFormat {
+    Png8,
+    Png16,
+    /// binary (P5) portable graymap
+    Pgm8,
+    Pgm16,
+    /// little-endian `u16` samples, row by row
+    RawU16 { columns: usize, rows: usize },
+    /// little-endian `f32` samples, row by row
+    RawF32 { columns: usize, rows: usize },
+}
+
+/// Where the samples of a heightmap end up in the world. A full-scale integer sample is
+/// `vertical_scale` high, a raw `f32` sample is multiplied by it.
+#[derive(Clone, Copy, Debug)]
+pub struct HeightmapScale {
+    pub width: f32,
+    pub depth: f32,
+    pub vertical_scale: f32,
+}
+
+/// Loads a heightmap resource. The top row of the image ends up at -z.
+#[allow(dead_code)]
+pub fn load_heightmap(
+    res: &Resources,
+    name: &str,
+    format: HeightmapFormat,
+    scale: &HeightmapScale,
+) -> Result<Heightfield, Error> {
+    let bytes = res.load_bytes(name).map_err(|e| Error::ResourceLoad { name: name.into(), inner: e })?;
+    let (columns, rows, samples) = decode(name, &bytes, format)?;
+
+    let heights = samples.iter().map(|s| s * scale.vertical_scale).collect();
+
+    Ok(Heightfield::from_heights(columns, rows, scale.width, scale.depth, heights))
+}
+
+/// Writes `field` in `format`, heights divided by `vertical_scale`. Heights outside
+/// 0.0..vertical_scale are clamped for the integer formats.
+#[allow(dead_code)]
+pub fn save_heightmap(field: &Heightfield, path: &Path, format: HeightmapFormat, vertical_scale: f32) -> Result<(), Error> {
+    let name = path.display().to_string();
+    let samples: Vec<f32> = field.heights().iter().map(|h| h / vertical_scale).collect();
+
+    let bytes = encode(&name, field.columns(), field.rows(), &samples, format)?;
+
+    fs::write(path, bytes).map_err(|e| Error::Io { name, inner: e })
+}
+
+/// Samples of a heightmap file, integer ones in 0.0..1.0, with the number of columns and rows.
+/// Anything smaller than 2x2 is rejected, it would have no cell to make terrain from.
+pub fn decode(name: &str, bytes: &[u8], format: HeightmapFormat) -> Result<(usize, usize, Vec<f32>), Error> {
+    let (columns, rows, samples) = match format {
+        HeightmapFormat::Png8 | HeightmapFormat::Png16 => decode_png(name, bytes)?,
+        HeightmapFormat::Pgm8 | HeightmapFormat::Pgm16 => decode_pgm(name, bytes)?,
+        HeightmapFormat::RawU16 { columns, rows } => {
+            check_size(name, columns, rows, 2, bytes.len())?;
+            let samples = bytes.chunks(2).map(|b| u16::from_le_bytes([b[0], b[1]]) as f32 / 65535.0).collect();
+            (columns, rows, samples)
+        },
+        HeightmapFormat::RawF32 { columns, rows } => {
+            check_size(name, columns, rows, 4, bytes.len())?;
+            let samples = bytes.chunks(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+            (columns, rows, samples)
+        },
+    };
+
+    if columns < 2 || rows < 2 {
+        return Err(Error::TooSmall { name: name.into(), columns, rows });
+    }
+
+    Ok((columns, rows, samples))
+}
+
+/// The file contents for `samples`, the inverse of `decode`.
+pub fn encode(name: &str, columns: usize, rows: usize, samples: &[f32], format: HeightmapFormat) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+
+    match format {
+        HeightmapFormat::Png8 | HeightmapFormat::Png16 => {
+            let depth = if format == HeightmapFormat::Png8 { png::BitDepth::Eight } else { png::BitDepth::Sixteen };
+            let data = quantize_big_endian(samples, depth == png::BitDepth::Sixteen);
+
+            let mut encoder = png::Encoder::new(&mut bytes, columns as u32, rows as u32);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(depth);
+
+            encoder.write_header()
+                .and_then(|mut writer| writer.write_image_data(&data))
+                .map_err(|e| Error::Encoding { name: name.into(), inner: e })?;
+        },
+        HeightmapFormat::Pgm8 | HeightmapFormat::Pgm16 => {
+            let sixteen_bit = format == HeightmapFormat::Pgm16;
+
+            bytes.extend(format!("P5\n{} {}\n{}\n", columns, rows, if sixteen_bit { 65535 } else { 255 }).bytes());
+            bytes.extend(quantize_big_endian(samples, sixteen_bit));
+        },
+        HeightmapFormat::RawU16 { .. } => {
+            for &s in samples {
+                bytes.extend_from_slice(&quantize(s, 65535.0).to_le_bytes());
+            }
+        },
+        HeightmapFormat::RawF32 { .. } => {
+            for &s in samples {
+                bytes.extend_from_slice(&s.to_le_bytes());
+            }
+        },
+    }
+
+    Ok(bytes)
+}
+
+fn decode_png(name: &str, bytes: &[u8]) -> Result<(usize, usize, Vec<f32>), Error> {
+    let decoding_error = |inner: png::DecodingError| Error::Decoding { name: name.into(), inner };
+
+    // palettes and depths below 8 bits are expanded to 8 bits, 16 bits are kept instead of
+    // being cut down to 8 like the decoder does by default
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::EXPAND);
+
+    let (info, mut reader) = decoder.read_info().map_err(decoding_error)?;
+
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        _ => return Err(Error::NotGrayscale { name: name.into() }),
+    };
+
+    let mut data = vec![0u8; info.buffer_size()];
+    reader.next_frame(&mut data).map_err(decoding_error)?;
+
+    let sixteen_bit = info.bit_depth == png::BitDepth::Sixteen;
+    let sample_size = if sixteen_bit { 2 } else { 1 };
+
+    // keep the gray of every pixel, alpha is no height
+    let samples = data.chunks(channels * sample_size).map(|pixel| {
+        if sixteen_bit {
+            u16::from_be_bytes([pixel[0], pixel[1]]) as f32 / 65535.0
+        } else {
+            pixel[0] as f32 / 255.0
+        }
+    }).collect();
+
+    Ok((info.width as usize, info.height as usize, samples))
+}
+
+fn decode_pgm(name: &str, bytes: &[u8]) -> Result<(usize, usize, Vec<f32>), Error> {
+    let invalid = |reason: &str| Error::InvalidPgm { name: name.into(), reason: reason.into() };
+
+    if !bytes.starts_with(b"P5") {
+        return Err(invalid("it does not start with P5"));
+    }
+
+    // the header is the magic number and three numbers, separated by whitespace and comments
+    let mut position = 2;
+    let mut fields = [0usize; 3];
+
+    for field in fields.iter_mut() {
+        loop {
+            match bytes.get(position) {
+                Some(b'#') => {
+                    while bytes.get(position).map_or(false, |&b| b != b'\n') {
+                        position += 1;
+                    }
+                },
+                Some(b) if b.is_ascii_whitespace() => position += 1,
+                _ => break,
+            }
+        }
+
+        let start = position;
+        while bytes.get(position).map_or(false, |b| b.is_ascii_digit()) {
+            position += 1;
+        }
+
+        *field = std::str::from_utf8(&bytes[start..position]).ok()
+            .and_then(|digits| digits.parse().ok())
+            .ok_or_else(|| invalid("the header is cut short"))?;
+    }
+
+    let [columns, rows, max_value] = fields;
+
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid("the maximum value is not in 1..65535"));
+    }
+
+    // exactly one whitespace character separates the header from the samples
+    let data = bytes.get(position + 1..).unwrap_or(&[]);
+    let sample_size = if max_value > 255 { 2 } else { 1 };
+
+    if columns.checked_mul(rows).and_then(|n| n.checked_mul(sample_size)).is_none() {
+        return Err(invalid("the image is too large"));
+    }
+
+    check_size(name, columns, rows, sample_size, data.len())?;
+
+    let samples = data.chunks(sample_size).map(|s| {
+        let value = if sample_size == 2 { u16::from_be_bytes([s[0], s[1]]) } else { s[0] as u16 };
+        value as f32 / max_value as f32
+    }).collect();
+
+    Ok((columns, rows, samples))
+}
+
+fn check_size(name: &str, columns: usize, rows: usize, sample_size: usize, actual: usize) -> Result<(), Error> {
+    // no file is anywhere near usize::MAX bytes, so one that overflows is always the wrong size
+    let expected = columns.checked_mul(rows)
+        .and_then(|n| n.checked_mul(sample_size))
+        .unwrap_or(usize::MAX);
+
+    if actual != expected {
+        return Err(Error::WrongSize { name: name.into(), columns, rows, expected, actual });
+    }
+
+    Ok(())
+}
+
+fn quantize(sample: f32, max: f32) -> u16 {
+    (sample.clamp(0.0, 1.0) * max).round() as u16
+}
+
+/// 8 or 16 bit samples, the way PNG and PGM store them.
+fn quantize_big_endian(samples: &[f32], sixteen_bit: bool) -> Vec<u8> {
+    if sixteen_bit {
+        samples.iter().flat_map(|&s| quantize(s, 65535.0).to_be_bytes().to_vec()).collect()
+    } else {
+        samples.iter().map(|&s| quantize(s, 255.0) as u8).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLUMNS: usize = 5;
+    const ROWS: usize = 3;
+
+    fn samples() -> Vec<f32> {
+        (0..COLUMNS * ROWS).map(|i| i as f32 / (COLUMNS * ROWS - 1) as f32).collect()
+    }
+
+    fn round_trip(format: HeightmapFormat, tolerance: f32) {
+        let original = samples();
+        let bytes = encode("test", COLUMNS, ROWS, &original, format).unwrap();
+        let (columns, rows, loaded) = decode("test", &bytes, format).unwrap();
+
+        assert_eq!((columns, rows), (COLUMNS, ROWS), "{:?}", format);
+
+        for (a, b) in original.iter().zip(loaded.iter()) {
+            assert!((a - b).abs() <= tolerance, "{:?}: {} came back as {}", format, a, b);
+        }
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        // off by at most half a step, plus rounding
+        round_trip(HeightmapFormat::Png8, 0.51 / 255.0);
+        round_trip(HeightmapFormat::Png16, 0.51 / 65535.0);
+        round_trip(HeightmapFormat::Pgm8, 0.51 / 255.0);
+        round_trip(HeightmapFormat::Pgm16, 0.51 / 65535.0);
+        round_trip(HeightmapFormat::RawU16 { columns: COLUMNS, rows: ROWS }, 0.51 / 65535.0);
+        round_trip(HeightmapFormat::RawF32 { columns: COLUMNS, rows: ROWS }, 0.0);
+    }
+
+    #[test]
+    fn pgm_header_may_contain_comments() {
+        let mut bytes = b"P5 # made by hand\n2 # columns\n  2\n# maximum\n1000\n".to_vec();
+        bytes.extend_from_slice(&[0x01, 0xf4, 0x03, 0xe8, 0x00, 0x00, 0x00, 0xfa]);
+
+        let (columns, rows, samples) = decode("test", &bytes, HeightmapFormat::Pgm16).unwrap();
+
+        assert_eq!((columns, rows), (2, 2));
+        assert_eq!(samples, vec![0.5, 1.0, 0.0, 0.25]);
+    }
+
+    #[test]
+    fn images_below_two_by_two_are_rejected() {
+        let line = encode("test", 1, 4, &[0.0; 4], HeightmapFormat::Png8).unwrap();
+
+        match decode("test", &line, HeightmapFormat::Png8) {
+            Err(Error::TooSmall { columns: 1, rows: 4, .. }) => {},
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+
+        match decode("test", b"P5\n0 0\n255\n", HeightmapFormat::Pgm8) {
+            Err(Error::TooSmall { columns: 0, rows: 0, .. }) => {},
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let bytes = encode("test", COLUMNS, ROWS, &samples(), HeightmapFormat::Pgm8).unwrap();
+
+        match decode("test", &bytes[..bytes.len() - 1], HeightmapFormat::Pgm8) {
+            Err(Error::WrongSize { expected: 15, actual: 14, .. }) => {},
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+
+        match decode("test", &[0u8; 7], HeightmapFormat::RawU16 { columns: 2, rows: 2 }) {
+            Err(Error::WrongSize { expected: 8, actual: 7, .. }) => {},
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+
+        assert!(decode("test", b"P5\n4 4", HeightmapFormat::Pgm8).is_err());
+    }
+
+    #[test]
+    fn oversized_headers_are_rejected() {
+        let bytes = b"P5 8589934592 2147483648 255\n".to_vec();
+
+        match decode("test", &bytes, HeightmapFormat::Pgm8) {
+            Err(Error::InvalidPgm { .. }) => {},
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+
+        let raw = HeightmapFormat::RawF32 { columns: usize::MAX / 2, rows: 3 };
+        match decode("test", &[], raw) {
+            Err(Error::WrongSize { actual: 0, .. }) => {},
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn color_images_are_rejected() {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 1, 1);
+            encoder.set_color(png::ColorType::RGB);
+            encoder.write_header().unwrap().write_image_data(&[1, 2, 3]).unwrap();
+        }
+
+        match decode("test", &bytes, HeightmapFormat::Png8) {
+            Err(Error::NotGrayscale { .. }) => {},
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn saved_terrain_loads_back_at_the_same_heights() {
+        let field = Heightfield::from_fn(9, 7, 80.0, 60.0, |x, z| 10.0 + (x * 0.1).sin() * 5.0 + z * 0.05);
+        // one directory per process, so concurrent test runs don't share the file
+        let directory = std::env::temp_dir().join(format!("heightmap-test-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let res = Resources::from_path(&directory);
+        let scale = HeightmapScale { width: 80.0, depth: 60.0, vertical_scale: 20.0 };
+
+        save_heightmap(&field, &directory.join("terrain.png"), HeightmapFormat::Png16, 20.0).unwrap();
+        let loaded = load_heightmap(&res, "terrain.png", HeightmapFormat::Png16, &scale).unwrap();
+
+        assert_eq!((loaded.columns(), loaded.rows()), (9, 7));
+        assert_eq!(loaded.position(8, 6), field.position(8, 6));
+
+        for (a, b) in field.heights().iter().zip(loaded.heights().iter()) {
+            assert!((a - b).abs() < 20.0 / 65535.0);
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
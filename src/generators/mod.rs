@@ -1 +1,11 @@
-pub mod mountain;
\ No newline at end of file
+pub mod chunks;
+pub mod erosion;
+pub mod heightfield;
+pub mod heightmap;
+pub mod mountain;
+pub mod noise;
+pub mod primitives;
+pub mod random;
+pub mod streaming;
+pub mod style;
+pub mod terrain;
\ No newline at end of file
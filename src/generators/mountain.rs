@@ -1,60 +1,36 @@
 extern crate nalgebra_glm as glm;
 
-use gl;
+use std::rc::Rc;
 
+use crate::generators::heightfield::{Heightfield, heightfield_mesh, make_heightfield};
+use crate::render_gl::device::{Device};
 use crate::render_gl::object::{Object};
-use crate::render_gl::shader::{Program};
+use crate::render_gl::material::{Material};
+use crate::render_gl::mesh::{Mesh, Shading};
 
+#[allow(dead_code)]
 pub fn make_mountain(
-    gl: &gl::Gl,
-    program: Program,
+    device: &Device,
+    material: Rc<Material>,
     width: f32,
     depth: f32,
     height: f32,
     point_count: u32,
+    shading: Shading,
 ) -> Object {
-    let width_space: f32 = width / point_count as f32;
-    let depth_space: f32 = depth / point_count as f32;
-
-    let mut points: Vec<(f32, f32, f32)> = Vec::new();
-    let mut colors: Vec<(f32, f32, f32)> = Vec::new();
-
-    for start_x in (0..point_count).map(|i| (i as f32 * width_space) - (width / 2.0)) {
-        for start_z in (0..point_count).map(|i| (i as f32 * depth_space) - (depth / 2.0)) {
-            for triangle in 0..2 {
-                let mut tri_points: Vec<(f32, f32, f32)> = Vec::new();
-
-                for vert in (triangle * 3)..((triangle * 3) + 3) {
-                    let x = start_x + match triangle + vert {
-                        1 | 2 | 4 => width_space,
-                        _ => 0.0,
-                    };
-                    let z = start_z + match triangle + vert {
-                        2 | 4 | 5 => depth_space,
-                        _ => 0.0,
-                    };
-                    let y = get_y(x, z, width, depth, height);
-
-                    tri_points.push((x, y, z));
-                    points.push((x, y, z));
-                }
-
-                let avg_y: f32 = (tri_points[0].1 + tri_points[1].1 + tri_points[2].1) / 3.0;
-                let avg_y_color: (f32, f32, f32) = get_color(avg_y, height);
+    make_heightfield(device, material, &mountain_heightfield(width, depth, height, point_count), shading)
+}
 
-                colors.push(avg_y_color);
-                colors.push(avg_y_color);
-                colors.push(avg_y_color);
-            }
-        }
-    }
+/// The heights of `make_mountain`, `point_count` cells along each side.
+pub fn mountain_heightfield(width: f32, depth: f32, height: f32, point_count: u32) -> Heightfield {
+    let samples = point_count as usize + 1;
+    Heightfield::from_fn(samples, samples, width, depth, |x, z| get_y(x, z, width, depth, height))
+}
 
-    Object::make(
-        gl,
-        program,
-        points,
-        colors,
-    )
+/// The vertices of `make_mountain`, without uploading them anywhere.
+#[allow(dead_code)]
+pub fn mountain_mesh(width: f32, depth: f32, height: f32, point_count: u32, shading: Shading) -> Mesh {
+    heightfield_mesh(&mountain_heightfield(width, depth, height, point_count), shading)
 }
 
 fn get_y(x: f32, z: f32, width: f32, depth: f32, height: f32) -> f32 {
@@ -72,7 +48,3 @@ fn get_y(x: f32, z: f32, width: f32, depth: f32, height: f32) -> f32 {
 
     xy.min(zy)
 }
-
-fn get_color(y: f32, height: f32) -> (f32, f32, f32) {
-    ((y / height) * 255.0, (y / height) * 255.0, (y / height) * 255.0)
-}
\ No newline at end of file
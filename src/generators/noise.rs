@@ -0,0 +1,307 @@
+use crate::generators::random::{Rng};
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoiseKind {
+    /// random values at the lattice points, smoothly blended. blocky
+    Value,
+    /// random gradients at the lattice points. zero on every lattice point
+    Perlin,
+    /// gradients on a triangle lattice, fewer axis aligned artifacts than `Perlin`
+    Simplex,
+}
+
+/// Smooth 2d noise in -1.0..1.0 with features about one unit apart. The same seed always gives
+/// the same noise.
+#[derive(Clone)]
+pub struct Noise {
+    kind: NoiseKind,
+    // 0..256 shuffled, what the seed changes
+    permutation: Vec<u8>,
+}
+
+impl Noise {
+    #[allow(dead_code)]
+    pub fn new(kind: NoiseKind, seed: u32) -> Noise {
+        let mut rng = Rng::new(seed as u64);
+        let mut permutation: Vec<u8> = (0..=255).collect();
+
+        for i in (1..permutation.len()).rev() {
+            let j = rng.below(i as u32 + 1) as usize;
+            permutation.swap(i, j);
+        }
+
+        Noise { kind, permutation }
+    }
+
+    pub fn sample(&self, x: f32, y: f32) -> f32 {
+        let value = match self.kind {
+            NoiseKind::Value => self.value(x, y),
+            NoiseKind::Perlin => self.perlin(x, y),
+            NoiseKind::Simplex => self.simplex(x, y),
+        };
+
+        value.clamp(-1.0, 1.0)
+    }
+
+    /// (x, y) moved by up to `strength` in each direction along this noise, sampled far enough
+    /// apart that the two directions look unrelated.
+    pub fn warp(&self, x: f32, y: f32, strength: f32) -> (f32, f32) {
+        (
+            x + strength * self.sample(x + 5.2, y + 1.3),
+            y + strength * self.sample(x - 9.7, y + 12.8),
+        )
+    }
+
+    fn hash(&self, x: i32, y: i32) -> u8 {
+        // every bit of both coordinates counts, a lookup by the low 8 bits alone would repeat
+        // the noise every 256 units
+        let mut h = (x as u32).wrapping_mul(0x8da6_b343) ^ (y as u32).wrapping_mul(0xd816_3841);
+        h ^= h >> 16;
+        h = h.wrapping_mul(0x7feb_352d);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x846c_a68b);
+        h ^= h >> 16;
+
+        self.permutation[(h & 255) as usize]
+    }
+
+    fn gradient(&self, x: i32, y: i32, dx: f32, dy: f32) -> f32 {
+        match self.hash(x, y) & 7 {
+            0 => dx + dy,
+            1 => -dx + dy,
+            2 => dx - dy,
+            3 => -dx - dy,
+            4 => dx,
+            5 => -dx,
+            6 => dy,
+            _ => -dy,
+        }
+    }
+
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (ix, iy) = (x0 as i32, y0 as i32);
+        let (u, v) = (fade(x - x0), fade(y - y0));
+
+        let corner = |cx: i32, cy: i32| self.hash(cx, cy) as f32 / 127.5 - 1.0;
+
+        lerp(
+            lerp(corner(ix, iy), corner(ix + 1, iy), u),
+            lerp(corner(ix, iy + 1), corner(ix + 1, iy + 1), u),
+            v,
+        )
+    }
+
+    fn perlin(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (ix, iy) = (x0 as i32, y0 as i32);
+        let (fx, fy) = (x - x0, y - y0);
+        let (u, v) = (fade(fx), fade(fy));
+
+        lerp(
+            lerp(self.gradient(ix, iy, fx, fy), self.gradient(ix + 1, iy, fx - 1.0, fy), u),
+            lerp(self.gradient(ix, iy + 1, fx, fy - 1.0), self.gradient(ix + 1, iy + 1, fx - 1.0, fy - 1.0), u),
+            v,
+        )
+    }
+
+    fn simplex(&self, x: f32, y: f32) -> f32 {
+        // skew into the square lattice to find the cell, then back to find the triangle
+        let skew = 0.5 * (3.0f32.sqrt() - 1.0);
+        let unskew = (3.0 - 3.0f32.sqrt()) / 6.0;
+
+        let s = (x + y) * skew;
+        let (i, j) = ((x + s).floor(), (y + s).floor());
+        let t = (i + j) * unskew;
+        let (x0, y0) = (x - (i - t), y - (j - t));
+
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+
+        let corners = [
+            (0, 0, x0, y0),
+            (i1, j1, x0 - i1 as f32 + unskew, y0 - j1 as f32 + unskew),
+            (1, 1, x0 - 1.0 + 2.0 * unskew, y0 - 1.0 + 2.0 * unskew),
+        ];
+
+        let (i, j) = (i as i32, j as i32);
+
+        let total: f32 = corners.iter().map(|&(ci, cj, dx, dy)| {
+            let falloff = 0.5 - dx * dx - dy * dy;
+            if falloff < 0.0 {
+                0.0
+            } else {
+                falloff.powi(4) * self.gradient(i + ci, j + cj, dx, dy)
+            }
+        }).sum();
+
+        // brings the extremes to about -1.0 and 1.0
+        70.0 * total
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FractalKind {
+    /// fractal Brownian motion, plain sum of the octaves. rolling hills
+    Fbm,
+    /// sharp crests where the noise crosses zero. mountain ranges
+    Ridged,
+    /// rounded bumps with creases between them. dunes, clouds
+    Billow,
+}
+
+/// Octaves of noise layered on top of each other, each `lacunarity` times the frequency and
+/// `gain` times the amplitude of the one before.
+#[derive(Clone, Copy, Debug)]
+pub struct Fractal {
+    pub kind: FractalKind,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub gain: f32,
+}
+
+impl Default for Fractal {
+    fn default() -> Self {
+        Fractal { kind: FractalKind::Fbm, octaves: 5, lacunarity: 2.0, gain: 0.5 }
+    }
+}
+
+impl Fractal {
+    /// The layered noise at (x, y), in 0.0..1.0.
+    pub fn sample(&self, noise: &Noise, x: f32, y: f32) -> f32 {
+        let mut frequency = 1.0;
+        let mut amplitude = 1.0;
+        let mut total = 0.0;
+        let mut total_amplitude = 0.0;
+
+        for octave in 0..self.octaves.max(1) {
+            // shift every octave, otherwise they all line up at the origin
+            let shift = octave as f32 * 17.31;
+            let n = noise.sample(x * frequency + shift, y * frequency - shift);
+
+            total += amplitude * match self.kind {
+                FractalKind::Fbm => n * 0.5 + 0.5,
+                FractalKind::Ridged => (1.0 - n.abs()) * (1.0 - n.abs()),
+                FractalKind::Billow => n.abs(),
+            };
+
+            total_amplitude += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.gain;
+        }
+
+        total / total_amplitude
+    }
+}
+
+/// Quintic smoothstep, its first and second derivatives are zero at both ends so there are no
+/// creases at the cell borders.
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [NoiseKind; 3] = [NoiseKind::Value, NoiseKind::Perlin, NoiseKind::Simplex];
+
+    fn grid() -> impl Iterator<Item = (f32, f32)> {
+        (0..2500).map(|i| ((i % 50) as f32 * 0.173 - 4.0, (i / 50) as f32 * 0.219 - 5.0))
+    }
+
+    #[test]
+    fn same_seed_gives_same_noise() {
+        for &kind in KINDS.iter() {
+            let a = Noise::new(kind, 42);
+            let b = Noise::new(kind, 42);
+
+            assert!(grid().all(|(x, y)| a.sample(x, y) == b.sample(x, y)), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_noise() {
+        for &kind in KINDS.iter() {
+            let a = Noise::new(kind, 1);
+            let b = Noise::new(kind, 2);
+
+            let differing = grid().filter(|&(x, y)| (a.sample(x, y) - b.sample(x, y)).abs() > 1e-3).count();
+            assert!(differing > 2000, "{:?}: only {} samples differ", kind, differing);
+        }
+    }
+
+    #[test]
+    fn noise_is_continuous_and_spans_its_range() {
+        for &kind in KINDS.iter() {
+            let noise = Noise::new(kind, 7);
+            let (mut min, mut max) = (std::f32::MAX, std::f32::MIN);
+
+            for (x, y) in grid() {
+                let value = noise.sample(x, y);
+                min = min.min(value);
+                max = max.max(value);
+
+                let step = (noise.sample(x + 0.001, y) - value).abs();
+                assert!(step < 0.02, "{:?} jumps by {} at ({}, {})", kind, step, x, y);
+            }
+
+            assert!(min >= -1.0 && max <= 1.0);
+            assert!(min < -0.4 && max > 0.4, "{:?} only spans {}..{}", kind, min, max);
+        }
+    }
+
+    #[test]
+    fn noise_does_not_repeat_after_256_units() {
+        for &kind in KINDS.iter() {
+            let noise = Noise::new(kind, 4);
+
+            for &period in [256.0, 512.0, 65536.0].iter() {
+                let differing = grid().filter(|&(x, y)| (noise.sample(x, y) - noise.sample(x + period, y)).abs() > 1e-3).count();
+                assert!(differing > 2000, "{:?} repeats after {}: only {} samples differ", kind, period, differing);
+            }
+        }
+    }
+
+    #[test]
+    fn perlin_is_zero_on_the_lattice() {
+        let noise = Noise::new(NoiseKind::Perlin, 3);
+
+        for x in -3..3 {
+            for y in -3..3 {
+                assert_eq!(noise.sample(x as f32, y as f32), 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn fractals_stay_in_range() {
+        let noise = Noise::new(NoiseKind::Simplex, 11);
+
+        for &kind in [FractalKind::Fbm, FractalKind::Ridged, FractalKind::Billow].iter() {
+            let fractal = Fractal { kind, octaves: 6, ..Fractal::default() };
+
+            for (x, y) in grid() {
+                let value = fractal.sample(&noise, x, y);
+                assert!(value >= 0.0 && value <= 1.0, "{:?} gave {}", kind, value);
+            }
+        }
+    }
+
+    #[test]
+    fn warping_moves_samples_by_at_most_the_strength() {
+        let noise = Noise::new(NoiseKind::Perlin, 5);
+
+        for (x, y) in grid() {
+            let (wx, wy) = noise.warp(x, y, 2.0);
+            assert!((wx - x).abs() <= 2.0 && (wy - y).abs() <= 2.0);
+        }
+
+        assert!(grid().any(|(x, y)| noise.warp(x, y, 2.0) != (x, y)));
+    }
+}
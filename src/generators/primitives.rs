@@ -0,0 +1,532 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use crate::render_gl::mesh::{Mesh};
+
+// Every primitive is white, indexed, centered on the origin unless said otherwise, and counter-
+// clockwise seen from outside. Texture coordinates run from 0.0 to 1.0 and tangents follow them.
+// Shapes with hard edges get separate vertices on either side of them.
+
+/// A cube `size` across with every face split into `subdivisions` x `subdivisions` squares.
+/// Each face is textured with the whole texture.
+#[allow(dead_code)]
+pub fn cube(size: f32, subdivisions: u32) -> Mesh {
+    let h = size / 2.0;
+    let mut mesh = Mesh::default();
+
+    // corner of each face, then the directions its u and v run in; u x v points out
+    let faces = [
+        (glm::vec3(h, -h, h), glm::vec3(0.0, 0.0, -size), glm::vec3(0.0, size, 0.0)),
+        (glm::vec3(-h, -h, -h), glm::vec3(0.0, 0.0, size), glm::vec3(0.0, size, 0.0)),
+        (glm::vec3(-h, h, h), glm::vec3(size, 0.0, 0.0), glm::vec3(0.0, 0.0, -size)),
+        (glm::vec3(-h, -h, -h), glm::vec3(size, 0.0, 0.0), glm::vec3(0.0, 0.0, size)),
+        (glm::vec3(-h, -h, h), glm::vec3(size, 0.0, 0.0), glm::vec3(0.0, size, 0.0)),
+        (glm::vec3(h, -h, -h), glm::vec3(-size, 0.0, 0.0), glm::vec3(0.0, size, 0.0)),
+    ];
+
+    for &(corner, u, v) in faces.iter() {
+        mesh.append(&grid(corner, u, v, subdivisions.max(1), subdivisions.max(1)));
+    }
+
+    finish(mesh)
+}
+
+/// A flat grid `width` x `depth` facing up, `columns` cells along x and `rows` along z. v runs
+/// towards -z.
+#[allow(dead_code)]
+pub fn plane(width: f32, depth: f32, columns: u32, rows: u32) -> Mesh {
+    let corner = glm::vec3(-width / 2.0, 0.0, depth / 2.0);
+    let mesh = grid(corner, glm::vec3(width, 0.0, 0.0), glm::vec3(0.0, 0.0, -depth), columns.max(1), rows.max(1));
+
+    finish(mesh)
+}
+
+/// A sphere of `segments` slices around the y axis and `rings` bands from pole to pole, u
+/// around and v up.
+#[allow(dead_code)]
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Mesh {
+    let rings = rings.max(2);
+
+    let profile: Vec<ProfilePoint> = (0..=rings).map(|ring| {
+        let v = ring as f32 / rings as f32;
+        let (sin, cos) = pole_sin_cos((v - 0.5) * PI);
+
+        ProfilePoint::new(radius * cos, radius * sin, (cos, sin), v)
+    }).collect();
+
+    finish(lathe(&[profile], segments))
+}
+
+/// A sphere from an icosahedron whose triangles are split in four `subdivisions` times, so
+/// all of them are about the same size. Texture coordinates are mapped like on `uv_sphere`:
+/// triangles across the seam at +x get their own copies of the vertices there, past the end
+/// of the texture, and every triangle at a pole its own pole vertex.
+#[allow(dead_code)]
+pub fn icosphere(radius: f32, subdivisions: u32) -> Mesh {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+
+    let mut points: Vec<glm::Vec3> = [
+        (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+        (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+        (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+    ].iter().map(|&(x, y, z)| glm::normalize(&glm::vec3(x, y, z))).collect();
+
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // edges are shared by two triangles, which have to share their middle too
+        let mut middles: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut middle = |a: u32, b: u32, points: &mut Vec<glm::Vec3>| {
+            *middles.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                points.push(glm::normalize(&(points[a as usize] + points[b as usize])));
+                points.len() as u32 - 1
+            })
+        };
+
+        triangles = triangles.iter().flat_map(|&[a, b, c]| {
+            let ab = middle(a, b, &mut points);
+            let bc = middle(b, c, &mut points);
+            let ca = middle(c, a, &mut points);
+
+            vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+        }).collect();
+    }
+
+    let u = |point: &glm::Vec3| (-point.z).atan2(point.x).rem_euclid(2.0 * PI) / (2.0 * PI);
+    let on_pole = |point: &glm::Vec3| point.x.abs() < 1e-6 && point.z.abs() < 1e-6;
+
+    let mut mesh = Mesh::default();
+    // one vertex per point and u, corners on the seam or a pole can need more than one
+    let mut vertices: HashMap<(u32, u32), u32> = HashMap::new();
+
+    for triangle in triangles.iter() {
+        let mut us: Vec<f32> = triangle.iter().map(|&i| u(&points[i as usize])).collect();
+        let around: Vec<usize> = (0..3).filter(|&c| !on_pole(&points[triangle[c] as usize])).collect();
+
+        // across the seam the corners at the start of the texture move past its end instead
+        // u is in 0.0..1.0 so far
+        let low = around.iter().map(|&c| us[c]).fold(1.0, f32::min);
+        let high = around.iter().map(|&c| us[c]).fold(0.0, f32::max);
+        if high - low > 0.5 {
+            for &c in around.iter() {
+                if us[c] < 0.5 {
+                    us[c] += 1.0;
+                }
+            }
+        }
+
+        // a pole has no u of its own, it takes the middle of the other two corners
+        let middle = around.iter().map(|&c| us[c]).sum::<f32>() / around.len() as f32;
+
+        for c in 0..3 {
+            let point = points[triangle[c] as usize];
+            let u = if on_pole(&point) { middle } else { us[c] };
+
+            let index = *vertices.entry((triangle[c], u.to_bits())).or_insert_with(|| {
+                mesh.positions.push((point.x * radius, point.y * radius, point.z * radius));
+                mesh.normals.push((point.x, point.y, point.z));
+                mesh.colors.push((1.0, 1.0, 1.0));
+                mesh.tex_coords.push((u, 0.5 + point.y.clamp(-1.0, 1.0).asin() / PI));
+                mesh.positions.len() as u32 - 1
+            });
+
+            mesh.indices.push(index);
+        }
+    }
+
+    finish(mesh)
+}
+
+/// A closed cylinder along the y axis, `segments` around and `stacks` bands up its side.
+#[allow(dead_code)]
+pub fn cylinder(radius: f32, height: f32, segments: u32, stacks: u32) -> Mesh {
+    let (bottom, top) = (-height / 2.0, height / 2.0);
+    let stacks = stacks.max(1);
+
+    let side: Vec<ProfilePoint> = (0..=stacks).map(|stack| {
+        let v = stack as f32 / stacks as f32;
+        ProfilePoint::new(radius, mix(bottom, top, v), (1.0, 0.0), v)
+    }).collect();
+
+    finish(lathe(&[
+        vec![ProfilePoint::new(0.0, bottom, (0.0, -1.0), 0.0), ProfilePoint::new(radius, bottom, (0.0, -1.0), 1.0)],
+        side,
+        vec![ProfilePoint::new(radius, top, (0.0, 1.0), 0.0), ProfilePoint::new(0.0, top, (0.0, 1.0), 1.0)],
+    ], segments))
+}
+
+/// A closed cone along the y axis with its tip up, `segments` around and `stacks` bands up
+/// its side.
+#[allow(dead_code)]
+pub fn cone(radius: f32, height: f32, segments: u32, stacks: u32) -> Mesh {
+    finish(lathe(&cone_profile(radius, -height / 2.0, height, stacks), segments))
+}
+
+/// A cylinder `length` long between two half spheres, along the y axis. `rings` bands make up
+/// each half sphere.
+#[allow(dead_code)]
+pub fn capsule(radius: f32, length: f32, segments: u32, rings: u32) -> Mesh {
+    let rings = rings.max(1);
+    let half = length / 2.0;
+
+    // v follows the distance along the outline, so the texture isn't stretched on the cylinder
+    let outline = PI * radius + length;
+    let mut profile: Vec<ProfilePoint> = vec![];
+
+    for &(offset, from) in [(-half, -PI / 2.0), (half, 0.0)].iter() {
+        for ring in 0..=rings {
+            let angle = from + ring as f32 / rings as f32 * PI / 2.0;
+            let (sin, cos) = pole_sin_cos(angle);
+            let travelled = radius * (angle + PI / 2.0) + if offset > 0.0 { length } else { 0.0 };
+
+            profile.push(ProfilePoint::new(radius * cos, offset + radius * sin, (cos, sin), travelled / outline));
+        }
+    }
+
+    finish(lathe(&[profile], segments))
+}
+
+/// A ring around the y axis, `major_radius` to the middle of its tube and `minor_radius`
+/// across the tube. `segments` around the ring and `sides` around the tube.
+#[allow(dead_code)]
+pub fn torus(major_radius: f32, minor_radius: f32, segments: u32, sides: u32) -> Mesh {
+    let sides = sides.max(3);
+
+    let profile: Vec<ProfilePoint> = (0..=sides).map(|side| {
+        let v = side as f32 / sides as f32;
+        // the last point is exactly the first, so the tube closes
+        let (sin, cos) = ((side % sides) as f32 / sides as f32 * 2.0 * PI).sin_cos();
+
+        ProfilePoint::new(major_radius + minor_radius * cos, minor_radius * sin, (cos, sin), v)
+    }).collect();
+
+    finish(lathe(&[profile], segments))
+}
+
+/// An arrow from the origin up the y axis, `length` long including its head.
+#[allow(dead_code)]
+pub fn arrow(length: f32, shaft_radius: f32, head_radius: f32, head_length: f32, segments: u32) -> Mesh {
+    let head_length = head_length.min(length);
+    let shaft_length = length - head_length;
+
+    let mut strips = vec![
+        vec![ProfilePoint::new(0.0, 0.0, (0.0, -1.0), 0.0), ProfilePoint::new(shaft_radius, 0.0, (0.0, -1.0), 1.0)],
+        vec![ProfilePoint::new(shaft_radius, 0.0, (1.0, 0.0), 0.0), ProfilePoint::new(shaft_radius, shaft_length, (1.0, 0.0), 1.0)],
+    ];
+
+    // the cone's own base is hidden under the ring around the shaft
+    let mut head = cone_profile(head_radius, shaft_length, head_length, 1);
+    head[0] = vec![ProfilePoint::new(shaft_radius, shaft_length, (0.0, -1.0), 0.0), ProfilePoint::new(head_radius, shaft_length, (0.0, -1.0), 1.0)];
+    strips.extend(head);
+
+    finish(lathe(&strips, segments))
+}
+
+/// A point on the outline a surface of revolution is turned from, with the outward normal
+/// at it as (radial, y).
+#[derive(Clone, Copy, Debug)]
+struct ProfilePoint {
+    radius: f32,
+    y: f32,
+    normal: (f32, f32),
+    v: f32,
+}
+
+impl ProfilePoint {
+    fn new(radius: f32, y: f32, normal: (f32, f32), v: f32) -> ProfilePoint {
+        ProfilePoint { radius, y, normal, v }
+    }
+}
+
+/// Base and side of a cone from `bottom` up, as strips for `lathe`.
+fn cone_profile(radius: f32, bottom: f32, height: f32, stacks: u32) -> Vec<Vec<ProfilePoint>> {
+    let stacks = stacks.max(1);
+    let slope = glm::normalize(&glm::vec2(height, radius));
+
+    let side: Vec<ProfilePoint> = (0..=stacks).map(|stack| {
+        let v = stack as f32 / stacks as f32;
+        ProfilePoint::new(radius * (1.0 - v), bottom + height * v, (slope.x, slope.y), v)
+    }).collect();
+
+    vec![
+        vec![ProfilePoint::new(0.0, bottom, (0.0, -1.0), 0.0), ProfilePoint::new(radius, bottom, (0.0, -1.0), 1.0)],
+        side,
+    ]
+}
+
+/// Turns every strip of the outline around the y axis in `segments` steps. Strips only connect
+/// within themselves, so where two meet the edge is hard. An outline going up the outside
+/// faces out.
+fn lathe(strips: &[Vec<ProfilePoint>], segments: u32) -> Mesh {
+    let segments = segments.max(3);
+    let around = segments as usize + 1;
+    let mut mesh = Mesh::default();
+
+    for strip in strips.iter() {
+        let first = mesh.positions.len();
+
+        for point in strip.iter() {
+            for segment in 0..=segments {
+                // the seam's two columns land on exactly the same positions
+                let angle = (segment % segments) as f32 / segments as f32 * 2.0 * PI;
+                let (sin, cos) = angle.sin_cos();
+
+                mesh.positions.push((point.radius * cos, point.y, -point.radius * sin));
+                mesh.normals.push((point.normal.0 * cos, point.normal.1, -point.normal.0 * sin));
+                mesh.colors.push((1.0, 1.0, 1.0));
+                mesh.tex_coords.push((segment as f32 / segments as f32, point.v));
+            }
+        }
+
+        for (row, pair) in strip.windows(2).enumerate() {
+            let (lower, upper) = ((first + row * around) as u32, (first + (row + 1) * around) as u32);
+
+            for segment in 0..segments {
+                let (a, b) = (lower + segment, lower + segment + 1);
+                let (c, d) = (upper + segment + 1, upper + segment);
+
+                // on the axis a row's vertices all meet, leaving one triangle per step
+                if pair[0].radius != 0.0 {
+                    mesh.indices.extend_from_slice(&[a, b, c]);
+                }
+                if pair[1].radius != 0.0 {
+                    mesh.indices.extend_from_slice(&[a, c, d]);
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+/// Corner, u and v across a grid of `columns` x `rows` cells, facing u x v.
+fn grid(corner: glm::Vec3, u: glm::Vec3, v: glm::Vec3, columns: u32, rows: u32) -> Mesh {
+    let normal = glm::normalize(&glm::cross::<f32, glm::U3>(&u, &v));
+    let mut mesh = Mesh::default();
+
+    for row in 0..=rows {
+        for column in 0..=columns {
+            let (s, t) = (column as f32 / columns as f32, row as f32 / rows as f32);
+            let position = corner + u * s + v * t;
+
+            mesh.positions.push((position.x, position.y, position.z));
+            mesh.normals.push((normal.x, normal.y, normal.z));
+            mesh.colors.push((1.0, 1.0, 1.0));
+            mesh.tex_coords.push((s, t));
+        }
+    }
+
+    for row in 0..rows {
+        for column in 0..columns {
+            let a = row * (columns + 1) + column;
+            let (b, c, d) = (a + 1, a + columns + 2, a + columns + 1);
+
+            mesh.indices.extend_from_slice(&[a, b, c, a, c, d]);
+        }
+    }
+
+    mesh
+}
+
+/// Sine and cosine of an angle up from the equator, with the poles exactly on the axis.
+fn pole_sin_cos(angle: f32) -> (f32, f32) {
+    if angle.abs() >= PI / 2.0 {
+        (angle.signum(), 0.0)
+    } else {
+        angle.sin_cos()
+    }
+}
+
+fn finish(mut mesh: Mesh) -> Mesh {
+    mesh.compute_tangents();
+    mesh
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn all() -> Vec<(&'static str, Mesh)> {
+        vec![
+            ("cube", cube(2.0, 3)),
+            ("uv sphere", uv_sphere(1.5, 16, 8)),
+            ("icosphere", icosphere(1.5, 2)),
+            ("cylinder", cylinder(1.0, 3.0, 12, 2)),
+            ("cone", cone(1.0, 2.0, 12, 3)),
+            ("capsule", capsule(0.5, 2.0, 12, 4)),
+            ("torus", torus(2.0, 0.5, 24, 12)),
+            ("arrow", arrow(3.0, 0.1, 0.3, 0.8, 12)),
+        ]
+    }
+
+    // positions rounded, so vertices on a seam count as one
+    fn key(mesh: &Mesh, i: u32) -> (i32, i32, i32) {
+        let (x, y, z) = mesh.positions[i as usize];
+        ((x * 1e4).round() as i32, (y * 1e4).round() as i32, (z * 1e4).round() as i32)
+    }
+
+    /// How often every edge between two welded positions is used in each direction.
+    fn edges(mesh: &Mesh) -> HashMap<((i32, i32, i32), (i32, i32, i32)), i32> {
+        let mut edges = HashMap::new();
+
+        for triangle in mesh.indices.chunks(3) {
+            for i in 0..3 {
+                let (from, to) = (key(mesh, triangle[i]), key(mesh, triangle[(i + 1) % 3]));
+                *edges.entry((from, to)).or_insert(0) += 1;
+            }
+        }
+
+        edges
+    }
+
+    fn face_normal(mesh: &Mesh, triangle: &[u32]) -> glm::Vec3 {
+        let [a, b, c] = [mesh.position(triangle[0] as usize), mesh.position(triangle[1] as usize), mesh.position(triangle[2] as usize)];
+        glm::cross::<f32, glm::U3>(&(b - a), &(c - a))
+    }
+
+    #[test]
+    fn closed_shapes_are_watertight() {
+        for (name, mesh) in all() {
+            let edges = edges(&mesh);
+
+            // every edge is used once each way, by the two triangles either side of it
+            for (&(from, to), &count) in edges.iter() {
+                assert_eq!(count, 1, "{}: edge {:?} -> {:?} used {} times", name, from, to, count);
+                assert_eq!(edges.get(&(to, from)), Some(&1), "{}: edge {:?} -> {:?} is open", name, from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn no_triangle_is_degenerate() {
+        for (name, mesh) in all().into_iter().chain(vec![("plane", plane(2.0, 1.0, 4, 2))]) {
+            for triangle in mesh.indices.chunks(3) {
+                assert!(face_normal(&mesh, triangle).norm() > 1e-6, "{}: {:?}", name, triangle);
+            }
+        }
+    }
+
+    #[test]
+    fn normals_agree_with_the_winding_and_point_out() {
+        for (name, mesh) in all() {
+            assert_eq!(mesh.normals.len(), mesh.positions.len());
+            assert_eq!(mesh.tex_coords.len(), mesh.positions.len());
+            assert_eq!(mesh.tangents.len(), mesh.positions.len());
+
+            for triangle in mesh.indices.chunks(3) {
+                let face = glm::normalize(&face_normal(&mesh, triangle));
+
+                for &i in triangle.iter() {
+                    let (x, y, z) = mesh.normals[i as usize];
+                    let normal = glm::vec3(x, y, z);
+
+                    assert!((normal.norm() - 1.0).abs() < 1e-4, "{}: normal {:?}", name, normal);
+                    assert!(glm::dot(&normal, &face) > 0.2, "{}: normal {:?} on face {:?}", name, normal, face);
+                }
+            }
+        }
+
+        // on convex shapes around the origin, outward is away from it
+        for (name, mesh) in all().into_iter().filter(|&(name, _)| name != "torus" && name != "arrow") {
+            for i in 0..mesh.positions.len() {
+                let (px, py, pz) = mesh.positions[i];
+                let (nx, ny, nz) = mesh.normals[i];
+
+                assert!(px * nx + py * ny + pz * nz > 0.0, "{}: vertex {} faces in", name, i);
+            }
+        }
+    }
+
+    #[test]
+    fn vertex_counts_follow_the_tessellation() {
+        let count = |mesh: Mesh| (mesh.positions.len(), mesh.indices.len() / 3);
+
+        assert_eq!(count(cube(1.0, 1)), (24, 12));
+        assert_eq!(count(cube(1.0, 4)), (6 * 25, 6 * 32));
+        assert_eq!(count(plane(4.0, 2.0, 4, 2)), (15, 16));
+        // poles get one triangle per segment, every other ring two
+        assert_eq!(count(uv_sphere(1.0, 16, 8)), (17 * 9, 16 * 2 + 16 * 6 * 2));
+        assert_eq!(count(icosphere(1.0, 0)).1, 20);
+        assert_eq!(count(icosphere(1.0, 3)).1, 20 * 4usize.pow(3));
+        assert_eq!(count(cylinder(1.0, 1.0, 8, 3)), (2 * 2 * 9 + 4 * 9, 2 * 8 + 3 * 8 * 2));
+        assert_eq!(count(cone(1.0, 1.0, 8, 1)), (2 * 9 + 2 * 9, 8 + 8));
+        assert_eq!(count(torus(2.0, 1.0, 24, 12)), (25 * 13, 24 * 12 * 2));
+    }
+
+    #[test]
+    fn sizes_are_honoured() {
+        let extent = |mesh: Mesh| {
+            let (min, max) = mesh.bounds();
+            let size = max - min;
+            ((size.x * 1e3).round() / 1e3, (size.y * 1e3).round() / 1e3, (size.z * 1e3).round() / 1e3)
+        };
+
+        assert_eq!(extent(cube(0.6, 2)), (0.6, 0.6, 0.6));
+        assert_eq!(extent(plane(4.0, 2.0, 3, 3)), (4.0, 0.0, 2.0));
+        assert_eq!(extent(uv_sphere(1.5, 16, 8)), (3.0, 3.0, 3.0));
+        assert_eq!(extent(icosphere(0.5, 1)).1, 1.0);
+        assert_eq!(extent(cylinder(0.5, 3.0, 16, 1)), (1.0, 3.0, 1.0));
+        assert_eq!(extent(capsule(0.5, 2.0, 16, 4)), (1.0, 3.0, 1.0));
+        assert_eq!(extent(torus(2.0, 0.5, 16, 8)), (5.0, 1.0, 5.0));
+
+        let (min, max) = arrow(3.0, 0.1, 0.3, 0.8, 8).bounds();
+        assert_eq!((min.y, max.y), (0.0, 3.0));
+    }
+
+    #[test]
+    fn spheres_lie_on_their_radius() {
+        for mesh in [uv_sphere(2.0, 12, 6), icosphere(2.0, 2)].iter() {
+            assert!(mesh.positions.iter().all(|&(x, y, z)| ((x * x + y * y + z * z).sqrt() - 2.0).abs() < 1e-4));
+        }
+    }
+
+    #[test]
+    fn icosphere_texture_has_no_jumps() {
+        let mesh = icosphere(1.0, 3);
+        let distinct: HashSet<(i32, i32, i32)> = (0..mesh.positions.len() as u32).map(|i| key(&mesh, i)).collect();
+
+        // the points are shared apart from the seam and poles
+        assert_eq!(distinct.len(), 10 * 4usize.pow(3) + 2);
+
+        for triangle in mesh.indices.chunks(3) {
+            let us: Vec<f32> = triangle.iter().map(|&i| mesh.tex_coords[i as usize].0).collect();
+            let span = us.iter().cloned().fold(std::f32::MIN, f32::max) - us.iter().cloned().fold(std::f32::MAX, f32::min);
+
+            assert!(span <= 0.5, "triangle {:?} spans {} of the texture", triangle, span);
+        }
+
+        // every triangle at a pole has its own pole vertex
+        let poles: Vec<u32> = mesh.indices.iter().cloned()
+            .filter(|&i| { let (x, _, z) = mesh.positions[i as usize]; x.abs() < 1e-6 && z.abs() < 1e-6 })
+            .collect();
+        let distinct_poles: HashSet<u32> = poles.iter().cloned().collect();
+
+        assert_eq!(poles.len(), 12);
+        assert_eq!(distinct_poles.len(), poles.len());
+    }
+
+    #[test]
+    fn plane_faces_up_with_an_open_border() {
+        let mesh = plane(2.0, 2.0, 3, 2);
+
+        assert!(mesh.normals.iter().all(|&n| n == (0.0, 1.0, 0.0)));
+        assert!(mesh.indices.chunks(3).all(|t| face_normal(&mesh, t).y > 0.0));
+
+        // edges without a partner going the other way run along the border
+        let edges = edges(&mesh);
+        let open = edges.keys().filter(|&&(from, to)| !edges.contains_key(&(to, from))).count();
+        assert_eq!(open, 2 * (3 + 2));
+    }
+}
@@ -0,0 +1,505 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration};
+
+use crate::generators::chunks::{self, ChunkCoord, ChunkLayout, chunk_heightfield, chunk_mesh, skirt_depth};
+use crate::generators::terrain::{Terrain};
+use crate::render_gl::buffer::{BufferUsage};
+use crate::render_gl::device::{Device};
+use crate::render_gl::material::{Material};
+use crate::render_gl::mesh::{Mesh};
+use crate::render_gl::object::{Object};
+
+/// A chunk at one level of detail.
+type ChunkKey = (ChunkCoord, u32);
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Invalid chunk layout")]
+    Layout(#[cause] chunks::Error),
+    #[fail(display = "Building chunk {}, {} at level {} failed", x, z, lod)]
+    Build { x: i32, z: i32, lod: u32 },
+    #[fail(display = "The terrain workers stopped")]
+    WorkersStopped,
+}
+
+impl From<chunks::Error> for Error {
+    fn from(other: chunks::Error) -> Self {
+        Error::Layout(other)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StreamingStats {
+    /// chunks waiting for or being built on the workers
+    pub pending: usize,
+    /// chunks built and waiting to be uploaded
+    pub ready: usize,
+    /// chunks uploaded, in view or not
+    pub loaded: usize,
+    /// chunks in view
+    pub visible: usize,
+    /// chunks in view drawn at another level, or not at all, because theirs isn't loaded yet
+    pub missing: usize,
+}
+
+/// Terrain that goes on forever, built in chunks around the viewer. Chunks are built on
+/// worker threads, nearest first, and uploaded a few per frame; uploaded chunks stay around
+/// until there are more than `budget` of them, the ones seen longest ago go first.
+pub struct StreamingTerrain {
+    pub layout: ChunkLayout,
+    pub budget: usize,
+    pub uploads_per_frame: usize,
+    device: Device,
+    material: Rc<Material>,
+    queue: Arc<JobQueue>,
+    // `None` for chunks whose build panicked
+    results: mpsc::Receiver<(ChunkKey, Option<Mesh>)>,
+    workers: Vec<thread::JoinHandle<()>>,
+    // queued or being built
+    requested: HashSet<ChunkKey>,
+    // never asked for again, they would only fail the same way
+    failed: HashSet<ChunkKey>,
+    // every worker is gone, nothing more is going to be built
+    stopped: bool,
+    ready: HashMap<ChunkKey, Mesh>,
+    loaded: HashMap<ChunkKey, LoadedChunk>,
+    // what gets drawn this frame, a stand-in level for chunks whose own isn't loaded yet
+    drawn: Vec<ChunkKey>,
+    visible: usize,
+    missing: usize,
+    frame: u64,
+}
+
+struct LoadedChunk {
+    object: Object,
+    last_drawn: u64,
+}
+
+impl StreamingTerrain {
+    pub fn new(
+        device: &Device,
+        material: Rc<Material>,
+        terrain: Terrain,
+        layout: ChunkLayout,
+        budget: usize,
+        worker_count: usize,
+    ) -> Result<StreamingTerrain, Error> {
+        layout.validate()?;
+
+        let queue = Arc::new(JobQueue::new());
+        let terrain = Arc::new(terrain);
+        let (sender, results) = mpsc::channel();
+
+        let workers = (0..worker_count.max(1)).map(|_| {
+            let queue = queue.clone();
+            let terrain = terrain.clone();
+            let sender = sender.clone();
+
+            thread::spawn(move || build_chunks(&terrain, &layout, &queue, &sender))
+        }).collect();
+
+        Ok(StreamingTerrain {
+            layout,
+            budget,
+            uploads_per_frame: 4,
+            device: device.clone(),
+            material,
+            queue,
+            results,
+            workers,
+            requested: HashSet::new(),
+            failed: HashSet::new(),
+            stopped: false,
+            ready: HashMap::new(),
+            loaded: HashMap::new(),
+            drawn: Vec::new(),
+            visible: 0,
+            missing: 0,
+            frame: 0,
+        })
+    }
+
+    /// Asks for the chunks around `position`, uploads what the workers finished and evicts
+    /// what's over budget. Call once per frame on the GL thread, it never waits for the workers.
+    pub fn update(&mut self, position: &glm::Vec3) {
+        self.frame += 1;
+
+        let selected = self.layout.select(position);
+        let wanted: HashSet<ChunkKey> = selected.iter().cloned().collect();
+
+        loop {
+            match self.results.try_recv() {
+                Ok((key, mesh)) => {
+                    self.requested.remove(&key);
+
+                    match mesh {
+                        Some(mesh) if wanted.contains(&key) => { self.ready.insert(key, mesh); },
+                        Some(_) => {},
+                        None => { self.failed.insert(key); },
+                    }
+                },
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.stopped = true;
+                    break;
+                },
+            }
+        }
+
+        self.ready.retain(|key, _| wanted.contains(key));
+        self.upload(&selected);
+        self.request(&selected);
+        self.choose_drawn(&selected);
+        self.evict(&wanted);
+    }
+
+    /// Updates until every chunk in view of `position` is loaded at its own level, waiting for
+    /// the workers. For a first frame or a screenshot that mustn't have holes. Fails instead of
+    /// waiting forever when a chunk in view can't be built.
+    pub fn load_all(&mut self, position: &glm::Vec3) -> Result<(), Error> {
+        loop {
+            self.update(position);
+
+            if self.missing == 0 {
+                return Ok(());
+            }
+
+            let selected = self.layout.select(position);
+            if let Some(&(coord, lod)) = selected.iter().find(|key| self.failed.contains(key)) {
+                return Err(Error::Build { x: coord.x, z: coord.z, lod });
+            }
+
+            if self.stopped {
+                return Err(Error::WorkersStopped);
+            }
+
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// The chunks to draw this frame.
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.drawn.iter().map(move |key| &self.loaded[key].object)
+    }
+
+    pub fn stats(&self) -> StreamingStats {
+        StreamingStats {
+            pending: self.requested.len(),
+            ready: self.ready.len(),
+            loaded: self.loaded.len(),
+            visible: self.visible,
+            missing: self.missing,
+        }
+    }
+
+    /// Uploads the nearest built chunks.
+    fn upload(&mut self, selected: &[ChunkKey]) {
+        let mut uploads = 0;
+
+        for key in selected.iter() {
+            if uploads == self.uploads_per_frame {
+                break;
+            }
+
+            if let Some(mesh) = self.ready.remove(key) {
+                let mut object = Object::from_mesh(&self.device, self.material.clone(), mesh, BufferUsage::Static);
+
+                let (x, z) = self.layout.center(key.0);
+                object.matrix = glm::translate(&glm::identity(), &glm::vec3(x, 0.0, z));
+
+                self.loaded.insert(*key, LoadedChunk { object, last_drawn: self.frame });
+                uploads += 1;
+            }
+        }
+    }
+
+    /// Replaces the queue with what's still missing, nearest first. Chunks that left the view
+    /// before a worker got to them are never built.
+    fn request(&mut self, selected: &[ChunkKey]) {
+        // whatever isn't waiting any more is on a worker right now
+        let waiting: HashSet<ChunkKey> = self.queue.replace(&[]).into_iter().collect();
+        let building: HashSet<ChunkKey> = self.requested.difference(&waiting).cloned().collect();
+
+        let jobs: Vec<ChunkKey> = selected.iter()
+            .filter(|key| !self.loaded.contains_key(key) && !self.ready.contains_key(key) && !building.contains(key) && !self.failed.contains(key))
+            .cloned()
+            .collect();
+
+        self.queue.replace(&jobs);
+
+        self.requested = building;
+        self.requested.extend(jobs);
+    }
+
+    fn choose_drawn(&mut self, selected: &[ChunkKey]) {
+        self.drawn.clear();
+        self.visible = selected.len();
+        self.missing = 0;
+
+        for &(coord, lod) in selected.iter() {
+            let key = if self.loaded.contains_key(&(coord, lod)) {
+                Some((coord, lod))
+            } else {
+                self.missing += 1;
+
+                // any level beats a hole, the closest one to what's wanted best
+                (0..self.layout.lod_count)
+                    .filter(|&other| self.loaded.contains_key(&(coord, other)))
+                    .min_by_key(|&other| (other as i32 - lod as i32).abs())
+                    .map(|other| (coord, other))
+            };
+
+            if let Some(key) = key {
+                self.loaded.get_mut(&key).unwrap().last_drawn = self.frame;
+                self.drawn.push(key);
+            }
+        }
+    }
+
+    /// Drops the chunks seen longest ago until the budget fits, never ones in view.
+    fn evict(&mut self, wanted: &HashSet<ChunkKey>) {
+        if self.loaded.len() <= self.budget {
+            return;
+        }
+
+        let mut candidates: Vec<(u64, ChunkKey)> = self.loaded.iter()
+            .filter(|(key, chunk)| !wanted.contains(key) && chunk.last_drawn < self.frame)
+            .map(|(key, chunk)| (chunk.last_drawn, *key))
+            .collect();
+
+        candidates.sort();
+
+        let excess = self.loaded.len() - self.budget;
+        for (_, key) in candidates.into_iter().take(excess) {
+            self.loaded.remove(&key);
+        }
+    }
+}
+
+impl Drop for StreamingTerrain {
+    fn drop(&mut self) {
+        self.queue.close();
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Chunks waiting for a worker, shared between the GL thread and the workers.
+struct JobQueue {
+    jobs: Mutex<(VecDeque<ChunkKey>, bool)>,
+    available: Condvar,
+}
+
+impl JobQueue {
+    fn new() -> JobQueue {
+        JobQueue { jobs: Mutex::new((VecDeque::new(), false)), available: Condvar::new() }
+    }
+
+    /// Puts `jobs` in place of the waiting ones, returning those.
+    fn replace(&self, jobs: &[ChunkKey]) -> Vec<ChunkKey> {
+        let mut guard = self.jobs.lock().unwrap();
+        let previous = guard.0.drain(..).collect();
+
+        guard.0.extend(jobs.iter().cloned());
+        self.available.notify_all();
+
+        previous
+    }
+
+    fn close(&self) {
+        self.jobs.lock().unwrap().1 = true;
+        self.available.notify_all();
+    }
+
+    /// Waits for the next job, `None` once the queue is closed.
+    fn next(&self) -> Option<ChunkKey> {
+        let mut guard = self.jobs.lock().unwrap();
+
+        loop {
+            if guard.1 {
+                return None;
+            }
+
+            if let Some(job) = guard.0.pop_front() {
+                return Some(job);
+            }
+
+            guard = self.available.wait(guard).unwrap();
+        }
+    }
+}
+
+fn build_chunks(terrain: &Terrain, layout: &ChunkLayout, queue: &JobQueue, results: &mpsc::Sender<(ChunkKey, Option<Mesh>)>) {
+    let height_at = |x: f32, z: f32| terrain.height_at(x, z);
+
+    while let Some((coord, lod)) = queue.next() {
+        // a chunk that panics is reported instead of taking the worker down with it, which
+        // would leave the chunk waiting to be built forever
+        let mesh = panic::catch_unwind(AssertUnwindSafe(|| {
+            let field = chunk_heightfield(layout, coord, lod, &height_at);
+            chunk_mesh(&field, skirt_depth(layout, coord, &height_at), (0.0, terrain.settings.height), layout.shading)
+        })).ok();
+
+        // the terrain is gone
+        if results.send(((coord, lod), mesh)).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Instant};
+    use crate::generators::terrain::{TerrainSettings};
+    use crate::render_gl::mesh::{Shading};
+    use crate::render_gl::recording_device::{RecordingDevice};
+    use crate::render_gl::shader::{Program};
+
+    fn streaming(budget: usize) -> StreamingTerrain {
+        let device: Device = Rc::new(RecordingDevice::new());
+        let material = Rc::new(Material::new(Rc::new(Program::make(&device, &[]).unwrap())));
+        let layout = ChunkLayout { chunk_size: 32.0, resolution: 8, lod_count: 3, lod_distance: 40.0, view_distance: 100.0, shading: Shading::Smooth };
+
+        StreamingTerrain::new(&device, material, Terrain::new(TerrainSettings::default()), layout, budget, 2).unwrap()
+    }
+
+    /// Updates until nothing is left to build or upload.
+    fn settle(terrain: &mut StreamingTerrain, position: &glm::Vec3) {
+        let start = Instant::now();
+
+        loop {
+            let before = terrain.stats().loaded;
+            terrain.update(position);
+
+            // uploads are spread over frames
+            assert!(terrain.stats().loaded <= before + terrain.uploads_per_frame);
+
+            let stats = terrain.stats();
+            if stats.pending == 0 && stats.ready == 0 {
+                return;
+            }
+
+            assert!(start.elapsed() < Duration::from_secs(30), "still streaming: {:?}", stats);
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn everything_in_view_gets_loaded() {
+        let mut terrain = streaming(1000);
+        let position = glm::vec3(5.0, 30.0, 5.0);
+
+        terrain.update(&position);
+        let first = terrain.stats();
+        assert_eq!(first.loaded, 0);
+        assert_eq!(first.missing, first.visible);
+
+        settle(&mut terrain, &position);
+
+        let stats = terrain.stats();
+        assert_eq!(stats.missing, 0);
+        assert_eq!(stats.loaded, stats.visible);
+        assert_eq!(terrain.objects().count(), stats.visible);
+
+        // every chunk sits over its own part of the ground
+        for (object, &(coord, _)) in terrain.objects().zip(terrain.layout.select(&position).iter()) {
+            let (x, z) = terrain.layout.center(coord);
+            assert_eq!((object.matrix[(0, 3)], object.matrix[(2, 3)]), (x, z));
+        }
+    }
+
+    #[test]
+    fn invalid_layouts_are_an_error() {
+        let device: Device = Rc::new(RecordingDevice::new());
+        let material = Rc::new(Material::new(Rc::new(Program::make(&device, &[]).unwrap())));
+        let layout = ChunkLayout { lod_count: 0, ..ChunkLayout::default() };
+
+        match StreamingTerrain::new(&device, material, Terrain::new(TerrainSettings::default()), layout, 10, 1) {
+            Err(Error::Layout(chunks::Error::NoLevels)) => {},
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn loading_everything_leaves_no_holes() {
+        let mut terrain = streaming(0);
+        let position = glm::vec3(-40.0, 10.0, 70.0);
+
+        terrain.load_all(&position).unwrap();
+
+        let stats = terrain.stats();
+        assert_eq!(stats.missing, 0);
+        assert_eq!(terrain.objects().count(), stats.visible);
+    }
+
+    #[test]
+    fn chunks_that_fail_to_build_stop_the_loading() {
+        let device: Device = Rc::new(RecordingDevice::new());
+        let material = Rc::new(Material::new(Rc::new(Program::make(&device, &[]).unwrap())));
+
+        // far too many samples for one chunk, the worker's allocation panics
+        let layout = ChunkLayout { resolution: 1 << 31, lod_count: 1, view_distance: 10.0, ..ChunkLayout::default() };
+        let mut terrain = StreamingTerrain::new(&device, material, Terrain::new(TerrainSettings::default()), layout, 10, 1).unwrap();
+
+        match terrain.load_all(&glm::vec3(1.0, 0.0, 1.0)) {
+            Err(Error::Build { lod: 0, .. }) => {},
+            other => panic!("{:?}", other),
+        }
+
+        // the worker is still around for the next chunk
+        assert!(!terrain.stopped);
+    }
+
+    #[test]
+    fn chunks_left_behind_are_evicted_over_budget() {
+        let mut terrain = streaming(0);
+        let home = glm::vec3(0.0, 0.0, 0.0);
+        let away = glm::vec3(5000.0, 0.0, 0.0);
+
+        settle(&mut terrain, &home);
+        let in_view = terrain.stats().visible;
+
+        // chunks in view stay even over budget
+        assert_eq!(terrain.stats().loaded, in_view);
+
+        settle(&mut terrain, &away);
+
+        let stats = terrain.stats();
+        assert_eq!(stats.loaded, stats.visible);
+        assert!(terrain.loaded.keys().all(|&(coord, _)| terrain.layout.distance(coord, &away) <= 100.0));
+    }
+
+    #[test]
+    fn least_recently_seen_chunks_go_first() {
+        let mut terrain = streaming(1000);
+
+        // chunk centers the same number of chunks apart, so the same number are in view
+        let places = [glm::vec3(16.0, 0.0, 16.0), glm::vec3(3216.0, 0.0, 16.0), glm::vec3(6416.0, 0.0, 16.0)];
+
+        settle(&mut terrain, &places[0]);
+        let in_view = terrain.stats().visible;
+
+        settle(&mut terrain, &places[1]);
+        assert_eq!(terrain.stats().loaded, 2 * in_view);
+
+        terrain.budget = 2 * in_view;
+        settle(&mut terrain, &places[2]);
+
+        assert_eq!(terrain.stats().loaded, 2 * in_view);
+        assert!(terrain.loaded.keys().all(|&(coord, _)| terrain.layout.distance(coord, &places[0]) > 100.0));
+
+        // going back to a place still in the budget needs no building
+        terrain.update(&places[1]);
+        assert_eq!(terrain.stats().missing, 0);
+        assert_eq!(terrain.stats().pending, 0);
+    }
+}
@@ -0,0 +1,423 @@
+extern crate nalgebra_glm as glm;
+
+use gl;
+use std::rc::Rc;
+
+use crate::generators::heightfield::{Heightfield};
+use crate::render_gl::material::{Splat};
+use crate::render_gl::mesh::{Mesh};
+use crate::render_gl::screenshot;
+use crate::render_gl::texture::{Texture};
+use crate::resources::{self, Resources};
+
+/// Most layers a style can have, one per channel of the splat map.
+pub const MAX_LAYERS: usize = 4;
+
+// how far past the ends of their ranges layers and the steep color fade out
+const HEIGHT_FADE: f32 = 0.04;
+const SLOPE_FADE: f32 = 5.0;
+// half texture, half color ramp, so the ramp still shows under the ground textures
+const SPLAT_STRENGTH: f32 = 0.5;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load resource {}", name)]
+    ResourceLoad { name: String, #[cause] inner: resources::Error },
+    #[fail(display = "{}, line {}: {}", name, line, message)]
+    Parse { name: String, line: usize, message: String },
+    #[fail(display = "Failed to load ground texture {}", name)]
+    Image { name: String, #[cause] inner: screenshot::Error },
+    #[fail(display = "Ground texture {} is {}x{}, the first one {}x{}", name, width, height, expected_width, expected_height)]
+    LayerSize { name: String, width: u32, height: u32, expected_width: u32, expected_height: u32 },
+}
+
+/// A ground texture and where it goes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    /// resource name of an RGBA PNG
+    pub texture: String,
+    /// world units over which the texture repeats
+    pub tiling: f32,
+    /// heights as fractions of the terrain's height
+    pub height: (f32, f32),
+    /// slopes in degrees
+    pub slope: (f32, f32),
+}
+
+/// The color of faces steeper than `slope.1` degrees, faded in from `slope.0`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Steep {
+    pub color: (f32, f32, f32),
+    pub slope: (f32, f32),
+}
+
+/// How to color a terrain by height and slope, and which ground textures to splat over it.
+/// Read from a style file, see `parse_style`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TerrainStyle {
+    /// (height, color) sorted by height
+    pub stops: Vec<(f32, (f32, f32, f32))>,
+    pub steep: Option<Steep>,
+    pub layers: Vec<Layer>,
+}
+
+#[allow(dead_code)]
+impl TerrainStyle {
+    pub fn from_res(res: &Resources, name: &str) -> Result<TerrainStyle, Error> {
+        let source = res.load_string(name).map_err(|e| Error::ResourceLoad { name: name.into(), inner: e })?;
+        parse_style(name, &source)
+    }
+
+    /// The color at `height`, a fraction of the terrain's height, on a face `slope` degrees
+    /// from flat.
+    pub fn color(&self, height: f32, slope: f32) -> (f32, f32, f32) {
+        let ramp = match self.stops.iter().position(|&(stop, _)| stop > height) {
+            None => self.stops.last().map(|&(_, color)| color).unwrap_or((1.0, 1.0, 1.0)),
+            Some(0) => self.stops[0].1,
+            Some(i) => {
+                let (low, low_color) = self.stops[i - 1];
+                let (high, high_color) = self.stops[i];
+                mix(low_color, high_color, (height - low) / (high - low))
+            },
+        };
+
+        match self.steep {
+            Some(steep) => mix(ramp, steep.color, smoothstep(steep.slope.0, steep.slope.1, slope)),
+            None => ramp,
+        }
+    }
+
+    /// How much of each layer shows at `height` and `slope`, adding up to 1.0 when there are
+    /// layers. Every layer covers the ones before it where it applies.
+    pub fn weights(&self, height: f32, slope: f32) -> [f32; MAX_LAYERS] {
+        let mut weights = [0.0; MAX_LAYERS];
+
+        for (i, layer) in self.layers.iter().enumerate() {
+            let coverage = if i == 0 {
+                1.0
+            } else {
+                band(height, layer.height, HEIGHT_FADE) * band(slope, layer.slope, SLOPE_FADE)
+            };
+
+            for weight in weights[..i].iter_mut() {
+                *weight *= 1.0 - coverage;
+            }
+            weights[i] = coverage;
+        }
+
+        weights
+    }
+
+    /// Colors every vertex of `mesh` by its height between `min` and `max` and the slope of
+    /// its normal. Meshes with a normal per face get one color per face.
+    pub fn color_mesh(&self, mesh: &mut Mesh, (min, max): (f32, f32)) {
+        let span = if max > min { max - min } else { 1.0 };
+
+        mesh.colors = mesh.positions.iter().zip(mesh.normals.iter())
+            .map(|(&(_, y, _), &(_, ny, _))| self.color((y - min) / span, slope_degrees(ny)))
+            .collect();
+    }
+
+    /// Layer weights for every sample of `field`, as RGBA8 texels row by row along z.
+    pub fn splat_map(&self, field: &Heightfield, (min, max): (f32, f32)) -> Vec<u8> {
+        let span = if max > min { max - min } else { 1.0 };
+        let mut texels = Vec::with_capacity(field.columns() * field.rows() * 4);
+
+        for row in 0..field.rows() {
+            for column in 0..field.columns() {
+                let (x, z) = field.position(column, row);
+                let height = (field.get(column, row) - min) / span;
+                let slope = slope_degrees(field.normal(x, z).y);
+
+                for weight in self.weights(height, slope).iter() {
+                    texels.push((weight * 255.0).round() as u8);
+                }
+            }
+        }
+
+        texels
+    }
+
+    /// Uploads the splat map of `field` and the layer textures. `field` is drawn untransformed,
+    /// its heights between `min` and `max`.
+    pub fn make_splat(&self, gl: &gl::Gl, res: &Resources, field: &Heightfield, range: (f32, f32)) -> Result<Splat, Error> {
+        let map = Texture::new_2d(gl, gl::RGBA8, field.columns() as u32, field.rows() as u32, 1);
+        map.upload(0, gl::RGBA, gl::UNSIGNED_BYTE, &self.splat_map(field, range));
+
+        let mut images: Vec<(u32, u32, Vec<u8>)> = vec![];
+
+        for layer in self.layers.iter() {
+            let path = res.path(&layer.texture);
+            let image = screenshot::load_png(&path).map_err(|e| Error::Image { name: layer.texture.clone(), inner: e })?;
+
+            if let Some(&(width, height, _)) = images.first() {
+                if (image.0, image.1) != (width, height) {
+                    return Err(Error::LayerSize {
+                        name: layer.texture.clone(),
+                        width: image.0,
+                        height: image.1,
+                        expected_width: width,
+                        expected_height: height,
+                    });
+                }
+            }
+
+            images.push(image);
+        }
+
+        let (width, height) = images.first().map(|&(width, height, _)| (width, height)).unwrap_or((1, 1));
+        let levels = 32 - width.max(height).leading_zeros();
+
+        let layers = Texture::new_2d_array(gl, gl::RGBA8, width, height, images.len().max(1) as u32, levels);
+        for (i, (_, _, pixels)) in images.iter().enumerate() {
+            layers.upload_layer(i as u32, gl::RGBA, gl::UNSIGNED_BYTE, pixels);
+        }
+        layers.generate_mipmaps();
+        layers.set_filter(gl::LINEAR_MIPMAP_LINEAR, gl::LINEAR);
+        layers.set_wrap(gl::REPEAT, gl::REPEAT);
+
+        let mut tiling = glm::vec4(1.0, 1.0, 1.0, 1.0);
+        for (i, layer) in self.layers.iter().enumerate() {
+            tiling[i] = layer.tiling;
+        }
+
+        Ok(Splat {
+            map: Rc::new(map),
+            layers: Rc::new(layers),
+            tiling,
+            area: glm::vec4(-field.width / 2.0, -field.depth / 2.0, field.width, field.depth),
+            strength: SPLAT_STRENGTH,
+        })
+    }
+}
+
+/// Parses a style file, one rule per line followed by its values as `name=value`:
+///
+/// - `stop height=h color=r,g,b` for the color ramp
+/// - `steep color=r,g,b slope=from,to`
+/// - `layer texture=name tiling=t height=low,high slope=low,high`, with the ranges optional
+///
+/// Blank lines and `#` comments are skipped.
+pub fn parse_style(name: &str, source: &str) -> Result<TerrainStyle, Error> {
+    let mut style = TerrainStyle { stops: vec![], steep: None, layers: vec![] };
+
+    let lines = resources::parse_lines(source)
+        .map_err(|(line, message)| Error::Parse { name: name.into(), line, message })?;
+
+    for line in lines {
+        let error = |message: String| Error::Parse { name: name.into(), line: line.number, message };
+        let (rule, values) = (line.rule, &line.values);
+
+        let find = |key: &str| values.iter().find(|&&(k, _)| k == key).map(|&(_, v)| v);
+
+        let numbers = |key: &str, count: usize| -> Result<Option<Vec<f32>>, Error> {
+            let value = match find(key) {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+
+            let numbers = resources::parse_numbers(value).map_err(error)?;
+
+            if numbers.len() != count {
+                return Err(error(format!("{} needs {} numbers, found {}", key, count, numbers.len())));
+            }
+
+            Ok(Some(numbers))
+        };
+
+        let required = |key: &str, count: usize| numbers(key, count)?.ok_or_else(|| error(format!("{} needs {}", rule, key)));
+        let range = |key: &str, default: (f32, f32)| -> Result<(f32, f32), Error> {
+            Ok(numbers(key, 2)?.map(|n| (n[0], n[1])).unwrap_or(default))
+        };
+
+        let allowed: &[&str] = match rule {
+            "stop" => {
+                let height = required("height", 1)?[0];
+                let color = required("color", 3)?;
+                style.stops.push((height, (color[0], color[1], color[2])));
+                &["height", "color"]
+            },
+            "steep" => {
+                let color = required("color", 3)?;
+                style.steep = Some(Steep { color: (color[0], color[1], color[2]), slope: range("slope", (45.0, 60.0))? });
+                &["color", "slope"]
+            },
+            "layer" => {
+                if style.layers.len() == MAX_LAYERS {
+                    return Err(error(format!("a style has at most {} layers", MAX_LAYERS)));
+                }
+
+                style.layers.push(Layer {
+                    texture: find("texture").ok_or_else(|| error("layer needs texture".into()))?.to_string(),
+                    tiling: numbers("tiling", 1)?.map(|n| n[0]).unwrap_or(1.0),
+                    height: range("height", (0.0, 1.0))?,
+                    slope: range("slope", (0.0, 90.0))?,
+                });
+                &["texture", "tiling", "height", "slope"]
+            },
+            _ => return Err(error(format!("unknown rule {}", rule))),
+        };
+
+        if let Some(&(key, _)) = values.iter().find(|&&(key, _)| !allowed.contains(&key)) {
+            return Err(error(format!("{} has no {}", rule, key)));
+        }
+    }
+
+    // the parser only lets finite numbers through
+    style.stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    Ok(style)
+}
+
+/// Degrees from flat of a face whose unit normal has `normal_y` as its y.
+fn slope_degrees(normal_y: f32) -> f32 {
+    normal_y.clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+/// 1.0 within `low..high`, fading to 0.0 over `fade` past either end.
+fn band(value: f32, (low, high): (f32, f32), fade: f32) -> f32 {
+    smoothstep(low - fade, low, value) * (1.0 - smoothstep(high, high + fade, value))
+}
+
+fn smoothstep(from: f32, to: f32, value: f32) -> f32 {
+    if to <= from {
+        return if value < from { 0.0 } else { 1.0 };
+    }
+
+    let t = ((value - from) / (to - from)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn mix(a: (f32, f32, f32), b: (f32, f32, f32), t: f32) -> (f32, f32, f32) {
+    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t, a.2 + (b.2 - a.2) * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "
+        # two stops out of order, a steep color and three layers
+        stop height=1.0 color=1,1,1
+        stop height=0.0 color=0,0.5,0
+        steep color=0.5,0.5,0.5 slope=30,40
+
+        layer texture=rock.png tiling=10
+        layer texture=grass.png tiling=4 height=0,0.5 slope=0,30
+        layer texture=snow.png height=0.8,1   # snow on top
+    ";
+
+    fn style() -> TerrainStyle {
+        parse_style("test.style", SOURCE).unwrap()
+    }
+
+    fn assert_close(a: (f32, f32, f32), b: (f32, f32, f32)) {
+        assert!((a.0 - b.0).abs() < 1e-5 && (a.1 - b.1).abs() < 1e-5 && (a.2 - b.2).abs() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn parses_every_rule() {
+        let style = style();
+
+        assert_eq!(style.stops, vec![(0.0, (0.0, 0.5, 0.0)), (1.0, (1.0, 1.0, 1.0))]);
+        assert_eq!(style.steep, Some(Steep { color: (0.5, 0.5, 0.5), slope: (30.0, 40.0) }));
+        assert_eq!(style.layers[1], Layer {
+            texture: "grass.png".into(),
+            tiling: 4.0,
+            height: (0.0, 0.5),
+            slope: (0.0, 30.0),
+        });
+        assert_eq!(style.layers[2].slope, (0.0, 90.0));
+        assert_eq!(style.layers[2].tiling, 1.0);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let cases = [
+            ("stop height=0.5", 1, "stop needs color"),
+            ("\nstop height=0.5 color=1,1", 2, "color needs 3 numbers, found 2"),
+            ("steep color=1,1,1 angle=3", 1, "steep has no angle"),
+            ("# fine\n\nlayer tiling=2", 3, "layer needs texture"),
+            ("hill height=3", 1, "unknown rule hill"),
+            ("stop height=nan color=1,1,1", 1, "nan is not a number or vector"),
+            ("stop height=0 color=1,inf,1", 1, "1,inf,1 is not a number or vector"),
+            ("stop height=0 color", 1, "expected name=value, found color"),
+        ];
+
+        for &(source, expected_line, expected_message) in cases.iter() {
+            match parse_style("test.style", source) {
+                Err(Error::Parse { line, message, .. }) => {
+                    assert_eq!((line, message.as_str()), (expected_line, expected_message));
+                },
+                other => panic!("{}: {:?}", source, other),
+            }
+        }
+
+        let five_layers = "layer texture=a\n".repeat(5);
+        assert!(parse_style("test.style", &five_layers).is_err());
+    }
+
+    #[test]
+    fn ramp_blends_between_stops() {
+        let style = style();
+
+        assert_close(style.color(0.0, 0.0), (0.0, 0.5, 0.0));
+        assert_close(style.color(0.5, 0.0), (0.5, 0.75, 0.5));
+        assert_close(style.color(-3.0, 0.0), (0.0, 0.5, 0.0));
+        assert_close(style.color(7.0, 0.0), (1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn steep_faces_turn_to_the_steep_color() {
+        let style = style();
+
+        assert_close(style.color(1.0, 29.0), (1.0, 1.0, 1.0));
+        assert_close(style.color(1.0, 41.0), (0.5, 0.5, 0.5));
+        assert_close(style.color(1.0, 35.0), (0.75, 0.75, 0.75));
+    }
+
+    #[test]
+    fn later_layers_cover_earlier_ones() {
+        let style = style();
+        let sum = |w: [f32; 4]| w.iter().sum::<f32>();
+
+        // grass on low flat ground, rock where it's steep, snow on top
+        assert_eq!(style.weights(0.2, 10.0), [0.0, 1.0, 0.0, 0.0]);
+        assert_eq!(style.weights(0.2, 60.0), [1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(style.weights(0.9, 60.0), [0.0, 0.0, 1.0, 0.0]);
+
+        for &(height, slope) in [(0.52, 10.0), (0.3, 33.0), (0.78, 20.0), (0.5, 31.0)].iter() {
+            let weights = style.weights(height, slope);
+            assert!((sum(weights) - 1.0).abs() < 1e-5, "{:?}", weights);
+        }
+    }
+
+    #[test]
+    fn mesh_colors_follow_height_and_normal() {
+        let style = style();
+        let mut mesh = Mesh::new(
+            vec![(0.0, 10.0, 0.0), (1.0, 10.0, 1.0), (1.0, 10.0, 0.0), (0.0, 20.0, 0.0), (0.0, 0.0, 1.0), (0.0, 20.0, 1.0)],
+            vec![(0.0, 0.0, 0.0); 6],
+            vec![],
+        );
+
+        style.color_mesh(&mut mesh, (10.0, 30.0));
+
+        // a flat triangle at the bottom of the range, and a wall
+        assert_close(mesh.colors[0], (0.0, 0.5, 0.0));
+        assert_close(mesh.colors[3], (0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn splat_map_has_a_texel_per_sample() {
+        let style = style();
+        let field = Heightfield::from_fn(5, 3, 40.0, 20.0, |x, _| if x > 0.0 { 9.0 } else { 1.0 });
+
+        let map = style.splat_map(&field, (0.0, 10.0));
+
+        assert_eq!(map.len(), 5 * 3 * 4);
+        // low and flat at the first sample, high and flat at the last
+        assert_eq!(&map[..4], &[0, 255, 0, 0]);
+        assert_eq!(&map[map.len() - 4..], &[0, 0, 255, 0]);
+    }
+}
@@ -0,0 +1,131 @@
+use std::rc::Rc;
+
+use crate::generators::heightfield::{Heightfield, make_heightfield};
+use crate::generators::noise::{Fractal, Noise, NoiseKind};
+use crate::render_gl::device::{Device};
+use crate::render_gl::material::{Material};
+use crate::render_gl::mesh::{Shading};
+use crate::render_gl::object::{Object};
+
+#[derive(Clone, Copy, Debug)]
+pub struct TerrainSettings {
+    pub seed: u32,
+    pub noise: NoiseKind,
+    pub fractal: Fractal,
+    /// world units between the largest features
+    pub scale: f32,
+    /// highest point, the lowest is at 0.0
+    pub height: f32,
+    /// how far, in world units, domain warping pushes samples around. 0.0 turns it off
+    pub warp: f32,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        TerrainSettings {
+            seed: 0,
+            noise: NoiseKind::Simplex,
+            fractal: Fractal::default(),
+            scale: 50.0,
+            height: 45.0,
+            warp: 0.0,
+        }
+    }
+}
+
+/// Heights from layered noise, the same for the same settings.
+pub struct Terrain {
+    pub settings: TerrainSettings,
+    noise: Noise,
+    warp_noise: Noise,
+}
+
+impl Terrain {
+    pub fn new(settings: TerrainSettings) -> Terrain {
+        Terrain {
+            settings,
+            noise: Noise::new(settings.noise, settings.seed),
+            // seeded differently, warping along the terrain itself folds it onto itself
+            warp_noise: Noise::new(settings.noise, settings.seed.wrapping_add(1)),
+        }
+    }
+
+    /// Height at world position (x, z), in 0.0..height.
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        let (mut x, mut z) = (x / self.settings.scale, z / self.settings.scale);
+
+        if self.settings.warp != 0.0 {
+            let warped = self.warp_noise.warp(x, z, self.settings.warp / self.settings.scale);
+            x = warped.0;
+            z = warped.1;
+        }
+
+        self.settings.fractal.sample(&self.noise, x, z) * self.settings.height
+    }
+
+    /// The terrain sampled on a `point_count` x `point_count` grid of cells centered on the
+    /// origin.
+    pub fn heightfield(&self, width: f32, depth: f32, point_count: u32) -> Heightfield {
+        let samples = point_count as usize + 1;
+        Heightfield::from_fn(samples, samples, width, depth, |x, z| self.height_at(x, z))
+    }
+}
+
+#[allow(dead_code)]
+pub fn make_terrain(
+    device: &Device,
+    material: Rc<Material>,
+    terrain: &Terrain,
+    width: f32,
+    depth: f32,
+    point_count: u32,
+    shading: Shading,
+) -> Object {
+    make_heightfield(device, material, &terrain.heightfield(width, depth, point_count), shading)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::noise::{FractalKind};
+
+    fn settings(seed: u32) -> TerrainSettings {
+        TerrainSettings { seed, warp: 10.0, ..TerrainSettings::default() }
+    }
+
+    #[test]
+    fn terrain_is_reproducible_from_its_seed() {
+        let first = Terrain::new(settings(9)).heightfield(100.0, 100.0, 16);
+        let second = Terrain::new(settings(9)).heightfield(100.0, 100.0, 16);
+        let other = Terrain::new(settings(10)).heightfield(100.0, 100.0, 16);
+
+        assert_eq!((first.columns(), first.rows()), (17, 17));
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn heights_stay_between_zero_and_height() {
+        for &kind in [FractalKind::Fbm, FractalKind::Ridged, FractalKind::Billow].iter() {
+            let mut settings = settings(4);
+            settings.fractal.kind = kind;
+
+            let field = Terrain::new(settings).heightfield(200.0, 200.0, 32);
+
+            assert!(field.heights().iter().all(|&h| h >= 0.0 && h <= settings.height), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn heightfield_samples_the_terrain() {
+        let terrain = Terrain::new(settings(2));
+        let field = terrain.heightfield(100.0, 100.0, 8);
+
+        for row in 0..field.rows() {
+            for column in 0..field.columns() {
+                let (x, z) = field.position(column, row);
+                assert_eq!(field.get(column, row), terrain.height_at(x, z));
+            }
+        }
+    }
+}
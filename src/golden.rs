@@ -0,0 +1,207 @@
+//! Golden-image tests: named scenes drawn with the software rasterizer and compared against
+//! reference PNGs in `tests/golden`. A failing scene leaves the image it got and a diff next to
+//! each other in `target/golden`.
+//!
+//! After an intended change to what a scene looks like, run the tests with `UPDATE_GOLDEN=1` to
+//! write new references, and look at them before committing.
+
+extern crate nalgebra_glm as glm;
+
+use gl;
+use std::path::{Path, PathBuf};
+
+use crate::generators::mountain::{mountain_mesh};
+use crate::render_gl::camera::{Camera};
+use crate::render_gl::mesh::{Mesh, Shading};
+use crate::render_gl::rasterizer::{Rasterizer};
+use crate::render_gl::render_state::{Cull, RenderState};
+use crate::render_gl::screenshot;
+use crate::scene::{cube_mesh};
+
+const CLEAR_COLOR: (f32, f32, f32, f32) = (0.3, 0.3, 0.5, 1.0);
+
+pub struct GoldenScene {
+    pub name: &'static str,
+    pub width: u32,
+    pub height: u32,
+    /// how far any channel of a pixel may be off before the pixel counts as different
+    pub tolerance: u8,
+    pub camera_position: glm::Vec3,
+    pub camera_target: glm::Vec3,
+    pub render: fn(&mut Rasterizer, &Camera),
+}
+
+pub fn scenes() -> Vec<GoldenScene> {
+    vec![
+        GoldenScene {
+            name: "mountain",
+            width: 160,
+            height: 120,
+            tolerance: 1,
+            // where the window's camera orbit starts
+            camera_position: glm::vec3(0.0, 80.0, 100.0),
+            camera_target: glm::vec3(0.0, 10.0, 0.0),
+            render: |rasterizer, camera| draw(rasterizer, camera, &mountain_mesh(100.0, 100.0, 45.0, 20, Shading::Flat)),
+        },
+        GoldenScene {
+            name: "cube",
+            width: 96,
+            height: 96,
+            tolerance: 1,
+            camera_position: glm::vec3(1.0, 0.8, 1.4),
+            camera_target: glm::vec3(0.0, 0.0, 0.0),
+            render: |rasterizer, camera| draw(rasterizer, camera, &cube_mesh()),
+        },
+    ]
+}
+
+/// Draws with back faces culled, so flipped triangles show up as holes.
+fn draw(rasterizer: &mut Rasterizer, camera: &Camera, mesh: &Mesh) {
+    let render_state = RenderState { cull: Some(Cull { face: gl::BACK, front: gl::CCW }), ..RenderState::opaque() };
+    rasterizer.draw_mesh(mesh, &glm::identity(), &camera.matrix, camera.projection(), &render_state);
+}
+
+pub fn render(scene: &GoldenScene) -> Rasterizer {
+    let mut camera = Camera::make(scene.width, scene.height, 45.0, 0.1, 1000.0);
+    camera.reposition_and_look_at(&scene.camera_position, &scene.camera_target);
+
+    let mut rasterizer = Rasterizer::new(scene.width, scene.height);
+    rasterizer.clear(CLEAR_COLOR);
+    (scene.render)(&mut rasterizer, &camera);
+
+    rasterizer
+}
+
+/// Pixels of two same-sized RGBA8 images that differ by more than `tolerance` in any channel.
+pub struct Comparison {
+    pub differing: usize,
+    /// the largest difference in any channel
+    pub max_difference: u8,
+    /// differing pixels in red over a faded copy of `expected`
+    pub diff: Vec<u8>,
+}
+
+pub fn compare(expected: &[u8], actual: &[u8], tolerance: u8) -> Comparison {
+    let mut comparison = Comparison { differing: 0, max_difference: 0, diff: Vec::with_capacity(expected.len()) };
+
+    for (e, a) in expected.chunks(4).zip(actual.chunks(4)) {
+        let difference = e.iter().zip(a.iter())
+            .map(|(&e, &a)| (e as i16 - a as i16).abs() as u8)
+            .max()
+            .unwrap_or(0);
+
+        comparison.max_difference = comparison.max_difference.max(difference);
+
+        if difference > tolerance {
+            comparison.differing += 1;
+            comparison.diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let faded = ((e[0] as u32 + e[1] as u32 + e[2] as u32) / 9) as u8;
+            comparison.diff.extend_from_slice(&[faded, faded, faded, 255]);
+        }
+    }
+
+    comparison
+}
+
+/// Renders `scene` and compares it with its reference image, or writes the reference when
+/// `UPDATE_GOLDEN` is set.
+pub fn check(scene: &GoldenScene) -> Result<(), String> {
+    let actual = render(scene);
+    let reference = reference_path(scene.name);
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        return save(&reference, scene.width, scene.height, actual.pixels());
+    }
+
+    let (width, height, expected) = screenshot::load_png(&reference)
+        .map_err(|e| format!("{}: {}, run with UPDATE_GOLDEN=1 to create it", scene.name, e))?;
+
+    if (width, height) != (scene.width, scene.height) {
+        save(&output_path(scene.name, "actual"), scene.width, scene.height, actual.pixels())?;
+        return Err(format!(
+            "{}: reference is {}x{}, the scene {}x{}",
+            scene.name, width, height, scene.width, scene.height
+        ));
+    }
+
+    let comparison = compare(&expected, actual.pixels(), scene.tolerance);
+
+    if comparison.differing > 0 {
+        let actual_path = output_path(scene.name, "actual");
+        let diff_path = output_path(scene.name, "diff");
+
+        save(&actual_path, scene.width, scene.height, actual.pixels())?;
+        save(&diff_path, scene.width, scene.height, &comparison.diff)?;
+
+        return Err(format!(
+            "{}: {} pixels differ by up to {}, see {} and {}",
+            scene.name, comparison.differing, comparison.max_difference, actual_path.display(), diff_path.display()
+        ));
+    }
+
+    Ok(())
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", name))
+}
+
+fn output_path(name: &str, kind: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden").join(format!("{}.{}.png", name, kind))
+}
+
+fn save(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory).map_err(|e| format!("{}: {}", directory.display(), e))?;
+    }
+
+    screenshot::save_png(path, width, height, pixels).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_scene(name: &str) {
+        let scene = scenes().into_iter().find(|scene| scene.name == name).unwrap();
+
+        if let Err(message) = check(&scene) {
+            panic!("{}", message);
+        }
+    }
+
+    #[test]
+    fn mountain() {
+        check_scene("mountain");
+    }
+
+    #[test]
+    fn cube() {
+        check_scene("cube");
+    }
+
+    #[test]
+    fn differences_within_tolerance_pass() {
+        let expected = [10, 20, 30, 255, 0, 0, 0, 255];
+        let actual = [11, 19, 30, 255, 0, 1, 0, 255];
+
+        let comparison = compare(&expected, &actual, 1);
+
+        assert_eq!(comparison.differing, 0);
+        assert_eq!(comparison.max_difference, 1);
+        assert!(comparison.diff.chunks(4).all(|pixel| pixel[0] == pixel[1]));
+    }
+
+    #[test]
+    fn differences_beyond_tolerance_are_counted() {
+        let expected = [10, 20, 30, 255, 0, 0, 0, 255];
+        let actual = [11, 19, 30, 255, 0, 0, 3, 255];
+
+        let comparison = compare(&expected, &actual, 1);
+
+        assert_eq!(comparison.differing, 1);
+        assert_eq!(comparison.max_difference, 3);
+        assert_eq!(&comparison.diff[4..], &[255, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,217 @@
+extern crate khronos_egl as egl;
+extern crate libloading;
+
+use failure::Fail;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int, c_uchar, c_uint, c_void};
+
+// EGL_MESA_platform_surfaceless, a display that needs no window system at all
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+// from osmesa.h
+const OSMESA_RGBA: c_int = 0x1908;
+const OSMESA_FORMAT: c_int = 0x22;
+const OSMESA_DEPTH_BITS: c_int = 0x30;
+const OSMESA_PROFILE: c_int = 0x33;
+const OSMESA_CORE_PROFILE: c_int = 0x34;
+const OSMESA_CONTEXT_MAJOR_VERSION: c_int = 0x36;
+const OSMESA_CONTEXT_MINOR_VERSION: c_int = 0x37;
+const OSMESA_LIBRARIES: [&str; 3] = ["libOSMesa.so.8", "libOSMesa.so.6", "libOSMesa.so"];
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load libEGL: {}", message)]
+    Load { message: String },
+    #[fail(display = "No EGL display available")]
+    NoDisplay,
+    #[fail(display = "No EGL config supports desktop OpenGL")]
+    NoConfig,
+    #[fail(display = "EGL call {} failed", call)]
+    Egl { call: &'static str, #[cause] inner: egl::Error },
+    #[fail(display = "Failed to load libOSMesa: {}", message)]
+    OsMesaLoad { message: String },
+    #[fail(display = "OSMesa call {} failed", call)]
+    OsMesa { call: &'static str },
+    #[fail(display = "No headless OpenGL context. EGL: {}. OSMesa: {}", egl, osmesa)]
+    Unavailable { egl: String, osmesa: String },
+}
+
+/// OpenGL 4.5 core context with no window or surface, for rendering into framebuffer objects on
+/// machines without a display. Uses Mesa's EGL surfaceless platform where available, and falls
+/// back to OSMesa otherwise. Without a GPU `LIBGL_ALWAYS_SOFTWARE=1` makes EGL use llvmpipe,
+/// which is all OSMesa has anyway.
+pub enum HeadlessContext {
+    Egl(EglContext),
+    OsMesa(OsMesaContext),
+}
+
+impl HeadlessContext {
+    /// Creates the context and makes it current on this thread.
+    pub fn new() -> Result<HeadlessContext, Error> {
+        let egl = match EglContext::new() {
+            Ok(context) => return Ok(HeadlessContext::Egl(context)),
+            Err(e) => e,
+        };
+
+        OsMesaContext::new()
+            .map(HeadlessContext::OsMesa)
+            .map_err(|osmesa| Error::Unavailable { egl: describe(&egl), osmesa: describe(&osmesa) })
+    }
+
+    /// For `gl::Gl::load_with`.
+    pub fn get_proc_address(&self, name: &str) -> *const c_void {
+        match self {
+            HeadlessContext::Egl(context) => context.get_proc_address(name),
+            HeadlessContext::OsMesa(context) => context.get_proc_address(name),
+        }
+    }
+}
+
+pub struct EglContext {
+    egl: egl::DynamicInstance<egl::EGL1_5>,
+    display: egl::Display,
+    context: egl::Context,
+}
+
+impl EglContext {
+    fn new() -> Result<EglContext, Error> {
+        let egl = unsafe { egl::DynamicInstance::<egl::EGL1_5>::load_required() }
+            .map_err(|e| Error::Load { message: e.to_string() })?;
+
+        let call = |call: &'static str| move |inner: egl::Error| Error::Egl { call, inner };
+
+        let display = egl.get_platform_display(PLATFORM_SURFACELESS_MESA, egl::DEFAULT_DISPLAY, &[egl::ATTRIB_NONE])
+            .ok()
+            .or_else(|| egl.get_display(egl::DEFAULT_DISPLAY))
+            .ok_or(Error::NoDisplay)?;
+
+        egl.initialize(display).map_err(call("eglInitialize"))?;
+        egl.bind_api(egl::OPENGL_API).map_err(call("eglBindAPI"))?;
+
+        // SURFACE_TYPE defaults to windows, which the surfaceless platform has none of
+        let config = egl.choose_first_config(display, &[
+            egl::RENDERABLE_TYPE, egl::OPENGL_BIT,
+            egl::SURFACE_TYPE, egl::PBUFFER_BIT,
+            egl::NONE,
+        ])
+            .map_err(call("eglChooseConfig"))?
+            .ok_or(Error::NoConfig)?;
+
+        let context = egl.create_context(display, config, None, &[
+            egl::CONTEXT_MAJOR_VERSION, 4,
+            egl::CONTEXT_MINOR_VERSION, 5,
+            egl::CONTEXT_OPENGL_PROFILE_MASK, egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+            egl::NONE,
+        ]).map_err(call("eglCreateContext"))?;
+
+        // surfaceless: everything has to be drawn into framebuffer objects
+        egl.make_current(display, None, None, Some(context)).map_err(call("eglMakeCurrent"))?;
+
+        Ok(EglContext { egl, display, context })
+    }
+
+    fn get_proc_address(&self, name: &str) -> *const c_void {
+        match self.egl.get_proc_address(name) {
+            Some(function) => function as *const c_void,
+            None => std::ptr::null(),
+        }
+    }
+}
+
+impl Drop for EglContext {
+    fn drop(&mut self) {
+        let _ = self.egl.make_current(self.display, None, None, None);
+        let _ = self.egl.destroy_context(self.display, self.context);
+        let _ = self.egl.terminate(self.display);
+    }
+}
+
+type CreateContextAttribs = unsafe extern "C" fn(*const c_int, *mut c_void) -> *mut c_void;
+type MakeCurrent = unsafe extern "C" fn(*mut c_void, *mut c_void, c_uint, c_int, c_int) -> c_uchar;
+type GetProcAddress = unsafe extern "C" fn(*const c_char) -> *const c_void;
+type DestroyContext = unsafe extern "C" fn(*mut c_void);
+
+pub struct OsMesaContext {
+    get_proc_address: GetProcAddress,
+    destroy_context: DestroyContext,
+    context: *mut c_void,
+    // OSMesa wants a color buffer to be current with, though everything goes into framebuffer
+    // objects; it has to live as long as the context
+    _buffer: Vec<u8>,
+    // keeps the functions above loaded
+    _library: libloading::Library,
+}
+
+impl OsMesaContext {
+    fn new() -> Result<OsMesaContext, Error> {
+        let library = OSMESA_LIBRARIES.iter()
+            .filter_map(|name| unsafe { libloading::Library::new(name) }.ok())
+            .next()
+            .ok_or_else(|| Error::OsMesaLoad { message: format!("none of {} found", OSMESA_LIBRARIES.join(", ")) })?;
+
+        let (create_context, make_current, get_proc_address, destroy_context) = unsafe {
+            (
+                load::<CreateContextAttribs>(&library, "OSMesaCreateContextAttribs")?,
+                load::<MakeCurrent>(&library, "OSMesaMakeCurrent")?,
+                load::<GetProcAddress>(&library, "OSMesaGetProcAddress")?,
+                load::<DestroyContext>(&library, "OSMesaDestroyContext")?,
+            )
+        };
+
+        let attributes = [
+            OSMESA_FORMAT, OSMESA_RGBA,
+            OSMESA_DEPTH_BITS, 24,
+            OSMESA_PROFILE, OSMESA_CORE_PROFILE,
+            OSMESA_CONTEXT_MAJOR_VERSION, 4,
+            OSMESA_CONTEXT_MINOR_VERSION, 5,
+            0,
+        ];
+
+        let context = unsafe { create_context(attributes.as_ptr(), std::ptr::null_mut()) };
+        if context.is_null() {
+            return Err(Error::OsMesa { call: "OSMesaCreateContextAttribs" });
+        }
+
+        let mut buffer = vec![0u8; 4];
+        if unsafe { make_current(context, buffer.as_mut_ptr() as *mut c_void, gl::UNSIGNED_BYTE, 1, 1) } == 0 {
+            unsafe { destroy_context(context) };
+            return Err(Error::OsMesa { call: "OSMesaMakeCurrent" });
+        }
+
+        Ok(OsMesaContext { get_proc_address, destroy_context, context, _buffer: buffer, _library: library })
+    }
+
+    fn get_proc_address(&self, name: &str) -> *const c_void {
+        match CString::new(name) {
+            Ok(name) => unsafe { (self.get_proc_address)(name.as_ptr()) },
+            Err(_) => std::ptr::null(),
+        }
+    }
+}
+
+impl Drop for OsMesaContext {
+    fn drop(&mut self) {
+        unsafe { (self.destroy_context)(self.context) };
+    }
+}
+
+// the function `name` from `library`, which has to be of type `T`
+unsafe fn load<T: Copy>(library: &libloading::Library, name: &str) -> Result<T, Error> {
+    library.get::<T>(name.as_bytes())
+        .map(|function| *function)
+        .map_err(|_| Error::OsMesaLoad { message: format!("{} is missing", name) })
+}
+
+// one line with every cause, so both attempts fit in one error
+fn describe(error: &dyn Fail) -> String {
+    let mut text = error.to_string();
+    let mut cause = error.cause();
+
+    while let Some(inner) = cause {
+        text += ": ";
+        text += &inner.to_string();
+        cause = inner.cause();
+    }
+
+    text
+}
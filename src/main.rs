@@ -8,29 +8,86 @@ extern crate nalgebra_glm as glm;
 mod render_gl;
 mod resources;
 mod generators;
+mod headless;
+mod scene;
+#[cfg(test)]
+mod golden;
 
 use crate::resources::Resources;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
 
-use crate::generators::mountain::{make_mountain};
-
-use crate::render_gl::object::{Object};
-use crate::render_gl::camera::{Camera};
+use crate::headless::{HeadlessContext};
+use crate::render_gl::render_target::{RenderTarget};
+use crate::render_gl::screenshot;
+use crate::scene::{Scene};
 
 const SCR_WIDTH: u32 = 800;
 const SCR_HEIGHT: u32 = 600;
 
 fn main() {
     if let Err(e) = run() {
-        println!("{}", failure_to_string(e));
+        eprintln!("{}", failure_to_string(e));
+        std::process::exit(1);
     }
 }
 
 fn run() -> Result<(), failure::Error> {
     let res = Resources::from_relative_exe_path(Path::new("assets")).unwrap();
+
+    // --render-to out.png [--frames N] renders without a window and saves the last frame
+    let mut render_to: Option<PathBuf> = None;
+    let mut frames: u32 = 1;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--render-to" => {
+                render_to = Some(args.next().ok_or_else(|| format_err!("--render-to needs a file name"))?.into());
+            },
+            "--frames" => {
+                frames = args.next()
+                    .and_then(|n| n.parse().ok())
+                    .ok_or_else(|| format_err!("--frames needs a number"))?;
+            },
+            _ => bail!("Unknown argument {}", arg),
+        }
+    }
+
+    match render_to {
+        Some(path) => run_headless(&res, &path, frames),
+        None => run_windowed(&res),
+    }
+}
+
+fn run_headless(res: &Resources, path: &Path, frames: u32) -> Result<(), failure::Error> {
+    let context = HeadlessContext::new()?;
+
+    let gl = gl::Gl::load_with(|s| context.get_proc_address(s));
+
+    let mut scene = Scene::new(&gl, res, SCR_WIDTH, SCR_HEIGHT)?;
+
+    // where the orbit in the window starts once the camera is moved
+    scene.camera.reposition(&glm::vec3(0.0, 80.0, 100.0));
+    // a saved frame can't wait for the landscape to stream in
+    scene.load_landscape()?;
+
+    // there is no window framebuffer, the final image goes here instead
+    let output = RenderTarget::new(&gl, SCR_WIDTH, SCR_HEIGHT, &[gl::RGBA8], None, 0)?;
+
+    for _ in 0..frames {
+        scene.update();
+        scene.render(Some(&output));
+    }
+
+    screenshot::capture(&gl, output.framebuffer().id(), SCR_WIDTH, SCR_HEIGHT, path)?;
+
+    Ok(())
+}
+
+fn run_windowed(res: &Resources) -> Result<(), failure::Error> {
     let sdl = sdl2::init().unwrap();
     let video_subsystem = sdl.video().unwrap();
     let mut timer = sdl.timer().unwrap();
@@ -50,71 +107,13 @@ fn run() -> Result<(), failure::Error> {
 
     let gl = gl::Gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void);
 
-    unsafe {
-        gl.Enable(gl::DEPTH_TEST);
-    }
-
-    let mountain_program = render_gl::Program::from_res(
-        &gl, &res, "shaders/triangle"
-    ).unwrap();
-
-    let square_program = render_gl::Program::from_res(
-        &gl, &res, "shaders/triangle"
-    ).unwrap();
-
-    let mut camera: Camera = Camera::make(
-        &gl,
-        SCR_WIDTH,
-        SCR_HEIGHT,
-        45.0,
-        0.1,
-        1000.0,
-    );
-
-    let mut camera_y = 80.0;
+    let mut scene = Scene::new(&gl, res, SCR_WIDTH, SCR_HEIGHT)?;
 
-    camera.reposition_and_look_at(&glm::vec3(0.0, camera_y, 0.0), &glm::vec3(0.0, 10.0, 0.0));
-    
-    let mountain: Object = make_mountain(&gl, mountain_program, 100.0, 100.0, 45.0, 20);
-
-    let square: Object = Object::make(
-        &gl,
-        square_program,
-        vec![
-            (-0.3, -0.3, -0.3), ( 0.3, -0.3, -0.3), ( 0.3,  0.3, -0.3),
-            ( 0.3,  0.3, -0.3), (-0.3,  0.3, -0.3), (-0.3, -0.3, -0.3),
-
-            (-0.3, -0.3,  0.3), ( 0.3, -0.3,  0.3), ( 0.3,  0.3,  0.3),
-            ( 0.3,  0.3,  0.3), (-0.3,  0.3,  0.3), (-0.3, -0.3,  0.3),
-
-            (-0.3,  0.3,  0.3), (-0.3,  0.3, -0.3), (-0.3, -0.3, -0.3),
-            (-0.3, -0.3, -0.3), (-0.3, -0.3,  0.3), (-0.3,  0.3,  0.3),
-
-            ( 0.3,  0.3,  0.3), ( 0.3,  0.3, -0.3), ( 0.3, -0.3, -0.3),
-            ( 0.3, -0.3, -0.3), ( 0.3, -0.3,  0.3), ( 0.3,  0.3,  0.3),
-
-            (-0.3, -0.3, -0.3), ( 0.3, -0.3, -0.3), ( 0.3, -0.3,  0.3),
-            ( 0.3, -0.3,  0.3), (-0.3, -0.3,  0.3), (-0.3, -0.3, -0.3),
-
-            (-0.3,  0.3, -0.3), ( 0.3,  0.3, -0.3), ( 0.3,  0.3,  0.3),
-            ( 0.3,  0.3,  0.3), (-0.3,  0.3,  0.3), (-0.3,  0.3, -0.3),
-        ],
-        vec![
-            (1.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 0.0, 0.0),
-            (0.0, 1.0, 0.0), (0.0, 1.0, 0.0), (0.0, 1.0, 0.0), (0.0, 1.0, 0.0), (0.0, 1.0, 0.0), (0.0, 1.0, 0.0),
-            (0.0, 0.0, 1.0), (0.0, 0.0, 1.0), (0.0, 0.0, 1.0), (0.0, 0.0, 1.0), (0.0, 0.0, 1.0), (0.0, 0.0, 1.0),
-            (1.0, 1.0, 0.0), (1.0, 1.0, 0.0), (1.0, 1.0, 0.0), (1.0, 1.0, 0.0), (1.0, 1.0, 0.0), (1.0, 1.0, 0.0),
-            (1.0, 0.0, 1.0), (1.0, 0.0, 1.0), (1.0, 0.0, 1.0), (1.0, 0.0, 1.0), (1.0, 0.0, 1.0), (1.0, 0.0, 1.0),
-            (1.0, 1.0, 1.0), (1.0, 1.0, 1.0), (1.0, 1.0, 1.0), (1.0, 1.0, 1.0), (1.0, 1.0, 1.0), (1.0, 1.0, 1.0),
-        ],
-    );
-
-    unsafe {
-        gl.Viewport(0, 0, SCR_WIDTH as i32, SCR_HEIGHT as i32); // set viewport
-        gl.ClearColor(0.3, 0.3, 0.5, 1.0);
-    }
+    let mut window_size = (SCR_WIDTH, SCR_HEIGHT);
+    let mut take_screenshot = false;
 
     let mut count = 0.0;
+    let mut camera_y = 80.0;
 
     let mut left_pressed = false;
     let mut right_pressed = false;
@@ -126,6 +125,10 @@ fn run() -> Result<(), failure::Error> {
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit {..} => break 'main,
+                Event::Window { win_event: WindowEvent::Resized(width, height), .. } => {
+                    window_size = (width as u32, height as u32);
+                    scene.resize(window_size.0, window_size.1)?;
+                },
                 Event::KeyDown { keycode: Some(Keycode::Left), .. } => {
                     left_pressed = true;
                 },
@@ -150,6 +153,32 @@ fn run() -> Result<(), failure::Error> {
                 Event::KeyUp { keycode: Some(Keycode::Down), .. } => {
                     down_pressed = false;
                 },
+                Event::KeyDown { keycode: Some(Keycode::F), repeat: false, .. } => {
+                    scene.debug_draw.flags.wireframe = !scene.debug_draw.flags.wireframe;
+                },
+                Event::KeyDown { keycode: Some(Keycode::N), repeat: false, .. } => {
+                    scene.debug_draw.flags.normals = !scene.debug_draw.flags.normals;
+                },
+                Event::KeyDown { keycode: Some(Keycode::B), repeat: false, .. } => {
+                    scene.debug_draw.flags.bounds = !scene.debug_draw.flags.bounds;
+                },
+                Event::KeyDown { keycode: Some(Keycode::X), repeat: false, .. } => {
+                    scene.debug_draw.flags.axes = !scene.debug_draw.flags.axes;
+                },
+                Event::KeyDown { keycode: Some(Keycode::G), repeat: false, .. } => {
+                    scene.debug_draw.flags.grid = !scene.debug_draw.flags.grid;
+                },
+                Event::KeyDown { keycode: Some(Keycode::R), repeat: false, .. } => {
+                    if let Err(e) = scene.reload(res) {
+                        println!("{}", failure_to_string(e));
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::P), repeat: false, .. } => {
+                    take_screenshot = true;
+                },
+                Event::KeyDown { keycode: Some(Keycode::L), repeat: false, .. } => {
+                    println!("{:?}", scene.landscape_stats());
+                },
                 _ => {},
             }
         }
@@ -159,20 +188,22 @@ fn run() -> Result<(), failure::Error> {
             if right_pressed { count = count + 0.03; }
             if up_pressed { camera_y = camera_y + 0.3; }
             if down_pressed { camera_y = camera_y - 0.3; }
-            camera.reposition(&glm::vec3(glm::sin(&glm::vec1(count)).x * 100.0, camera_y, glm::cos(&glm::vec1(count)).x * 100.0));
-        }
-
-        unsafe {
-            gl.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            scene.camera.reposition(&glm::vec3(glm::sin(&glm::vec1(count)).x * 100.0, camera_y, glm::cos(&glm::vec1(count)).x * 100.0));
         }
 
         // count = count + 0.005;
 
-        // square.matrix = glm::rotate_y(&square.matrix, glm::radians(&glm::vec1(2.0)).x);
-        // camera.matrix = glm::translate(&camera.matrix, &glm::vec3(0.0, 0.0, -0.01));
+        scene.update();
+        scene.render(None);
 
-        camera.draw(&square);
-        camera.draw(&mountain);
+        // read back before the swap, while the frame is still in the back buffer
+        if take_screenshot {
+            take_screenshot = false;
+            match screenshot::capture(&gl, 0, window_size.0, window_size.1, Path::new("screenshot.png")) {
+                Ok(()) => println!("Saved screenshot.png"),
+                Err(e) => println!("{}", failure_to_string(e.into())),
+            }
+        }
 
         window.gl_swap_window();
     }
@@ -0,0 +1,161 @@
+use gl;
+use crate::render_gl::device::{self, Device};
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BufferUsage {
+    /// uploaded once, drawn many times
+    Static,
+    /// rewritten now and then, e.g. terrain edits
+    Dynamic,
+    /// rewritten every frame or close to it
+    Stream,
+}
+
+impl BufferUsage {
+    fn gl_enum(self) -> gl::types::GLenum {
+        match self {
+            BufferUsage::Static => gl::STATIC_DRAW,
+            BufferUsage::Dynamic => gl::DYNAMIC_DRAW,
+            BufferUsage::Stream => gl::STREAM_DRAW,
+        }
+    }
+}
+
+/// Owned GL buffer object, deleted on drop. Edited through direct state access, so it never has
+/// to be bound to a target to change its data.
+pub struct Buffer {
+    device: Device,
+    id: gl::types::GLuint,
+    usage: BufferUsage,
+    size: usize,
+}
+
+impl Buffer {
+    pub fn new(device: &Device, usage: BufferUsage) -> Buffer {
+        Buffer {
+            device: device.clone(),
+            id: device.create_buffer(),
+            usage,
+            size: 0,
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    /// Size of the current data store in bytes.
+    #[allow(dead_code)]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Binds the buffer to an indexed binding point, e.g. `layout (binding = 0)` of a
+    /// `gl::UNIFORM_BUFFER` block.
+    pub fn bind_base(&self, target: gl::types::GLenum, index: u32) {
+        self.device.bind_buffer_base(target, index, self.id);
+    }
+
+    /// Replaces the whole data store, which may change its size.
+    pub fn upload<T: Copy>(&mut self, data: &[T]) {
+        self.size = data.len() * std::mem::size_of::<T>();
+
+        self.device.buffer_data(self.id, device::as_bytes(data), self.usage.gl_enum());
+    }
+
+    /// Overwrites part of the data store in place. `first` and the data length are counted in
+    /// elements of `T`, and the range has to fit into what was last uploaded.
+    pub fn update<T: Copy>(&self, first: usize, data: &[T]) {
+        let offset = first * std::mem::size_of::<T>();
+        let size = data.len() * std::mem::size_of::<T>();
+
+        assert!(offset + size <= self.size, "buffer update out of range: {}..{} of {} bytes", offset, offset + size, self.size);
+
+        self.device.buffer_sub_data(self.id, offset, device::as_bytes(data));
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        self.device.delete_buffer(self.id);
+    }
+}
+
+/// Persistently mapped buffer split into `segments` equally sized parts that are written round
+/// robin, so the CPU can fill one part while the GPU still reads the ones written before it.
+/// Every write fences the segment it leaves behind and waits for the fence of the segment it
+/// moves into, which only blocks when the CPU gets more than `segments - 1` frames ahead.
+pub struct RingBuffer {
+    device: Device,
+    id: gl::types::GLuint,
+    ptr: *mut u8,
+    segment_size: usize,
+    fences: Vec<gl::types::GLsync>,
+    current: usize,
+}
+
+impl RingBuffer {
+    pub fn new(device: &Device, segment_size: usize, segments: usize) -> RingBuffer {
+        let (id, ptr) = device.create_mapped_buffer(segment_size * segments);
+
+        RingBuffer {
+            device: device.clone(),
+            id,
+            ptr,
+            segment_size,
+            fences: vec![std::ptr::null(); segments],
+            current: 0,
+        }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    /// Byte offset of the segment that was written last.
+    pub fn offset(&self) -> usize {
+        self.current * self.segment_size
+    }
+
+    /// Moves on to the next segment and copies `data` to its start. Returns the byte offset of
+    /// that segment; draws issued afterwards should read from there.
+    pub fn write<T: Copy>(&mut self, data: &[T]) -> usize {
+        let size = data.len() * std::mem::size_of::<T>();
+
+        assert!(size <= self.segment_size, "ring buffer write of {} bytes exceeds segment size {}", size, self.segment_size);
+
+        // everything reading the current segment has been issued by now
+        self.fences[self.current] = self.device.fence_sync();
+
+        self.current = (self.current + 1) % self.fences.len();
+
+        let fence = self.fences[self.current];
+        if !fence.is_null() {
+            self.device.wait_sync(fence);
+            self.device.delete_sync(fence);
+            self.fences[self.current] = std::ptr::null();
+        }
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr() as *const u8,
+                self.ptr.add(self.offset()),
+                size,
+            );
+        }
+
+        self.offset()
+    }
+}
+
+impl Drop for RingBuffer {
+    fn drop(&mut self) {
+        for fence in self.fences.iter().filter(|fence| !fence.is_null()) {
+            self.device.delete_sync(*fence);
+        }
+
+        self.device.unmap_buffer(self.id);
+        self.device.delete_buffer(self.id);
+    }
+}
@@ -1,20 +1,21 @@
 extern crate nalgebra_glm as glm;
 
 use crate::render_gl::object::{Object};
-use gl;
+use crate::render_gl::state::{GlState};
+use crate::render_gl::render_state::{RenderState};
 
 pub struct Camera {
     pub matrix: glm::Mat4,
     pub position: glm::Vec3,
-    gl: gl::Gl,
     lense: glm::Mat4,
+    near: f32,
+    far: f32,
     up_direction: glm::Vec3,
     target: glm::Vec3,
 }
 
 impl Camera {
     pub fn make(
-        gl: &gl::Gl,
         width: u32,
         height: u32,
         angle: f32,
@@ -33,9 +34,10 @@ impl Camera {
             up_direction,
             position: camera_position,
             target: camera_target,
-            gl: gl.clone(),
             matrix: glm::look_at(&camera_position, &camera_target, &up_direction),
             lense: glm::perspective(width as f32 / height as f32, glm::radians(&glm::vec1(angle)).x, near, far),
+            near,
+            far,
         }
     }
 
@@ -58,10 +60,52 @@ impl Camera {
         self.matrix = glm::look_at(&self.position, &self.target, &self.up_direction);
     }
 
-    pub fn draw(&self, obj: &Object) {
-        obj.shader_program.set_mat4(&self.gl, "view", &self.matrix);
-        obj.shader_program.set_mat4(&self.gl, "projection", &self.lense);
+    /// This camera seen in a horizontal mirror at `height`. It renders what the mirror shows
+    /// lined up with this camera's image, so a pixel of the mirror finds its reflection at the
+    /// same place. Triangles come out with their winding flipped.
+    #[allow(dead_code)]
+    pub fn mirrored(&self, height: f32) -> Camera {
+        let mirror = glm::mat4(
+            1.0, 0.0, 0.0, 0.0,
+            0.0, -1.0, 0.0, 2.0 * height,
+            0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, 0.0, 1.0,
+        );
+        let flip = |p: &glm::Vec3| glm::vec3(p.x, 2.0 * height - p.y, p.z);
+
+        Camera {
+            matrix: self.matrix * mirror,
+            position: flip(&self.position),
+            lense: self.lense,
+            near: self.near,
+            far: self.far,
+            up_direction: self.up_direction,
+            target: flip(&self.target),
+        }
+    }
+
+    pub fn projection(&self) -> &glm::Mat4 {
+        &self.lense
+    }
+
+    /// Distances to the near and far clip planes.
+    pub fn clip_range(&self) -> (f32, f32) {
+        (self.near, self.far)
+    }
+
+    pub fn draw(&self, state: &mut GlState, obj: &Object) {
+        obj.material.program.set_mat4("view", &self.matrix);
+        obj.material.program.set_mat4("projection", &self.lense);
+        obj.material.program.set_vec3("camera_position", &self.position);
+
+        obj.draw(state);
+    }
+
+    pub fn draw_as(&self, state: &mut GlState, obj: &Object, render_state: &RenderState) {
+        obj.material.program.set_mat4("view", &self.matrix);
+        obj.material.program.set_mat4("projection", &self.lense);
+        obj.material.program.set_vec3("camera_position", &self.position);
 
-        obj.draw();
+        obj.draw_as(state, render_state);
     }
 }
\ No newline at end of file
@@ -1,5 +1,27 @@
 use gl;
 
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct f32_f32 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl f32_f32 {
+    pub fn new(x: f32, y: f32) -> f32_f32 {
+        f32_f32 {
+            x, y
+        }
+    }
+}
+
+impl From<(f32, f32)> for f32_f32 {
+    fn from(other: (f32, f32)) -> Self {
+        f32_f32::new(other.0, other.1)
+    }
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Copy, Clone, Debug)]
 #[repr(C, packed)]
@@ -21,4 +43,28 @@ impl From<(f32, f32, f32)> for f32_f32_f32 {
     fn from(other: (f32, f32, f32)) -> Self {
         f32_f32_f32::new(other.0, other.1, other.2)
     }
-}
\ No newline at end of file
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct f32_f32_f32_f32 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl f32_f32_f32_f32 {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> f32_f32_f32_f32 {
+        f32_f32_f32_f32 {
+            x, y, z, w
+        }
+    }
+}
+
+impl From<(f32, f32, f32, f32)> for f32_f32_f32_f32 {
+    fn from(other: (f32, f32, f32, f32)) -> Self {
+        f32_f32_f32_f32::new(other.0, other.1, other.2, other.3)
+    }
+}
@@ -0,0 +1,200 @@
+extern crate nalgebra_glm as glm;
+
+use gl;
+use crate::resources::Resources;
+use crate::render_gl::data;
+use crate::render_gl::buffer::{Buffer, BufferUsage};
+use crate::render_gl::device::{Device};
+use crate::render_gl::vertex_array::{VertexArray};
+use crate::render_gl::camera::{Camera};
+use crate::render_gl::object::{Object};
+use crate::render_gl::render_state::{RenderState};
+use crate::render_gl::shader::{Error, Program};
+use crate::render_gl::state::{GlState};
+
+const WIREFRAME_COLOR: (f32, f32, f32) = (0.1, 1.0, 0.3);
+const NORMAL_COLOR: (f32, f32, f32) = (1.0, 0.0, 1.0);
+const BOUNDS_COLOR: (f32, f32, f32) = (1.0, 1.0, 0.0);
+const GRID_COLOR: (f32, f32, f32) = (0.5, 0.5, 0.5);
+const SPHERE_SEGMENTS: usize = 24;
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+struct LineVertex {
+    pos: data::f32_f32_f32,
+    clr: data::f32_f32_f32,
+}
+
+/// What `draw_object` and `flush` add on top of the regular scene.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DebugFlags {
+    pub wireframe: bool,
+    pub normals: bool,
+    pub bounds: bool,
+    pub axes: bool,
+    pub grid: bool,
+}
+
+/// Immediate mode debug drawing. Lines queued during a frame all go into one stream buffer and
+/// are drawn with a single call in `flush`.
+pub struct DebugDraw {
+    pub flags: DebugFlags,
+    pub normal_length: f32,
+    program: Program,
+    lines: Vec<LineVertex>,
+    buffer: Buffer,
+    vert_array: VertexArray,
+    device: Device,
+}
+
+impl DebugDraw {
+    pub fn new(device: &Device, res: &Resources) -> Result<DebugDraw, Error> {
+        let program = Program::from_res(device, res, "shaders/debug")?;
+        let buffer = Buffer::new(device, BufferUsage::Stream);
+        let vert_array = VertexArray::new(device);
+
+        vert_array.vertex_buffer(0, buffer.id(), 0, std::mem::size_of::<LineVertex>());
+        vert_array.attrib_format(0, 3, 0, 0);
+        vert_array.attrib_format(1, 3, 0, std::mem::size_of::<data::f32_f32_f32>());
+
+        Ok(DebugDraw {
+            flags: DebugFlags::default(),
+            normal_length: 1.0,
+            program,
+            lines: vec![],
+            buffer,
+            vert_array,
+            device: device.clone(),
+        })
+    }
+
+    pub fn debug_line(&mut self, from: &glm::Vec3, to: &glm::Vec3, color: (f32, f32, f32)) {
+        self.lines.push(LineVertex { pos: (from.x, from.y, from.z).into(), clr: color.into() });
+        self.lines.push(LineVertex { pos: (to.x, to.y, to.z).into(), clr: color.into() });
+    }
+
+    /// Edges of the box between `min` and `max`, moved into place by `transform`.
+    pub fn debug_box(&mut self, min: &glm::Vec3, max: &glm::Vec3, transform: &glm::Mat4, color: (f32, f32, f32)) {
+        let corner = |i: usize| {
+            let local = glm::vec4(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+                1.0,
+            );
+            (transform * local).xyz()
+        };
+
+        // corners that differ in exactly one bit share an edge
+        for i in 0..8 {
+            for &bit in [1, 2, 4].iter() {
+                if i & bit == 0 {
+                    self.debug_line(&corner(i), &corner(i | bit), color);
+                }
+            }
+        }
+    }
+
+    /// Three circles around `center`, one in each axis plane.
+    #[allow(dead_code)]
+    pub fn debug_sphere(&mut self, center: &glm::Vec3, radius: f32, color: (f32, f32, f32)) {
+        let step = 2.0 * std::f32::consts::PI / SPHERE_SEGMENTS as f32;
+
+        for segment in 0..SPHERE_SEGMENTS {
+            let (s0, c0) = ((segment as f32 * step).sin() * radius, (segment as f32 * step).cos() * radius);
+            let (s1, c1) = (((segment + 1) as f32 * step).sin() * radius, ((segment + 1) as f32 * step).cos() * radius);
+
+            self.debug_line(&(center + glm::vec3(c0, s0, 0.0)), &(center + glm::vec3(c1, s1, 0.0)), color);
+            self.debug_line(&(center + glm::vec3(c0, 0.0, s0)), &(center + glm::vec3(c1, 0.0, s1)), color);
+            self.debug_line(&(center + glm::vec3(0.0, c0, s0)), &(center + glm::vec3(0.0, c1, s1)), color);
+        }
+    }
+
+    /// Draws `obj` as a wireframe right away if enabled, and queues its bounds and normals. For
+    /// instanced objects those are queued per instance, but there is no wireframe.
+    pub fn draw_object(&mut self, state: &mut GlState, camera: &Camera, obj: &Object) {
+        if self.flags.wireframe && obj.instance_models().is_none() {
+            self.set_camera(camera);
+            self.program.set_vec4("override_color", &glm::vec4(WIREFRAME_COLOR.0, WIREFRAME_COLOR.1, WIREFRAME_COLOR.2, 1.0));
+            obj.draw_with(state, &self.program, &RenderState::wireframe());
+
+            // the wireframe leaves depth writes off, which would also keep the next clear off it
+            RenderState::opaque().apply(state);
+        }
+
+        let transforms = match obj.instance_models() {
+            Some(models) => models.iter().map(|model| obj.matrix * model).collect(),
+            None => vec![obj.matrix],
+        };
+
+        if self.flags.bounds {
+            let (min, max) = obj.bounds();
+
+            for transform in transforms.iter() {
+                self.debug_box(&min, &max, transform, BOUNDS_COLOR);
+            }
+        }
+
+        if self.flags.normals {
+            let mesh = obj.mesh();
+
+            for transform in transforms.iter() {
+                let normal_matrix = glm::transpose(&glm::inverse(transform));
+
+                for i in 0..mesh.positions.len() {
+                    let (px, py, pz) = mesh.positions[i];
+                    let (nx, ny, nz) = mesh.normals[i];
+
+                    let position = (transform * glm::vec4(px, py, pz, 1.0)).xyz();
+                    let normal = glm::normalize(&(normal_matrix * glm::vec4(nx, ny, nz, 0.0)).xyz());
+
+                    self.debug_line(&position, &(position + normal * self.normal_length), NORMAL_COLOR);
+                }
+            }
+        }
+    }
+
+    /// Draws everything queued this frame, plus the axes and grid if enabled, and clears the queue.
+    pub fn flush(&mut self, state: &mut GlState, camera: &Camera) {
+        if self.flags.axes {
+            let origin = glm::vec3(0.0, 0.0, 0.0);
+            self.debug_line(&origin, &glm::vec3(10.0, 0.0, 0.0), (1.0, 0.0, 0.0));
+            self.debug_line(&origin, &glm::vec3(0.0, 10.0, 0.0), (0.0, 1.0, 0.0));
+            self.debug_line(&origin, &glm::vec3(0.0, 0.0, 10.0), (0.0, 0.0, 1.0));
+        }
+
+        if self.flags.grid {
+            let (half_size, spacing) = (100.0, 5.0);
+            let lines = (2.0 * half_size / spacing) as i32;
+
+            for i in 0..=lines {
+                let offset = -half_size + i as f32 * spacing;
+                self.debug_line(&glm::vec3(offset, 0.0, -half_size), &glm::vec3(offset, 0.0, half_size), GRID_COLOR);
+                self.debug_line(&glm::vec3(-half_size, 0.0, offset), &glm::vec3(half_size, 0.0, offset), GRID_COLOR);
+            }
+        }
+
+        if self.lines.is_empty() {
+            return;
+        }
+
+        self.buffer.upload(&self.lines);
+
+        self.set_camera(camera);
+        self.program.set_mat4("model", &glm::identity());
+        self.program.set_vec4("override_color", &glm::vec4(0.0, 0.0, 0.0, 0.0));
+
+        RenderState::opaque().apply(state);
+        state.use_program(self.program.id());
+        state.bind_vertex_array(self.vert_array.id());
+
+        self.device.draw_arrays(gl::LINES, 0, self.lines.len() as i32);
+
+        self.lines.clear();
+    }
+
+    fn set_camera(&self, camera: &Camera) {
+        self.program.set_mat4("view", &camera.matrix);
+        self.program.set_mat4("projection", camera.projection());
+    }
+}
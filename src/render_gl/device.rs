@@ -0,0 +1,430 @@
+extern crate nalgebra_glm as glm;
+
+use gl;
+use std::ffi::{CStr, CString};
+use std::rc::Rc;
+
+/// A uniform value, passed by name so backends can record what was set without knowing the
+/// program's locations.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Uniform {
+    Float(f32),
+    Int(i32),
+    Vec2(glm::Vec2),
+    Vec3(glm::Vec3),
+    Vec4(glm::Vec4),
+    Mat4(glm::Mat4),
+    /// consecutive elements of a `float name[]` array, starting at the first
+    FloatArray(Vec<f32>),
+    /// consecutive elements of a `mat4 name[]` array, starting at the first
+    Mat4Array(Vec<glm::Mat4>),
+}
+
+/// The buffer, vertex array, program, pipeline state and draw operations the renderer issues.
+/// `GlDevice` sends them to OpenGL; `RecordingDevice` only writes them down, for tests that
+/// check which commands something issues without needing a context.
+///
+/// Textures and framebuffers still go to `gl::Gl` directly.
+pub trait RenderDevice {
+    fn create_buffer(&self) -> gl::types::GLuint;
+    /// Replaces the whole data store of `buffer` with `data`.
+    fn buffer_data(&self, buffer: gl::types::GLuint, data: &[u8], usage: gl::types::GLenum);
+    fn buffer_sub_data(&self, buffer: gl::types::GLuint, offset: usize, data: &[u8]);
+    fn bind_buffer_base(&self, target: gl::types::GLenum, index: u32, buffer: gl::types::GLuint);
+    /// Immutable buffer of `size` bytes, mapped persistently and coherently for writing.
+    fn create_mapped_buffer(&self, size: usize) -> (gl::types::GLuint, *mut u8);
+    fn unmap_buffer(&self, buffer: gl::types::GLuint);
+    fn delete_buffer(&self, buffer: gl::types::GLuint);
+
+    /// Fence signalled once every command issued so far has completed.
+    fn fence_sync(&self) -> gl::types::GLsync;
+    /// Blocks until `fence` is signalled.
+    fn wait_sync(&self, fence: gl::types::GLsync);
+    fn delete_sync(&self, fence: gl::types::GLsync);
+
+    fn create_vertex_array(&self) -> gl::types::GLuint;
+    fn vertex_array_vertex_buffer(&self, vertex_array: gl::types::GLuint, binding: u32, buffer: gl::types::GLuint, offset: usize, stride: usize);
+    fn vertex_array_element_buffer(&self, vertex_array: gl::types::GLuint, buffer: gl::types::GLuint);
+    /// Enables a float attribute at `location` reading `components` values from `binding`.
+    fn vertex_array_attrib_format(&self, vertex_array: gl::types::GLuint, location: u32, components: usize, binding: u32, relative_offset: usize);
+    fn vertex_array_binding_divisor(&self, vertex_array: gl::types::GLuint, binding: u32, divisor: u32);
+    fn delete_vertex_array(&self, vertex_array: gl::types::GLuint);
+
+    /// Compiles a shader of `kind`, returning the info log on failure.
+    fn compile_shader(&self, kind: gl::types::GLenum, source: &CStr) -> Result<gl::types::GLuint, String>;
+    fn delete_shader(&self, shader: gl::types::GLuint);
+    /// Links `shaders` into a new program, returning the info log on failure.
+    fn link_program(&self, shaders: &[gl::types::GLuint]) -> Result<gl::types::GLuint, String>;
+    fn delete_program(&self, program: gl::types::GLuint);
+    /// Sets a uniform of `program`, whether it is in use or not.
+    fn set_uniform(&self, program: gl::types::GLuint, name: &str, value: &Uniform);
+
+    fn use_program(&self, program: gl::types::GLuint);
+    fn bind_vertex_array(&self, vertex_array: gl::types::GLuint);
+    fn bind_texture_unit(&self, unit: u32, texture: gl::types::GLuint);
+    fn set_enabled(&self, capability: gl::types::GLenum, enabled: bool);
+    fn blend_equation(&self, equation: gl::types::GLenum);
+    fn blend_func(&self, source: gl::types::GLenum, destination: gl::types::GLenum);
+    fn depth_func(&self, func: gl::types::GLenum);
+    fn depth_mask(&self, write: bool);
+    fn cull_face(&self, face: gl::types::GLenum);
+    fn front_face(&self, winding: gl::types::GLenum);
+    fn polygon_mode(&self, mode: gl::types::GLenum);
+    fn polygon_offset(&self, factor: f32, units: f32);
+    fn stencil_func(&self, func: gl::types::GLenum, reference: i32, mask: u32);
+    fn stencil_op(&self, stencil_fail: gl::types::GLenum, depth_fail: gl::types::GLenum, pass: gl::types::GLenum);
+    fn stencil_mask(&self, mask: u32);
+    fn scissor(&self, x: i32, y: i32, width: i32, height: i32);
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32);
+    fn active_texture(&self, unit: u32);
+
+    fn draw_arrays(&self, mode: gl::types::GLenum, first: i32, count: i32);
+    fn draw_arrays_instanced(&self, mode: gl::types::GLenum, first: i32, count: i32, instances: i32);
+    /// Draws `count` u32 indices from the start of the element buffer.
+    fn draw_elements_base_vertex(&self, mode: gl::types::GLenum, count: i32, base_vertex: i32);
+    fn draw_elements_instanced_base_vertex(&self, mode: gl::types::GLenum, count: i32, instances: i32, base_vertex: i32);
+
+    /// `glGetIntegerv`, filling as many values as `name` has.
+    fn get_integers(&self, name: gl::types::GLenum, values: &mut [gl::types::GLint]);
+    fn is_enabled(&self, capability: gl::types::GLenum) -> bool;
+}
+
+/// Shared handle to a device, held by everything that creates or draws through it.
+pub type Device = Rc<dyn RenderDevice>;
+
+/// The OpenGL backend.
+pub struct GlDevice {
+    gl: gl::Gl,
+}
+
+impl GlDevice {
+    pub fn new(gl: &gl::Gl) -> GlDevice {
+        GlDevice { gl: gl.clone() }
+    }
+}
+
+impl RenderDevice for GlDevice {
+    fn create_buffer(&self) -> gl::types::GLuint {
+        let mut id: gl::types::GLuint = 0;
+        unsafe { self.gl.CreateBuffers(1, &mut id); }
+        id
+    }
+
+    fn buffer_data(&self, buffer: gl::types::GLuint, data: &[u8], usage: gl::types::GLenum) {
+        unsafe {
+            self.gl.NamedBufferData(
+                buffer,
+                data.len() as gl::types::GLsizeiptr,
+                data.as_ptr() as *const gl::types::GLvoid,
+                usage,
+            );
+        }
+    }
+
+    fn buffer_sub_data(&self, buffer: gl::types::GLuint, offset: usize, data: &[u8]) {
+        unsafe {
+            self.gl.NamedBufferSubData(
+                buffer,
+                offset as gl::types::GLintptr,
+                data.len() as gl::types::GLsizeiptr,
+                data.as_ptr() as *const gl::types::GLvoid,
+            );
+        }
+    }
+
+    fn bind_buffer_base(&self, target: gl::types::GLenum, index: u32, buffer: gl::types::GLuint) {
+        unsafe { self.gl.BindBufferBase(target, index, buffer); }
+    }
+
+    fn create_mapped_buffer(&self, size: usize) -> (gl::types::GLuint, *mut u8) {
+        let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+        let id = self.create_buffer();
+
+        let ptr = unsafe {
+            self.gl.NamedBufferStorage(id, size as gl::types::GLsizeiptr, std::ptr::null(), flags);
+            self.gl.MapNamedBufferRange(id, 0, size as gl::types::GLsizeiptr, flags)
+        };
+
+        (id, ptr as *mut u8)
+    }
+
+    fn unmap_buffer(&self, buffer: gl::types::GLuint) {
+        unsafe { self.gl.UnmapNamedBuffer(buffer); }
+    }
+
+    fn delete_buffer(&self, buffer: gl::types::GLuint) {
+        unsafe { self.gl.DeleteBuffers(1, &buffer); }
+    }
+
+    fn fence_sync(&self) -> gl::types::GLsync {
+        unsafe { self.gl.FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) }
+    }
+
+    fn wait_sync(&self, fence: gl::types::GLsync) {
+        unsafe {
+            while self.gl.ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, 1_000_000) == gl::TIMEOUT_EXPIRED {}
+        }
+    }
+
+    fn delete_sync(&self, fence: gl::types::GLsync) {
+        unsafe { self.gl.DeleteSync(fence); }
+    }
+
+    fn create_vertex_array(&self) -> gl::types::GLuint {
+        let mut id: gl::types::GLuint = 0;
+        unsafe { self.gl.CreateVertexArrays(1, &mut id); }
+        id
+    }
+
+    fn vertex_array_vertex_buffer(&self, vertex_array: gl::types::GLuint, binding: u32, buffer: gl::types::GLuint, offset: usize, stride: usize) {
+        unsafe {
+            self.gl.VertexArrayVertexBuffer(
+                vertex_array,
+                binding,
+                buffer,
+                offset as gl::types::GLintptr,
+                stride as gl::types::GLsizei,
+            );
+        }
+    }
+
+    fn vertex_array_element_buffer(&self, vertex_array: gl::types::GLuint, buffer: gl::types::GLuint) {
+        unsafe { self.gl.VertexArrayElementBuffer(vertex_array, buffer); }
+    }
+
+    fn vertex_array_attrib_format(&self, vertex_array: gl::types::GLuint, location: u32, components: usize, binding: u32, relative_offset: usize) {
+        unsafe {
+            self.gl.EnableVertexArrayAttrib(vertex_array, location);
+            self.gl.VertexArrayAttribFormat(
+                vertex_array,
+                location,
+                components as gl::types::GLint,
+                gl::FLOAT,
+                gl::FALSE, // normalized (int-to-float conversion)
+                relative_offset as gl::types::GLuint,
+            );
+            self.gl.VertexArrayAttribBinding(vertex_array, location, binding);
+        }
+    }
+
+    fn vertex_array_binding_divisor(&self, vertex_array: gl::types::GLuint, binding: u32, divisor: u32) {
+        unsafe { self.gl.VertexArrayBindingDivisor(vertex_array, binding, divisor); }
+    }
+
+    fn delete_vertex_array(&self, vertex_array: gl::types::GLuint) {
+        unsafe { self.gl.DeleteVertexArrays(1, &vertex_array); }
+    }
+
+    fn compile_shader(&self, kind: gl::types::GLenum, source: &CStr) -> Result<gl::types::GLuint, String> {
+        let id = unsafe { self.gl.CreateShader(kind) };
+
+        unsafe {
+            self.gl.ShaderSource(id, 1, &source.as_ptr(), std::ptr::null());
+            self.gl.CompileShader(id);
+        }
+
+        let mut success: gl::types::GLint = 1;
+        unsafe { self.gl.GetShaderiv(id, gl::COMPILE_STATUS, &mut success); }
+
+        if success == 0 {
+            let mut len: gl::types::GLint = 0;
+            unsafe { self.gl.GetShaderiv(id, gl::INFO_LOG_LENGTH, &mut len); }
+
+            let error = create_filled_cstring(len as usize, b' ');
+
+            unsafe {
+                self.gl.GetShaderInfoLog(id, len, std::ptr::null_mut(), error.as_ptr() as *mut gl::types::GLchar);
+                self.gl.DeleteShader(id);
+            }
+
+            return Err(error.to_string_lossy().into_owned());
+        }
+
+        Ok(id)
+    }
+
+    fn delete_shader(&self, shader: gl::types::GLuint) {
+        unsafe { self.gl.DeleteShader(shader); }
+    }
+
+    fn link_program(&self, shaders: &[gl::types::GLuint]) -> Result<gl::types::GLuint, String> {
+        let id = unsafe { self.gl.CreateProgram() };
+
+        for &shader in shaders {
+            unsafe { self.gl.AttachShader(id, shader); }
+        }
+
+        unsafe { self.gl.LinkProgram(id); }
+
+        let mut success: gl::types::GLint = 1;
+        unsafe { self.gl.GetProgramiv(id, gl::LINK_STATUS, &mut success); }
+
+        if success == 0 {
+            let mut len: gl::types::GLint = 0;
+            unsafe { self.gl.GetProgramiv(id, gl::INFO_LOG_LENGTH, &mut len); }
+
+            let error = create_filled_cstring(len as usize, b' ');
+
+            unsafe {
+                self.gl.GetProgramInfoLog(id, len, std::ptr::null_mut(), error.as_ptr() as *mut gl::types::GLchar);
+                self.gl.DeleteProgram(id);
+            }
+
+            return Err(error.to_string_lossy().into_owned());
+        }
+
+        for &shader in shaders {
+            unsafe { self.gl.DetachShader(id, shader); }
+        }
+
+        Ok(id)
+    }
+
+    fn delete_program(&self, program: gl::types::GLuint) {
+        unsafe { self.gl.DeleteProgram(program); }
+    }
+
+    fn set_uniform(&self, program: gl::types::GLuint, name: &str, value: &Uniform) {
+        let c_str = CString::new(name).unwrap();
+
+        // ProgramUniform writes to this program even when another one is in use
+        unsafe {
+            let loc = self.gl.GetUniformLocation(program, c_str.as_ptr());
+
+            match value {
+                Uniform::Float(value) => self.gl.ProgramUniform1f(program, loc, *value),
+                Uniform::Int(value) => self.gl.ProgramUniform1i(program, loc, *value),
+                Uniform::Vec2(value) => self.gl.ProgramUniform2fv(program, loc, 1, value.as_ptr()),
+                Uniform::Vec3(value) => self.gl.ProgramUniform3fv(program, loc, 1, value.as_ptr()),
+                Uniform::Vec4(value) => self.gl.ProgramUniform4fv(program, loc, 1, value.as_ptr()),
+                Uniform::Mat4(value) => self.gl.ProgramUniformMatrix4fv(program, loc, 1, gl::FALSE, value.as_ptr()),
+                Uniform::FloatArray(values) => {
+                    self.gl.ProgramUniform1fv(program, loc, values.len() as gl::types::GLsizei, values.as_ptr())
+                },
+                Uniform::Mat4Array(values) => {
+                    let floats: Vec<f32> = values.iter().flat_map(|m| m.as_slice().to_vec()).collect();
+                    self.gl.ProgramUniformMatrix4fv(program, loc, values.len() as gl::types::GLsizei, gl::FALSE, floats.as_ptr())
+                },
+            }
+        }
+    }
+
+    fn use_program(&self, program: gl::types::GLuint) {
+        unsafe { self.gl.UseProgram(program); }
+    }
+
+    fn bind_vertex_array(&self, vertex_array: gl::types::GLuint) {
+        unsafe { self.gl.BindVertexArray(vertex_array); }
+    }
+
+    fn bind_texture_unit(&self, unit: u32, texture: gl::types::GLuint) {
+        unsafe { self.gl.BindTextureUnit(unit, texture); }
+    }
+
+    fn set_enabled(&self, capability: gl::types::GLenum, enabled: bool) {
+        unsafe {
+            if enabled {
+                self.gl.Enable(capability);
+            } else {
+                self.gl.Disable(capability);
+            }
+        }
+    }
+
+    fn blend_equation(&self, equation: gl::types::GLenum) {
+        unsafe { self.gl.BlendEquation(equation); }
+    }
+
+    fn blend_func(&self, source: gl::types::GLenum, destination: gl::types::GLenum) {
+        unsafe { self.gl.BlendFunc(source, destination); }
+    }
+
+    fn depth_func(&self, func: gl::types::GLenum) {
+        unsafe { self.gl.DepthFunc(func); }
+    }
+
+    fn depth_mask(&self, write: bool) {
+        unsafe { self.gl.DepthMask(if write { gl::TRUE } else { gl::FALSE }); }
+    }
+
+    fn cull_face(&self, face: gl::types::GLenum) {
+        unsafe { self.gl.CullFace(face); }
+    }
+
+    fn front_face(&self, winding: gl::types::GLenum) {
+        unsafe { self.gl.FrontFace(winding); }
+    }
+
+    fn polygon_mode(&self, mode: gl::types::GLenum) {
+        unsafe { self.gl.PolygonMode(gl::FRONT_AND_BACK, mode); }
+    }
+
+    fn polygon_offset(&self, factor: f32, units: f32) {
+        unsafe { self.gl.PolygonOffset(factor, units); }
+    }
+
+    fn stencil_func(&self, func: gl::types::GLenum, reference: i32, mask: u32) {
+        unsafe { self.gl.StencilFunc(func, reference, mask); }
+    }
+
+    fn stencil_op(&self, stencil_fail: gl::types::GLenum, depth_fail: gl::types::GLenum, pass: gl::types::GLenum) {
+        unsafe { self.gl.StencilOp(stencil_fail, depth_fail, pass); }
+    }
+
+    fn stencil_mask(&self, mask: u32) {
+        unsafe { self.gl.StencilMask(mask); }
+    }
+
+    fn scissor(&self, x: i32, y: i32, width: i32, height: i32) {
+        unsafe { self.gl.Scissor(x, y, width, height); }
+    }
+
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32) {
+        unsafe { self.gl.Viewport(x, y, width, height); }
+    }
+
+    fn active_texture(&self, unit: u32) {
+        unsafe { self.gl.ActiveTexture(gl::TEXTURE0 + unit); }
+    }
+
+    fn draw_arrays(&self, mode: gl::types::GLenum, first: i32, count: i32) {
+        unsafe { self.gl.DrawArrays(mode, first, count); }
+    }
+
+    fn draw_arrays_instanced(&self, mode: gl::types::GLenum, first: i32, count: i32, instances: i32) {
+        unsafe { self.gl.DrawArraysInstanced(mode, first, count, instances); }
+    }
+
+    fn draw_elements_base_vertex(&self, mode: gl::types::GLenum, count: i32, base_vertex: i32) {
+        unsafe { self.gl.DrawElementsBaseVertex(mode, count, gl::UNSIGNED_INT, std::ptr::null(), base_vertex); }
+    }
+
+    fn draw_elements_instanced_base_vertex(&self, mode: gl::types::GLenum, count: i32, instances: i32, base_vertex: i32) {
+        unsafe {
+            self.gl.DrawElementsInstancedBaseVertex(mode, count, gl::UNSIGNED_INT, std::ptr::null(), instances, base_vertex);
+        }
+    }
+
+    fn get_integers(&self, name: gl::types::GLenum, values: &mut [gl::types::GLint]) {
+        unsafe { self.gl.GetIntegerv(name, values.as_mut_ptr()); }
+    }
+
+    fn is_enabled(&self, capability: gl::types::GLenum) -> bool {
+        unsafe { self.gl.IsEnabled(capability) == gl::TRUE }
+    }
+}
+
+fn create_filled_cstring(len: usize, fill: u8) -> CString {
+    // make error buffer with correct size
+    let mut buffer: Vec<u8> = Vec::with_capacity(len as usize + 1);
+
+    // fill it with spaces
+    buffer.extend([fill].iter().cycle().take(len as usize));
+
+    unsafe { CString::from_vec_unchecked(buffer) }
+}
+
+/// Views a slice of plain vertex or index data as bytes, for uploading.
+pub fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * std::mem::size_of::<T>()) }
+}
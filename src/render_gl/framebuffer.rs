@@ -0,0 +1,175 @@
+use gl;
+use crate::render_gl::texture::{Texture};
+
+/// Why a framebuffer can't be rendered to, from `glCheckFramebufferStatus`.
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Framebuffer is incomplete: an attachment is missing its image or has a format it can't render to")]
+    IncompleteAttachment,
+    #[fail(display = "Framebuffer is incomplete: nothing is attached")]
+    MissingAttachment,
+    #[fail(display = "Framebuffer is incomplete: a draw buffer has no attachment")]
+    IncompleteDrawBuffer,
+    #[fail(display = "Framebuffer is incomplete: the read buffer has no attachment")]
+    IncompleteReadBuffer,
+    #[fail(display = "Framebuffer attachment formats are not supported together by the driver")]
+    Unsupported,
+    #[fail(display = "Framebuffer is incomplete: attachments have different sample counts")]
+    IncompleteMultisample,
+    #[fail(display = "Framebuffer is incomplete: layered and non-layered attachments are mixed")]
+    IncompleteLayerTargets,
+    #[fail(display = "Framebuffer is incomplete, status 0x{:x}", status)]
+    Unknown { status: gl::types::GLenum },
+}
+
+impl Error {
+    /// `None` for `gl::FRAMEBUFFER_COMPLETE`.
+    pub fn from_status(status: gl::types::GLenum) -> Option<Error> {
+        match status {
+            gl::FRAMEBUFFER_COMPLETE => None,
+            gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => Some(Error::IncompleteAttachment),
+            gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => Some(Error::MissingAttachment),
+            gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => Some(Error::IncompleteDrawBuffer),
+            gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => Some(Error::IncompleteReadBuffer),
+            gl::FRAMEBUFFER_UNSUPPORTED => Some(Error::Unsupported),
+            gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => Some(Error::IncompleteMultisample),
+            gl::FRAMEBUFFER_INCOMPLETE_LAYER_TARGETS => Some(Error::IncompleteLayerTargets),
+            status => Some(Error::Unknown { status }),
+        }
+    }
+}
+
+/// Owned framebuffer object, deleted on drop. Attachments are set through direct state access,
+/// it only gets bound to render into it.
+#[allow(dead_code)]
+pub struct Framebuffer {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+}
+
+#[allow(dead_code)]
+impl Framebuffer {
+    pub fn new(gl: &gl::Gl) -> Framebuffer {
+        let mut id: gl::types::GLuint = 0;
+
+        unsafe {
+            gl.CreateFramebuffers(1, &mut id);
+        }
+
+        Framebuffer { gl: gl.clone(), id }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    /// Makes this the target of draw calls (and reads) until something else is bound.
+    pub fn bind(&self) {
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, self.id);
+        }
+    }
+
+    /// Goes back to rendering into the window.
+    pub fn bind_default(gl: &gl::Gl) {
+        unsafe {
+            gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// `attachment` is e.g. `gl::COLOR_ATTACHMENT0` or `gl::DEPTH_ATTACHMENT`.
+    pub fn attach_texture(&self, attachment: gl::types::GLenum, texture: &Texture, level: u32) {
+        unsafe {
+            self.gl.NamedFramebufferTexture(self.id, attachment, texture.id(), level as gl::types::GLint);
+        }
+    }
+
+    /// Attaches a single layer of an array texture.
+    pub fn attach_texture_layer(&self, attachment: gl::types::GLenum, texture: &Texture, level: u32, layer: u32) {
+        unsafe {
+            self.gl.NamedFramebufferTextureLayer(self.id, attachment, texture.id(), level as gl::types::GLint, layer as gl::types::GLint);
+        }
+    }
+
+    pub fn attach_renderbuffer(&self, attachment: gl::types::GLenum, renderbuffer: &Renderbuffer) {
+        unsafe {
+            self.gl.NamedFramebufferRenderbuffer(self.id, attachment, gl::RENDERBUFFER, renderbuffer.id());
+        }
+    }
+
+    /// Which color attachments fragment shader outputs 0, 1, .. write to. Pass an empty slice
+    /// for depth-only framebuffers.
+    pub fn draw_buffers(&self, attachments: &[gl::types::GLenum]) {
+        unsafe {
+            if attachments.is_empty() {
+                self.gl.NamedFramebufferDrawBuffer(self.id, gl::NONE);
+                self.gl.NamedFramebufferReadBuffer(self.id, gl::NONE);
+            } else {
+                self.gl.NamedFramebufferDrawBuffers(self.id, attachments.len() as gl::types::GLsizei, attachments.as_ptr());
+            }
+        }
+    }
+
+    /// Raw completeness status, `gl::FRAMEBUFFER_COMPLETE` when it can be rendered to.
+    pub fn status(&self) -> gl::types::GLenum {
+        unsafe {
+            self.gl.CheckNamedFramebufferStatus(self.id, gl::FRAMEBUFFER)
+        }
+    }
+
+    /// `Ok` when the attachments make a framebuffer that can be rendered to.
+    pub fn check(&self) -> Result<(), Error> {
+        match Error::from_status(self.status()) {
+            None => Ok(()),
+            Some(e) => Err(e),
+        }
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.DeleteFramebuffers(1, &self.id);
+        }
+    }
+}
+
+/// Owned renderbuffer, deleted on drop. For attachments that are rendered to but never sampled.
+#[allow(dead_code)]
+pub struct Renderbuffer {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+}
+
+#[allow(dead_code)]
+impl Renderbuffer {
+    /// `samples` of 0 makes a regular, not multisampled, renderbuffer.
+    pub fn new(gl: &gl::Gl, format: gl::types::GLenum, width: u32, height: u32, samples: u32) -> Renderbuffer {
+        let mut id: gl::types::GLuint = 0;
+
+        unsafe {
+            gl.CreateRenderbuffers(1, &mut id);
+            gl.NamedRenderbufferStorageMultisample(
+                id,
+                samples as gl::types::GLsizei,
+                format,
+                width as gl::types::GLsizei,
+                height as gl::types::GLsizei,
+            );
+        }
+
+        Renderbuffer { gl: gl.clone(), id }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+}
+
+impl Drop for Renderbuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.DeleteRenderbuffers(1, &self.id);
+        }
+    }
+}
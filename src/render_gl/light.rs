@@ -0,0 +1,129 @@
+extern crate nalgebra_glm as glm;
+
+use gl;
+use crate::render_gl::buffer::{Buffer, BufferUsage};
+use crate::render_gl::device::{Device};
+
+/// `layout (std140, binding = ..)` of the `Lights` uniform block in the lit shaders.
+pub const LIGHTS_BINDING: u32 = 0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    /// infinitely far away, like the sun; only the direction matters
+    Directional,
+    /// shines in all directions from a position, fading with distance
+    Point,
+    /// a point light limited to a cone, with soft edges between the inner and outer angle
+    Spot { inner_angle: f32, outer_angle: f32 },
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub position: glm::Vec3,
+    /// direction the light travels in, for directional and spot lights
+    pub direction: glm::Vec3,
+    pub color: glm::Vec3,
+    pub intensity: f32,
+    /// constant, linear and quadratic falloff over distance, for point and spot lights
+    pub attenuation: glm::Vec3,
+}
+
+impl Light {
+    pub fn directional(direction: &glm::Vec3, color: &glm::Vec3, intensity: f32) -> Light {
+        Light {
+            kind: LightKind::Directional,
+            position: glm::vec3(0.0, 0.0, 0.0),
+            direction: glm::normalize(direction),
+            color: *color,
+            intensity,
+            attenuation: glm::vec3(1.0, 0.0, 0.0),
+        }
+    }
+
+    /// Point light that falls to roughly 1% of its intensity at `range`.
+    pub fn point(position: &glm::Vec3, color: &glm::Vec3, intensity: f32, range: f32) -> Light {
+        Light {
+            kind: LightKind::Point,
+            position: *position,
+            direction: glm::vec3(0.0, -1.0, 0.0),
+            color: *color,
+            intensity,
+            attenuation: glm::vec3(1.0, 4.5 / range, 75.0 / (range * range)),
+        }
+    }
+
+    /// Spot light; angles are in degrees from the center of the cone.
+    #[allow(dead_code)]
+    pub fn spot(position: &glm::Vec3, direction: &glm::Vec3, color: &glm::Vec3, intensity: f32, range: f32, inner_angle: f32, outer_angle: f32) -> Light {
+        Light {
+            kind: LightKind::Spot { inner_angle, outer_angle },
+            direction: glm::normalize(direction),
+            ..Light::point(position, color, intensity, range)
+        }
+    }
+
+    /// The four std140 vec4s of the shader's `Light` struct.
+    fn rows(&self) -> [[f32; 4]; 4] {
+        let (kind, cos_inner, cos_outer) = match self.kind {
+            LightKind::Directional => (0.0, 1.0, 1.0),
+            LightKind::Point => (1.0, 1.0, 1.0),
+            LightKind::Spot { inner_angle, outer_angle } => (
+                2.0,
+                glm::radians(&glm::vec1(inner_angle)).x.cos(),
+                glm::radians(&glm::vec1(outer_angle)).x.cos(),
+            ),
+        };
+        let color = self.color * self.intensity;
+
+        [
+            [self.position.x, self.position.y, self.position.z, kind],
+            [self.direction.x, self.direction.y, self.direction.z, cos_inner],
+            [color.x, color.y, color.z, cos_outer],
+            [self.attenuation.x, self.attenuation.y, self.attenuation.z, 0.0],
+        ]
+    }
+}
+
+/// The lights of a scene, mirrored into a uniform buffer. The shaders are compiled for at most
+/// `max_lights` (see `defines`), anything past that is left out of the upload.
+pub struct LightSet {
+    pub ambient: glm::Vec3,
+    pub lights: Vec<Light>,
+    max_lights: usize,
+    buffer: Buffer,
+}
+
+impl LightSet {
+    pub fn new(device: &Device, max_lights: usize) -> LightSet {
+        LightSet {
+            ambient: glm::vec3(0.1, 0.1, 0.1),
+            lights: vec![],
+            max_lights,
+            buffer: Buffer::new(device, BufferUsage::Dynamic),
+        }
+    }
+
+    /// Defines to compile the lit shaders with, so their light array fits this set.
+    pub fn defines(&self) -> Vec<(&'static str, String)> {
+        vec![("MAX_LIGHTS", self.max_lights.to_string())]
+    }
+
+    /// Copies the lights into the uniform buffer and binds it for the lit shaders.
+    pub fn upload(&mut self) {
+        let count = self.lights.len().min(self.max_lights);
+
+        // std140: vec4 ambient, int light_count padded to 16 bytes, then Light[MAX_LIGHTS]
+        let mut rows: Vec<[f32; 4]> = Vec::with_capacity(2 + 4 * self.max_lights);
+        rows.push([self.ambient.x, self.ambient.y, self.ambient.z, 0.0]);
+        rows.push([f32::from_bits(count as u32), 0.0, 0.0, 0.0]); // the shader reads these bits as an int
+
+        for light in self.lights.iter().take(count) {
+            rows.extend(light.rows().iter());
+        }
+        rows.resize(2 + 4 * self.max_lights, [0.0; 4]);
+
+        self.buffer.upload(&rows);
+        self.buffer.bind_base(gl::UNIFORM_BUFFER, LIGHTS_BINDING);
+    }
+}
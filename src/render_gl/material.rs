@@ -0,0 +1,125 @@
+extern crate nalgebra_glm as glm;
+
+use std::rc::Rc;
+use crate::render_gl::shader::{Program};
+use crate::render_gl::state::{GlState};
+use crate::render_gl::texture::{Texture};
+
+// `layout (binding = ..)` of the material samplers in the pbr shader, after the shadow map
+const BASE_COLOR_UNIT: u32 = 2;
+const METALLIC_ROUGHNESS_UNIT: u32 = 3;
+const NORMAL_UNIT: u32 = 4;
+const OCCLUSION_UNIT: u32 = 5;
+const EMISSIVE_UNIT: u32 = 6;
+const SPLAT_MAP_UNIT: u32 = 7;
+const SPLAT_LAYERS_UNIT: u32 = 8;
+
+// bits of the `texture_flags` uniform, telling the shader which samplers have a texture
+const HAS_BASE_COLOR: i32 = 1;
+const HAS_METALLIC_ROUGHNESS: i32 = 2;
+const HAS_NORMAL: i32 = 4;
+const HAS_OCCLUSION: i32 = 8;
+const HAS_EMISSIVE: i32 = 16;
+const HAS_SPLAT: i32 = 32;
+
+/// Ground textures blended by a splat map over the vertex color, e.g. for terrain.
+#[allow(dead_code)]
+pub struct Splat {
+    /// weight of each layer in rgba, spread over `area`
+    pub map: Rc<Texture>,
+    /// up to four RGBA textures in an array
+    pub layers: Rc<Texture>,
+    /// world units over which each layer repeats
+    pub tiling: glm::Vec4,
+    /// min x, min z, width and depth in world space covered by the map
+    pub area: glm::Vec4,
+    /// how much the textures cover the vertex color, from 0 for none to 1 for all of it
+    pub strength: f32,
+}
+
+/// glTF style metallic/roughness material. Every factor is multiplied with its texture where one
+/// is set, and the base color also with the vertex color. Programs and textures are shared, so
+/// many materials can use the same compiled shader.
+#[allow(dead_code)]
+pub struct Material {
+    pub program: Rc<Program>,
+    pub base_color: glm::Vec4,
+    /// 0.0 for dielectrics, 1.0 for bare metal
+    pub metallic: f32,
+    /// 0.0 is a perfect mirror, 1.0 completely diffuse
+    pub roughness: f32,
+    pub emissive: glm::Vec3,
+    /// strength of the normal map's xy
+    pub normal_scale: f32,
+    /// how much the occlusion map darkens indirect light
+    pub occlusion_strength: f32,
+    /// rgb base color, alpha coverage
+    pub base_color_texture: Option<Rc<Texture>>,
+    /// roughness in the green channel, metallic in blue
+    pub metallic_roughness_texture: Option<Rc<Texture>>,
+    /// tangent space normals
+    pub normal_texture: Option<Rc<Texture>>,
+    /// occlusion in the red channel
+    pub occlusion_texture: Option<Rc<Texture>>,
+    pub emissive_texture: Option<Rc<Texture>>,
+    pub splat: Option<Splat>,
+}
+
+impl Material {
+    /// White, fully rough dielectric without textures, the glTF defaults.
+    pub fn new(program: Rc<Program>) -> Material {
+        Material {
+            program,
+            base_color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            metallic: 0.0,
+            roughness: 1.0,
+            emissive: glm::vec3(0.0, 0.0, 0.0),
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+            splat: None,
+        }
+    }
+
+    /// Sets the factors on the program and binds the textures, ready for a draw.
+    pub fn bind(&self, state: &mut GlState) {
+        self.program.set_vec4("base_color_factor", &self.base_color);
+        self.program.set_float("metallic_factor", self.metallic);
+        self.program.set_float("roughness_factor", self.roughness);
+        self.program.set_vec3("emissive_factor", &self.emissive);
+        self.program.set_float("normal_scale", self.normal_scale);
+        self.program.set_float("occlusion_strength", self.occlusion_strength);
+
+        let textures = [
+            (&self.base_color_texture, BASE_COLOR_UNIT, HAS_BASE_COLOR),
+            (&self.metallic_roughness_texture, METALLIC_ROUGHNESS_UNIT, HAS_METALLIC_ROUGHNESS),
+            (&self.normal_texture, NORMAL_UNIT, HAS_NORMAL),
+            (&self.occlusion_texture, OCCLUSION_UNIT, HAS_OCCLUSION),
+            (&self.emissive_texture, EMISSIVE_UNIT, HAS_EMISSIVE),
+        ];
+
+        let mut flags = 0;
+
+        for &(texture, unit, flag) in textures.iter() {
+            if let Some(texture) = texture {
+                state.bind_texture_unit(unit, texture.id());
+                flags |= flag;
+            }
+        }
+
+        if let Some(ref splat) = self.splat {
+            state.bind_texture_unit(SPLAT_MAP_UNIT, splat.map.id());
+            state.bind_texture_unit(SPLAT_LAYERS_UNIT, splat.layers.id());
+            self.program.set_vec4("splat_tiling", &splat.tiling);
+            self.program.set_vec4("splat_area", &splat.area);
+            self.program.set_float("splat_strength", splat.strength);
+            flags |= HAS_SPLAT;
+        }
+
+        self.program.set_int("texture_flags", flags);
+    }
+}
@@ -0,0 +1,363 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashMap;
+
+/// Vertex data on the CPU side, before it goes into an `Object`. Colors are in 0.0..1.0.
+/// Without indices every three vertices make a triangle, counter-clockwise seen from the front.
+/// Texture coordinates are optional, an empty list maps everything to (0, 0). So are tangents,
+/// without them the shader derives a tangent frame per pixel.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub positions: Vec<(f32, f32, f32)>,
+    pub colors: Vec<(f32, f32, f32)>,
+    pub normals: Vec<(f32, f32, f32)>,
+    pub tex_coords: Vec<(f32, f32)>,
+    /// xyz along increasing u, w is 1.0 or -1.0 for which way v goes along the bitangent
+    pub tangents: Vec<(f32, f32, f32, f32)>,
+    pub indices: Vec<u32>,
+}
+
+/// How a mesh's normals run over its surface.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shading {
+    /// one normal per face, every edge shows
+    Flat,
+    /// normals averaged over the faces meeting at a point, hiding the edges
+    Smooth,
+}
+
+impl Mesh {
+    /// Mesh with normals computed from its triangles, see `compute_normals`.
+    pub fn new(positions: Vec<(f32, f32, f32)>, colors: Vec<(f32, f32, f32)>, indices: Vec<u32>) -> Mesh {
+        let mut mesh = Mesh { positions, colors, normals: vec![], tex_coords: vec![], tangents: vec![], indices };
+        mesh.compute_normals();
+        mesh
+    }
+
+    /// Replaces the normals. `Flat` gives every triangle its own vertices first; `Smooth` averages
+    /// the faces around every position, whether the vertices there are shared or not, weighted
+    /// by the angle at the corner so it doesn't matter how a face is split into triangles. See
+    /// `compute_normals` for weighting by area. Tangents are dropped, they no longer fit.
+    #[allow(dead_code)]
+    pub fn shade(&mut self, shading: Shading) {
+        self.tangents.clear();
+
+        match shading {
+            Shading::Flat => {
+                self.unweld();
+                self.compute_normals();
+            },
+            Shading::Smooth => self.compute_smooth_normals(),
+        }
+    }
+
+    /// Tangents from the texture coordinates, made perpendicular to the normals. Vertices
+    /// without any change in texture coordinates around them get some tangent across their
+    /// normal.
+    #[allow(dead_code)]
+    pub fn compute_tangents(&mut self) {
+        let count = self.positions.len();
+        let mut tangents = vec![glm::vec3(0.0, 0.0, 0.0); count];
+        let mut bitangents = vec![glm::vec3(0.0, 0.0, 0.0); count];
+
+        for triangle in self.triangle_indices() {
+            let [a, b, c] = triangle;
+            let uv = |i: usize| self.tex_coords.get(i).map(|&(u, v)| glm::vec2(u, v)).unwrap_or_else(glm::Vec2::zeros);
+
+            let (edge1, edge2) = (self.position(b) - self.position(a), self.position(c) - self.position(a));
+            let (duv1, duv2) = (uv(b) - uv(a), uv(c) - uv(a));
+
+            let determinant = duv1.x * duv2.y - duv2.x * duv1.y;
+            if determinant.abs() < 1e-12 {
+                continue;
+            }
+
+            // unnormalized, so triangles stretched over more of the texture count for more
+            let tangent = (edge1 * duv2.y - edge2 * duv1.y) / determinant;
+            let bitangent = (edge2 * duv1.x - edge1 * duv2.x) / determinant;
+
+            for &corner in triangle.iter() {
+                tangents[corner] += tangent;
+                bitangents[corner] += bitangent;
+            }
+        }
+
+        self.tangents = (0..count).map(|i| {
+            let normal = self.normals.get(i).map(|&(x, y, z)| glm::vec3(x, y, z)).unwrap_or_else(glm::Vec3::y);
+            let along = tangents[i] - normal * glm::dot(&normal, &tangents[i]);
+
+            let tangent = if along.norm_squared() > 1e-12 {
+                glm::normalize(&along)
+            } else {
+                any_perpendicular(&normal)
+            };
+
+            let handedness = if glm::dot(&glm::cross::<f32, glm::U3>(&normal, &tangent), &bitangents[i]) < 0.0 { -1.0 } else { 1.0 };
+
+            (tangent.x, tangent.y, tangent.z, handedness)
+        }).collect();
+    }
+
+    /// Adds the vertices and triangles of `other`, making both indexed if either one is.
+    #[allow(dead_code)]
+    pub fn append(&mut self, other: &Mesh) {
+        if !self.indices.is_empty() || !other.indices.is_empty() {
+            if self.indices.is_empty() {
+                self.indices = (0..self.positions.len() as u32).collect();
+            }
+
+            let offset = self.positions.len() as u32;
+            if other.indices.is_empty() {
+                self.indices.extend(offset..offset + other.positions.len() as u32);
+            } else {
+                self.indices.extend(other.indices.iter().map(|i| i + offset));
+            }
+        }
+
+        // optional attributes stay in line with the positions once either mesh has them
+        let count = self.positions.len();
+        append_optional(&mut self.tex_coords, &other.tex_coords, count, other.positions.len(), (0.0, 0.0));
+        append_optional(&mut self.tangents, &other.tangents, count, other.positions.len(), (0.0, 0.0, 0.0, 0.0));
+
+        self.positions.extend_from_slice(&other.positions);
+        self.colors.extend_from_slice(&other.colors);
+        self.normals.extend_from_slice(&other.normals);
+    }
+
+    /// Flat face normals when every triangle has its own vertices, otherwise normals averaged
+    /// over the faces sharing a vertex, weighted by face area.
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![glm::vec3(0.0, 0.0, 0.0); self.positions.len()];
+
+        for triangle in self.triangle_indices() {
+            // unnormalized, so larger faces count for more
+            let face = self.face_normal(triangle);
+
+            for &corner in triangle.iter() {
+                normals[corner] += face;
+            }
+        }
+
+        self.normals = normals.iter()
+            .map(|n| if n.norm_squared() > 0.0 { glm::normalize(n) } else { *n })
+            .map(|n| (n.x, n.y, n.z))
+            .collect();
+    }
+
+    pub fn position(&self, i: usize) -> glm::Vec3 {
+        let (x, y, z) = self.positions[i];
+        glm::vec3(x, y, z)
+    }
+
+    /// Vertex indices of every triangle, whether the mesh is indexed or not.
+    pub fn triangle_indices(&self) -> Vec<[usize; 3]> {
+        if self.indices.is_empty() {
+            (0..(self.positions.len() / 3)).map(|t| [t * 3, t * 3 + 1, t * 3 + 2]).collect()
+        } else {
+            self.indices.chunks(3).map(|t| [t[0] as usize, t[1] as usize, t[2] as usize]).collect()
+        }
+    }
+
+    /// Corner positions of every triangle.
+    #[allow(dead_code)]
+    pub fn triangles(&self) -> Vec<[glm::Vec3; 3]> {
+        self.triangle_indices().iter()
+            .map(|t| [self.position(t[0]), self.position(t[1]), self.position(t[2])])
+            .collect()
+    }
+
+    /// Corners of the axis aligned box around all vertices.
+    pub fn bounds(&self) -> (glm::Vec3, glm::Vec3) {
+        let mut min = glm::vec3(std::f32::MAX, std::f32::MAX, std::f32::MAX);
+        let mut max = glm::vec3(std::f32::MIN, std::f32::MIN, std::f32::MIN);
+
+        for i in 0..self.positions.len() {
+            min = glm::min2(&min, &self.position(i));
+            max = glm::max2(&max, &self.position(i));
+        }
+
+        (min, max)
+    }
+
+    /// Gives every triangle its own three vertices and drops the indices.
+    fn unweld(&mut self) {
+        if self.indices.is_empty() {
+            return;
+        }
+
+        let corners: Vec<usize> = self.indices.iter().map(|&i| i as usize).collect();
+
+        self.positions = corners.iter().map(|&i| self.positions[i]).collect();
+        self.colors = corners.iter().map(|&i| self.colors[i]).collect();
+        self.normals = corners.iter().map(|&i| self.normals[i]).collect();
+        if !self.tex_coords.is_empty() {
+            self.tex_coords = corners.iter().map(|&i| self.tex_coords[i]).collect();
+        }
+        self.indices.clear();
+    }
+
+    fn compute_smooth_normals(&mut self) {
+        // adding 0.0 turns -0.0 into 0.0, the same position
+        let key = |(x, y, z): (f32, f32, f32)| ((x + 0.0).to_bits(), (y + 0.0).to_bits(), (z + 0.0).to_bits());
+        let mut sums: HashMap<(u32, u32, u32), glm::Vec3> = HashMap::new();
+
+        for triangle in self.triangle_indices() {
+            // degenerate triangles have no direction to add
+            let face = self.face_normal(triangle);
+            if face.norm_squared() == 0.0 {
+                continue;
+            }
+            let face = glm::normalize(&face);
+
+            for corner in 0..3 {
+                let at = self.position(triangle[corner]);
+                let to_next = self.position(triangle[(corner + 1) % 3]) - at;
+                let to_previous = self.position(triangle[(corner + 2) % 3]) - at;
+
+                let angle = glm::angle(&to_next, &to_previous);
+                *sums.entry(key(self.positions[triangle[corner]])).or_insert_with(glm::Vec3::zeros) += face * angle;
+            }
+        }
+
+        self.normals = self.positions.iter()
+            .map(|&position| sums.get(&key(position)).cloned().unwrap_or_else(glm::Vec3::zeros))
+            .map(|n| if n.norm_squared() > 0.0 { glm::normalize(&n) } else { n })
+            .map(|n| (n.x, n.y, n.z))
+            .collect();
+    }
+
+    fn face_normal(&self, triangle: [usize; 3]) -> glm::Vec3 {
+        let a = self.position(triangle[0]);
+        let b = self.position(triangle[1]);
+        let c = self.position(triangle[2]);
+
+        glm::cross::<f32, glm::U3>(&(b - a), &(c - a))
+    }
+}
+
+fn any_perpendicular(normal: &glm::Vec3) -> glm::Vec3 {
+    // crossing with the axis least like the normal keeps the result well away from zero
+    let axis = if normal.x.abs() < 0.9 { glm::Vec3::x() } else { glm::Vec3::y() };
+    glm::normalize(&glm::cross::<f32, glm::U3>(&axis, normal))
+}
+
+fn append_optional<T: Copy>(values: &mut Vec<T>, other: &[T], count: usize, other_count: usize, fill: T) {
+    if values.is_empty() && other.is_empty() {
+        return;
+    }
+
+    values.resize(count, fill);
+    values.extend_from_slice(other);
+    values.resize(count + other_count, fill);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: (f32, f32, f32), b: (f32, f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4 && (a.2 - b.2).abs() < 1e-4
+    }
+
+    // two faces folded along the z axis into a roof, sharing their ridge vertices
+    fn roof() -> Mesh {
+        Mesh::new(
+            vec![(0.0, 1.0, 0.0), (0.0, 1.0, 1.0), (-1.0, 0.0, 0.0), (-1.0, 0.0, 1.0), (1.0, 0.0, 0.0), (1.0, 0.0, 1.0)],
+            vec![(1.0, 1.0, 1.0); 6],
+            vec![0, 3, 1, 0, 2, 3, 0, 1, 5, 0, 5, 4],
+        )
+    }
+
+    #[test]
+    fn flat_shading_gives_every_face_its_own_normal() {
+        let mut mesh = roof();
+        mesh.shade(Shading::Flat);
+
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+
+        assert!(mesh.indices.is_empty());
+        assert_eq!(mesh.positions.len(), 12);
+        assert!(mesh.normals[..6].iter().all(|&n| close(n, (-half, half, 0.0))));
+        assert!(mesh.normals[6..].iter().all(|&n| close(n, (half, half, 0.0))));
+    }
+
+    #[test]
+    fn smooth_shading_joins_vertices_at_the_same_position() {
+        let mut mesh = roof();
+        mesh.shade(Shading::Flat);
+        mesh.shade(Shading::Smooth);
+
+        // the ridge points straight up on both faces, the eaves keep their face's normal
+        assert!(close(mesh.normals[0], (0.0, 1.0, 0.0)));
+        assert!(close(mesh.normals[6], (0.0, 1.0, 0.0)));
+        assert!(close(mesh.normals[5], mesh.normals[4]));
+        assert!(mesh.normals[5].0 < 0.0);
+    }
+
+    #[test]
+    fn smooth_normals_do_not_depend_on_how_a_face_is_split() {
+        // the left face split into four triangles around its middle instead of two
+        let mut mesh = roof();
+        let middle = (-0.5, 0.5, 0.5);
+        let left = [(0.0, 1.0, 0.0), (-1.0, 0.0, 0.0), (-1.0, 0.0, 1.0), (0.0, 1.0, 1.0)];
+
+        mesh.indices.drain(..6);
+        for i in 0..4 {
+            mesh.append(&Mesh::new(vec![left[i], left[(i + 1) % 4], middle], vec![(1.0, 1.0, 1.0); 3], vec![]));
+        }
+
+        mesh.shade(Shading::Smooth);
+
+        let mut expected = roof();
+        expected.shade(Shading::Smooth);
+
+        assert!(close(mesh.normals[0], expected.normals[0]));
+        assert!(close(mesh.normals[0], (0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn tangents_follow_u_and_are_perpendicular_to_the_normal() {
+        let mut mesh = Mesh::new(
+            vec![(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 0.0, -1.0), (0.0, 0.0, -1.0)],
+            vec![(1.0, 1.0, 1.0); 4],
+            vec![0, 1, 2, 0, 2, 3],
+        );
+        mesh.tex_coords = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        mesh.compute_tangents();
+
+        assert!(mesh.tangents.iter().all(|&t| t == (1.0, 0.0, 0.0, 1.0)));
+
+        // u running the other way mirrors the texture
+        mesh.tex_coords = vec![(1.0, 0.0), (0.0, 0.0), (0.0, 1.0), (1.0, 1.0)];
+        mesh.compute_tangents();
+
+        assert!(mesh.tangents.iter().all(|&t| t == (-1.0, 0.0, 0.0, -1.0)));
+    }
+
+    #[test]
+    fn tangents_without_texture_coordinates_are_still_perpendicular() {
+        let mut mesh = roof();
+        mesh.compute_tangents();
+
+        assert_eq!(mesh.tangents.len(), mesh.positions.len());
+
+        for (&(tx, ty, tz, w), &(nx, ny, nz)) in mesh.tangents.iter().zip(mesh.normals.iter()) {
+            assert!((tx * nx + ty * ny + tz * nz).abs() < 1e-5);
+            assert!(((tx * tx + ty * ty + tz * tz) - 1.0).abs() < 1e-5);
+            assert_eq!(w.abs(), 1.0);
+        }
+    }
+
+    #[test]
+    fn appending_to_an_indexed_mesh_indexes_the_new_vertices() {
+        let mut mesh = roof();
+        let triangle = Mesh::new(vec![(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 0.0, -1.0)], vec![(1.0, 1.0, 1.0); 3], vec![]);
+
+        mesh.append(&triangle);
+
+        assert_eq!(mesh.positions.len(), 9);
+        assert_eq!(mesh.normals.len(), 9);
+        assert_eq!(&mesh.indices[12..], &[6, 7, 8]);
+    }
+}
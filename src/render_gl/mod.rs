@@ -2,5 +2,23 @@ pub mod shader;
 pub mod data;
 pub mod object;
 pub mod camera;
+pub mod buffer;
+pub mod vertex_array;
+pub mod texture;
+pub mod framebuffer;
+pub mod state;
+pub mod render_state;
+pub mod debug;
+pub mod mesh;
+pub mod light;
+pub mod shadow;
+pub mod material;
+pub mod render_target;
+pub mod post;
+pub mod screenshot;
+pub mod rasterizer;
+pub mod device;
+pub mod recording_device;
+pub mod water;
 
-pub use self::shader::{Error, Program, Shader};
\ No newline at end of file
+pub use self::shader::{Error, Program, Shader};
@@ -1,7 +1,15 @@
 extern crate nalgebra_glm as glm;
 
 use gl;
+use std::rc::Rc;
 use crate::render_gl::data;
+use crate::render_gl::buffer::{Buffer, BufferUsage, RingBuffer};
+use crate::render_gl::device::{Device};
+use crate::render_gl::vertex_array::{VertexArray};
+use crate::render_gl::state::{GlState};
+use crate::render_gl::render_state::{RenderState};
+use crate::render_gl::material::{Material};
+use crate::render_gl::mesh::{Mesh, Shading};
 use crate::render_gl::shader::{Program};
 
 #[derive(Copy, Clone, Debug)]
@@ -9,29 +17,126 @@ use crate::render_gl::shader::{Program};
 struct Vertex {
     pos: data::f32_f32_f32,
     clr: data::f32_f32_f32,
+    nrm: data::f32_f32_f32,
+    uv: data::f32_f32,
+    tan: data::f32_f32_f32_f32,
+}
+
+// per-instance attributes start here, leaving the lower locations for per-vertex data.
+// a mat4 attribute takes up 4 consecutive locations (one per column)
+const INSTANCE_MODEL_LOCATION: u32 = 8;
+const INSTANCE_TINT_LOCATION: u32 = 12;
+
+// vertex array binding points the vertex and instance buffers are attached to
+const VERTEX_BINDING: u32 = 0;
+const INSTANCE_BINDING: u32 = 1;
+
+#[derive(Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct Instance {
+    model: [data::f32_f32_f32_f32; 4],
+    tint: data::f32_f32_f32_f32,
+}
+
+impl Instance {
+    pub fn new(model: &glm::Mat4, tint: &glm::Vec4) -> Instance {
+        let column = |i: usize| (model[(0, i)], model[(1, i)], model[(2, i)], model[(3, i)]).into();
+
+        Instance {
+            model: [column(0), column(1), column(2), column(3)],
+            tint: (tint.x, tint.y, tint.z, tint.w).into(),
+        }
+    }
+
+    pub fn model(&self) -> glm::Mat4 {
+        // copied out first, fields of a packed struct can't be borrowed
+        let model = self.model;
+        let column = |c: data::f32_f32_f32_f32| glm::vec4(c.x, c.y, c.z, c.w);
+
+        glm::Mat4::from_columns(&[column(model[0]), column(model[1]), column(model[2]), column(model[3])])
+    }
+}
+
+enum VertexStorage {
+    Buffer(Buffer),
+    Ring(RingBuffer),
 }
 
 pub struct Object {
     pub matrix: glm::Mat4,
-    pub shader_program: Program,
-    vertices: Vec<Vertex>,
-    vert_array: gl::types::GLuint,
-    gl: gl::Gl,
+    pub material: Rc<Material>,
+    pub render_state: RenderState,
+    /// How `replace_vertices` and `update_vertices` shade the changed mesh, see `Mesh::shade`.
+    /// `None` weights the normals by face area like `Mesh::compute_normals`.
+    pub shading: Option<Shading>,
+    mesh: Mesh,
+    vert_array: VertexArray,
+    vertex_storage: VertexStorage,
+    index_buffer: Option<Buffer>,
+    instance_buffer: Option<Buffer>,
+    // model matrix of every copy to draw; `None` draws the mesh once without per-instance data
+    instance_models: Option<Vec<glm::Mat4>>,
+    device: Device,
 }
 
 impl Object {
+    #[allow(dead_code)]
     pub fn make(
-        gl: &gl::Gl,
-        program: Program,
+        device: &Device,
+        material: Rc<Material>,
         verts: Vec<(f32, f32, f32)>,
         colors: Vec<(f32, f32, f32)>,
     ) -> Object {
+        Object::make_indexed(device, material, verts, colors, vec![])
+    }
+
+    #[allow(dead_code)]
+    pub fn make_indexed(
+        device: &Device,
+        material: Rc<Material>,
+        verts: Vec<(f32, f32, f32)>,
+        colors: Vec<(f32, f32, f32)>,
+        indices: Vec<u32>,
+    ) -> Object {
+        Object::make_with_usage(device, material, verts, colors, indices, BufferUsage::Static)
+    }
+
+    /// Like `make_indexed`, with a usage hint for how often the vertices are going to be
+    /// replaced or updated afterwards.
+    #[allow(dead_code)]
+    pub fn make_with_usage(
+        device: &Device,
+        material: Rc<Material>,
+        verts: Vec<(f32, f32, f32)>,
+        colors: Vec<(f32, f32, f32)>,
+        indices: Vec<u32>,
+        usage: BufferUsage,
+    ) -> Object {
+        let colors = colors.iter().map(|&(r, g, b)| (r / 255.0, g / 255.0, b / 255.0)).collect();
+
+        Object::from_mesh(device, material, Mesh::new(verts, colors, indices), usage)
+    }
+
+    pub fn from_mesh(
+        device: &Device,
+        material: Rc<Material>,
+        mesh: Mesh,
+        usage: BufferUsage,
+    ) -> Object {
+        let vertex_buffer = Buffer::new(device, usage);
+
         let mut obj: Object = Object {
             matrix: glm::identity(),
-            vertices: verts.iter().zip(colors.iter()).map(|(&vert, &col)| Vertex { pos: vert.into(), clr: (col.0 / 255.0, col.1 / 255.0, col.2 / 255.0).into() }).collect(),
-            vert_array: 0,
-            shader_program: program,
-            gl: gl.clone(),
+            mesh,
+            vert_array: VertexArray::new(device),
+            vertex_storage: VertexStorage::Buffer(vertex_buffer),
+            index_buffer: None,
+            instance_buffer: None,
+            instance_models: None,
+            material,
+            render_state: RenderState::default(),
+            shading: None,
+            device: device.clone(),
         };
 
         obj.gen_buffers();
@@ -39,81 +144,475 @@ impl Object {
         obj
     }
 
-    pub fn draw(&self) {
-        self.shader_program.set_mat4(&self.gl, "model", &self.matrix);
+    /// Object whose vertices live in a persistently mapped ring buffer of `frames` segments, each
+    /// holding up to `capacity` vertices. Meant for meshes that are rewritten every frame; starts
+    /// out empty until the first `replace_vertices`.
+    #[allow(dead_code)]
+    pub fn make_streaming(
+        device: &Device,
+        material: Rc<Material>,
+        capacity: usize,
+        frames: usize,
+    ) -> Object {
+        let ring = RingBuffer::new(device, capacity * std::mem::size_of::<Vertex>(), frames);
 
-        self.shader_program.activate();
+        let mut obj: Object = Object {
+            matrix: glm::identity(),
+            mesh: Mesh::default(),
+            vert_array: VertexArray::new(device),
+            vertex_storage: VertexStorage::Ring(ring),
+            index_buffer: None,
+            instance_buffer: None,
+            instance_models: None,
+            material,
+            render_state: RenderState::default(),
+            shading: None,
+            device: device.clone(),
+        };
 
-        unsafe {
-            self.gl.BindVertexArray(self.vert_array);
-            self.gl.DrawArrays(
+        obj.gen_buffers();
+
+        obj
+    }
+
+    /// Binds the material and draws with its program.
+    pub fn draw(&self, state: &mut GlState) {
+        self.draw_as(state, &self.render_state);
+    }
+
+    /// Draws with some other pipeline state than the object's own, e.g. as a wireframe overlay.
+    pub fn draw_as(&self, state: &mut GlState, render_state: &RenderState) {
+        self.material.bind(state);
+        self.draw_with(state, &self.material.program, render_state);
+    }
+
+    /// Draws the mesh through another program, which gets the object's model matrix. Its
+    /// view and projection have to be set already.
+    pub fn draw_with(&self, state: &mut GlState, program: &Program, render_state: &RenderState) {
+        program.set_mat4("model", &self.matrix);
+
+        render_state.apply(state);
+        state.use_program(program.id());
+
+        // streamed vertices start wherever the ring buffer wrote them last
+        let first = match self.vertex_storage {
+            VertexStorage::Buffer(_) => 0,
+            VertexStorage::Ring(ref ring) => ring.offset() / std::mem::size_of::<Vertex>(),
+        };
+
+        state.bind_vertex_array(self.vert_array.id());
+
+        let device = &self.device;
+
+        match (self.mesh.indices.is_empty(), self.instance_models.as_ref().map(|models| models.len())) {
+            (_, Some(0)) => {},
+            (true, None) => device.draw_arrays(
+                gl::TRIANGLES,
+                first as i32,
+                self.mesh.positions.len() as i32,
+            ),
+            (false, None) => device.draw_elements_base_vertex(
+                gl::TRIANGLES,
+                self.mesh.indices.len() as i32,
+                first as i32,
+            ),
+            (true, Some(instances)) => device.draw_arrays_instanced(
                 gl::TRIANGLES,
-                0,
-                self.vertices.len() as i32,
-            );
+                first as i32,
+                self.mesh.positions.len() as i32,
+                instances as i32,
+            ),
+            (false, Some(instances)) => device.draw_elements_instanced_base_vertex(
+                gl::TRIANGLES,
+                self.mesh.indices.len() as i32,
+                instances as i32,
+                first as i32,
+            ),
         }
     }
 
-    fn gen_buffers(&mut self) {
-        let mut vertex_buffer: gl::types::GLuint = 0;
+    /// Replaces the per-instance data. From then on `draw` renders one copy of the mesh per
+    /// instance, so nothing at all for an empty slice; the shader gets the instance model matrix and tint at locations 8 and 12.
+    /// Safe to call every frame - the buffer storage is orphaned on each upload.
+    #[allow(dead_code)]
+    pub fn set_instances(&mut self, instances: &[Instance]) {
+        if self.instance_buffer.is_none() {
+            self.gen_instance_buffer();
+        }
+
+        if let Some(ref mut buffer) = self.instance_buffer {
+            buffer.upload(instances);
+        }
+
+        self.instance_models = Some(instances.iter().map(Instance::model).collect());
+    }
+
+    /// Goes back to drawing the mesh once, with no per-instance data.
+    #[allow(dead_code)]
+    pub fn clear_instances(&mut self) {
+        self.instance_models = None;
+    }
+
+    /// Swaps out all vertices, the count may change. Indices are kept, so they have to stay valid
+    /// for the new vertices. Colors are in 0.0..255.0 like in `make`, normals and tangents get
+    /// recomputed, see `reshade`.
+    #[allow(dead_code)]
+    pub fn replace_vertices(&mut self, verts: &[(f32, f32, f32)], colors: &[(f32, f32, f32)]) {
+        self.mesh.positions = verts.to_vec();
+        self.mesh.colors = colors.iter().map(|&(r, g, b)| (r / 255.0, g / 255.0, b / 255.0)).collect();
+        self.reshade();
+
+        self.upload_vertices();
+    }
+
+    /// Swaps out the whole mesh, indices and normals included.
+    #[allow(dead_code)]
+    pub fn replace_mesh(&mut self, mesh: Mesh) {
+        self.mesh = mesh;
+
+        self.upload_vertices();
+        self.upload_indices();
+    }
+
+    /// Overwrites the vertices from `first` onwards, leaving the rest untouched. Normals and
+    /// tangents are recomputed, so for indexed or smooth shaded meshes, where a change spreads
+    /// into neighbouring vertices, the whole vertex buffer gets uploaded again.
+    #[allow(dead_code)]
+    pub fn update_vertices(&mut self, first: usize, verts: &[(f32, f32, f32)], colors: &[(f32, f32, f32)]) {
+        let last = first + verts.len();
+
+        assert!(last <= self.mesh.positions.len(), "vertex update out of range");
+        assert_eq!(colors.len(), verts.len(), "one color per updated vertex");
+
+        self.mesh.positions[first..last].copy_from_slice(verts);
+        for (i, &(r, g, b)) in colors.iter().enumerate() {
+            self.mesh.colors[first + i] = (r / 255.0, g / 255.0, b / 255.0);
+        }
+        self.reshade();
+
+        // without indices a vertex only affects the normals of its own triangle, unless smooth
+        // shading averages them with every other vertex at the same position
+        let (first, last) = if self.mesh.indices.is_empty() && self.shading != Some(Shading::Smooth) {
+            (first / 3 * 3, (last + 2) / 3 * 3)
+        } else {
+            (0, self.mesh.positions.len())
+        };
+
+        let vertices = self.vertices();
+
+        match self.vertex_storage {
+            VertexStorage::Buffer(ref buffer) => buffer.update(first, &vertices[first..last]),
+            // a ring segment may be stale by a few frames, so it always gets the whole mesh
+            VertexStorage::Ring(ref mut ring) => { ring.write(&vertices); },
+        }
+    }
 
-        unsafe {
-            self.gl.GenBuffers(1, &mut vertex_buffer);
+    /// Model matrices of the instances set with `set_instances`, in the object's space.
+    pub fn instance_models(&self) -> Option<&[glm::Mat4]> {
+        self.instance_models.as_ref().map(|models| &models[..])
+    }
+
+    pub fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+
+    /// Corners of the axis aligned box around all vertices, in object space.
+    pub fn bounds(&self) -> (glm::Vec3, glm::Vec3) {
+        self.mesh.bounds()
+    }
+
+    // normals for changed positions, shaded like `shading` says. Tangents are recomputed when
+    // the mesh had them and still has texture coordinates for every vertex, otherwise they are
+    // dropped and the shader derives its own
+    fn reshade(&mut self) {
+        let had_tangents = !self.mesh.tangents.is_empty();
+
+        match self.shading {
+            Some(Shading::Smooth) => self.mesh.shade(Shading::Smooth),
+            // flat shaded meshes have their own vertices per triangle already, and unwelding
+            // an indexed mesh here would change the vertex count under the caller
+            Some(Shading::Flat) | None => {
+                self.mesh.tangents.clear();
+                self.mesh.compute_normals();
+            },
         }
 
-        unsafe {
-            //                  v- we give it a buffer type that we want to work with
-            self.gl.BindBuffer(gl::ARRAY_BUFFER, vertex_buffer);
-            self.gl.BufferData(
-                gl::ARRAY_BUFFER,
-                (self.vertices.len() * std::mem::size_of::<Vertex>()) as gl::types::GLsizeiptr, // size of data in bytes
-                self.vertices.as_ptr() as *const gl::types::GLvoid,
-                gl::STATIC_DRAW // i guess this is how we want this buffer to be used?
-            );
-            self.gl.BindBuffer(gl::ARRAY_BUFFER, 0); // unbind the buffer.. looks like there's only a single buffer to interact with passing data at one time
+        if had_tangents && self.mesh.tex_coords.len() == self.mesh.positions.len() {
+            self.mesh.compute_tangents();
+        }
+    }
+
+    fn vertices(&self) -> Vec<Vertex> {
+        let mesh = &self.mesh;
+
+        (0..mesh.positions.len()).map(|i| Vertex {
+            pos: mesh.positions[i].into(),
+            clr: mesh.colors[i].into(),
+            nrm: mesh.normals[i].into(),
+            uv: mesh.tex_coords.get(i).cloned().unwrap_or((0.0, 0.0)).into(),
+            // a zero tangent tells the shader to work one out itself
+            tan: mesh.tangents.get(i).cloned().unwrap_or((0.0, 0.0, 0.0, 0.0)).into(),
+        }).collect()
+    }
+
+    fn upload_vertices(&mut self) {
+        let vertices = self.vertices();
+
+        match self.vertex_storage {
+            VertexStorage::Buffer(ref mut buffer) => buffer.upload(&vertices),
+            VertexStorage::Ring(ref mut ring) => { ring.write(&vertices); },
         }
+    }
 
-        unsafe {
-            self.gl.GenVertexArrays(1, &mut self.vert_array);
-            self.gl.BindVertexArray(self.vert_array);
-            self.gl.BindBuffer(gl::ARRAY_BUFFER, vertex_buffer);
+    fn upload_indices(&mut self) {
+        if self.mesh.indices.is_empty() {
+            return;
         }
 
-        self.set_vertex_attrib_pointers();
+        if self.index_buffer.is_none() {
+            let index_buffer = Buffer::new(&self.device, BufferUsage::Static);
+            self.vert_array.element_buffer(index_buffer.id());
+            self.index_buffer = Some(index_buffer);
+        }
 
-        unsafe {
-            self.gl.BindBuffer(gl::ARRAY_BUFFER, 0);
-            self.gl.BindVertexArray(0);
+        if let Some(ref mut index_buffer) = self.index_buffer {
+            index_buffer.upload(&self.mesh.indices);
         }
     }
 
-    fn set_vertex_attrib_pointers(&self) {
-        let stride = std::mem::size_of::<Vertex>(); // byte offset between consecutive attributes
+    fn gen_buffers(&mut self) {
+        let stride = std::mem::size_of::<Vertex>(); // byte offset between consecutive vertices
+        let attribute_size = std::mem::size_of::<data::f32_f32_f32>();
+
+        let buffer_id = match self.vertex_storage {
+            VertexStorage::Buffer(ref buffer) => buffer.id(),
+            VertexStorage::Ring(ref ring) => ring.id(),
+        };
+
+        self.vert_array.vertex_buffer(VERTEX_BINDING, buffer_id, 0, stride);
 
-        let location = 0; // layout (location = 0)
-        let offset = 0; // offset for first component
+        self.vert_array.attrib_format(0, 3, VERTEX_BINDING, 0); // layout (location = 0), position
+        self.vert_array.attrib_format(1, 3, VERTEX_BINDING, attribute_size); // color
+        self.vert_array.attrib_format(2, 3, VERTEX_BINDING, 2 * attribute_size); // normal
+        self.vert_array.attrib_format(3, 2, VERTEX_BINDING, 3 * attribute_size); // texture coordinates
+        self.vert_array.attrib_format(4, 4, VERTEX_BINDING, 3 * attribute_size + std::mem::size_of::<data::f32_f32>()); // tangent
 
-        unsafe {
-            self.set_vertex_attrib_pointer(stride, location, offset);
+        if let VertexStorage::Buffer(_) = self.vertex_storage {
+            self.upload_vertices();
         }
 
-        let location = 1;
-        let offset = offset + std::mem::size_of::<data::f32_f32_f32>();
+        self.upload_indices();
+    }
+
+    fn gen_instance_buffer(&mut self) {
+        // rewritten often, possibly every frame
+        let instance_buffer = Buffer::new(&self.device, BufferUsage::Stream);
+
+        let stride = std::mem::size_of::<Instance>();
+        let column_size = std::mem::size_of::<data::f32_f32_f32_f32>();
 
-        unsafe {
-            self.set_vertex_attrib_pointer(stride, location, offset);
+        self.vert_array.vertex_buffer(INSTANCE_BINDING, instance_buffer.id(), 0, stride);
+        self.vert_array.binding_divisor(INSTANCE_BINDING, 1); // advance once per instance, not per vertex
+
+        for column in 0..4 {
+            self.vert_array.attrib_format(INSTANCE_MODEL_LOCATION + column, 4, INSTANCE_BINDING, column as usize * column_size);
         }
+
+        self.vert_array.attrib_format(INSTANCE_TINT_LOCATION, 4, INSTANCE_BINDING, 4 * column_size);
+
+        self.instance_buffer = Some(instance_buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_gl::camera::{Camera};
+    use crate::render_gl::device::{Uniform};
+    use crate::render_gl::recording_device::{Call, RecordingDevice};
+
+    fn triangle() -> Mesh {
+        Mesh::new(vec![(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)], vec![(1.0, 1.0, 1.0); 3], vec![])
+    }
+
+    fn object(recorder: &Rc<RecordingDevice>, mesh: Mesh) -> Object {
+        let device: Device = recorder.clone();
+        let program = Program::make(&device, &[]).unwrap();
+
+        Object::from_mesh(&device, Rc::new(Material::new(Rc::new(program))), mesh, BufferUsage::Static)
+    }
+
+    fn without_uniforms(calls: Vec<Call>) -> Vec<Call> {
+        calls.into_iter().filter(|call| match call { Call::SetUniform { .. } => false, _ => true }).collect()
+    }
+
+    #[test]
+    fn creating_sets_up_one_attribute_per_vertex_field() {
+        let recorder = Rc::new(RecordingDevice::new());
+        let obj = object(&recorder, triangle());
+
+        let formats: Vec<(u32, usize)> = recorder.calls().into_iter().filter_map(|call| match call {
+            Call::VertexArrayAttribFormat { vertex_array, location, components, .. } => {
+                assert_eq!(vertex_array, obj.vert_array.id());
+                Some((location, components))
+            },
+            _ => None,
+        }).collect();
+
+        assert_eq!(formats, vec![(0, 3), (1, 3), (2, 3), (3, 2), (4, 4)]);
     }
 
-    unsafe fn set_vertex_attrib_pointer(&self, stride: usize, location: usize, offset: usize) {
-        self.gl.EnableVertexAttribArray(location as gl::types::GLuint);
-        self.gl.VertexAttribPointer(
-            location as gl::types::GLuint,
-            3, // number of components per vertex attribute
-            gl::FLOAT, // data type
-            gl::FALSE, // normalized (int-to-float conversion)
-            stride as gl::types::GLint,
-            offset as *const gl::types::GLvoid
+    #[test]
+    fn first_draw_sets_the_whole_pipeline() {
+        let recorder = Rc::new(RecordingDevice::new());
+        let device: Device = recorder.clone();
+        let obj = object(&recorder, triangle());
+        let mut state = GlState::new(&device);
+        let camera = Camera::make(4, 3, 45.0, 0.1, 100.0);
+
+        recorder.take_calls();
+        camera.draw(&mut state, &obj);
+
+        let calls = recorder.take_calls();
+        let program = obj.material.program.id();
+
+        assert_eq!(calls[0], Call::SetUniform { program, name: "view".into(), value: Uniform::Mat4(camera.matrix) });
+        assert!(calls.contains(&Call::SetUniform { program, name: "model".into(), value: Uniform::Mat4(obj.matrix) }));
+
+        assert_eq!(without_uniforms(calls), vec![
+            Call::SetEnabled { capability: gl::BLEND, enabled: false },
+            Call::SetEnabled { capability: gl::DEPTH_TEST, enabled: true },
+            Call::DepthFunc { func: gl::LESS },
+            Call::DepthMask { write: true },
+            Call::SetEnabled { capability: gl::CULL_FACE, enabled: false },
+            Call::PolygonMode { mode: gl::FILL },
+            Call::SetEnabled { capability: gl::POLYGON_OFFSET_FILL, enabled: false },
+            Call::SetEnabled { capability: gl::POLYGON_OFFSET_LINE, enabled: false },
+            Call::SetEnabled { capability: gl::POLYGON_OFFSET_POINT, enabled: false },
+            Call::SetEnabled { capability: gl::STENCIL_TEST, enabled: false },
+            Call::SetEnabled { capability: gl::SCISSOR_TEST, enabled: false },
+            Call::UseProgram { program },
+            Call::BindVertexArray { vertex_array: obj.vert_array.id() },
+            Call::DrawArrays { mode: gl::TRIANGLES, first: 0, count: 3 },
+        ]);
+    }
+
+    #[test]
+    fn drawing_again_only_sets_uniforms() {
+        let recorder = Rc::new(RecordingDevice::new());
+        let device: Device = recorder.clone();
+        let obj = object(&recorder, triangle());
+        let mut state = GlState::new(&device);
+
+        obj.draw(&mut state);
+        recorder.take_calls();
+        obj.draw(&mut state);
+
+        assert_eq!(without_uniforms(recorder.take_calls()), vec![
+            Call::DrawArrays { mode: gl::TRIANGLES, first: 0, count: 3 },
+        ]);
+    }
+
+    #[test]
+    fn indexed_instances_are_drawn_in_one_call() {
+        let recorder = Rc::new(RecordingDevice::new());
+        let device: Device = recorder.clone();
+        let quad = Mesh::new(
+            vec![(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 1.0, 0.0), (0.0, 1.0, 0.0)],
+            vec![(1.0, 1.0, 1.0); 4],
+            vec![0, 1, 2, 0, 2, 3],
         );
+        let mut obj = object(&recorder, quad);
+        let mut state = GlState::new(&device);
+
+        obj.set_instances(&[Instance::new(&glm::identity(), &glm::vec4(1.0, 1.0, 1.0, 1.0)); 5]);
+        recorder.take_calls();
+        obj.draw(&mut state);
+
+        let draws: Vec<Call> = recorder.take_calls().into_iter().filter(|call| match call {
+            Call::DrawArrays { .. } | Call::DrawArraysInstanced { .. } |
+            Call::DrawElementsBaseVertex { .. } | Call::DrawElementsInstancedBaseVertex { .. } => true,
+            _ => false,
+        }).collect();
+
+        assert_eq!(draws, vec![Call::DrawElementsInstancedBaseVertex { mode: gl::TRIANGLES, count: 6, instances: 5, base_vertex: 0 }]);
+    }
+
+    #[test]
+    fn instances_keep_their_model_matrices() {
+        let recorder = Rc::new(RecordingDevice::new());
+        let mut obj = object(&recorder, triangle());
+        let model = glm::rotate_y(&glm::translate(&glm::identity(), &glm::vec3(1.0, 2.0, 3.0)), 0.5);
+
+        assert_eq!(obj.instance_models(), None);
+
+        obj.set_instances(&[Instance::new(&model, &glm::vec4(1.0, 1.0, 1.0, 1.0))]);
+
+        assert_eq!(obj.instance_models(), Some(&[model][..]));
+    }
+
+    #[test]
+    #[should_panic(expected = "one color per updated vertex")]
+    fn updating_vertices_needs_a_color_each() {
+        let recorder = Rc::new(RecordingDevice::new());
+        let mut obj = object(&recorder, triangle());
+
+        obj.update_vertices(0, &[(0.0, 0.0, 1.0), (1.0, 0.0, 1.0)], &[(255.0, 0.0, 0.0)]);
+    }
+
+    #[test]
+    fn no_instances_draw_nothing_until_cleared() {
+        let recorder = Rc::new(RecordingDevice::new());
+        let device: Device = recorder.clone();
+        let mut obj = object(&recorder, triangle());
+        let mut state = GlState::new(&device);
+
+        let draws = |calls: Vec<Call>| -> Vec<Call> {
+            calls.into_iter().filter(|call| match call {
+                Call::DrawArrays { .. } | Call::DrawArraysInstanced { .. } => true,
+                _ => false,
+            }).collect()
+        };
+
+        obj.set_instances(&[]);
+        recorder.take_calls();
+        obj.draw(&mut state);
+
+        assert_eq!(draws(recorder.take_calls()), vec![]);
+
+        obj.clear_instances();
+        obj.draw(&mut state);
+
+        assert_eq!(draws(recorder.take_calls()), vec![Call::DrawArrays { mode: gl::TRIANGLES, first: 0, count: 3 }]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn updates_keep_the_shading_and_tangents() {
+        let recorder = Rc::new(RecordingDevice::new());
+        let mut mesh = crate::generators::primitives::cube(2.0, 2);
+        mesh.shade(Shading::Smooth);
+        mesh.compute_tangents();
+
+        let mut obj = object(&recorder, mesh.clone());
+        obj.shading = Some(Shading::Smooth);
+
+        // raise one corner and shade the expected mesh the same way
+        let moved = (1.0, 1.5, 1.0);
+        let corner = mesh.positions.iter().position(|&p| p == (1.0, 1.0, 1.0)).unwrap();
+        mesh.positions[corner] = moved;
+        mesh.shade(Shading::Smooth);
+        mesh.compute_tangents();
+
+        obj.update_vertices(corner, &[moved], &[(255.0, 255.0, 255.0)]);
+
+        assert_eq!(obj.mesh().normals, mesh.normals);
+        assert_eq!(obj.mesh().tangents, mesh.tangents);
+
+        // without texture coordinates for every new vertex the tangents can't be kept
+        let mut positions = mesh.positions.clone();
+        positions.push((0.0, 0.0, 0.0));
+        obj.replace_vertices(&positions, &vec![(255.0, 255.0, 255.0); positions.len()]);
+
+        assert!(obj.mesh().tangents.is_empty());
+    }
+}
@@ -0,0 +1,356 @@
+extern crate nalgebra_glm as glm;
+
+use gl;
+use crate::resources::{self, Resources};
+use crate::render_gl::device::{Device};
+use crate::render_gl::framebuffer::{self, Framebuffer};
+use crate::render_gl::render_state::{RenderState};
+use crate::render_gl::render_target::{RenderTarget};
+use crate::render_gl::shader::{self, Program, Shader};
+use crate::render_gl::state::{GlState};
+use crate::render_gl::texture::{Texture};
+use crate::render_gl::vertex_array::{VertexArray};
+
+// `layout (binding = ..)` of the samplers in the pass shaders
+const INPUT_UNIT: u32 = 0;
+const LUT_UNIT: u32 = 1;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to load resource {}", name)]
+    ResourceLoad { name: String, #[cause] inner: resources::Error },
+    #[fail(display = "{}, line {}: {}", name, line, message)]
+    Parse { name: String, line: usize, message: String },
+    #[fail(display = "Failed to build post-processing pass {}", name)]
+    Shader { name: String, #[cause] inner: shader::Error },
+    #[fail(display = "Failed to create post-processing buffers")]
+    Framebuffer(#[cause] framebuffer::Error),
+}
+
+impl From<framebuffer::Error> for Error {
+    fn from(other: framebuffer::Error) -> Self {
+        Error::Framebuffer(other)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Param {
+    /// a float or vector uniform, 1 to 4 components
+    Value(Vec<f32>),
+    /// resource name of a `.cube` color lookup table for the `lut` sampler
+    Lut(String),
+}
+
+/// One line of a chain file: the pass shader and the values it gets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PassDesc {
+    pub shader: String,
+    pub params: Vec<(String, Param)>,
+}
+
+/// Parses a chain file, one pass per line as `shader name=value ...`. Values are numbers, comma
+/// separated vectors, or a `.cube` resource for `lut`. Blank lines and `#` comments are skipped.
+pub fn parse_chain(name: &str, source: &str) -> Result<Vec<PassDesc>, Error> {
+    let lines = resources::parse_lines(source)
+        .map_err(|(line, message)| Error::Parse { name: name.into(), line, message })?;
+
+    lines.into_iter().map(|line| {
+        let error = |message: String| Error::Parse { name: name.into(), line: line.number, message };
+
+        let params = line.values.iter().map(|&(key, value)| {
+            let param = if value.ends_with(".cube") {
+                Param::Lut(value.to_string())
+            } else {
+                let components = resources::parse_numbers(value).map_err(error)?;
+
+                if components.len() > 4 {
+                    return Err(error(format!("{} has more than 4 components", key)));
+                }
+
+                Param::Value(components)
+            };
+
+            Ok((key.to_string(), param))
+        }).collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(PassDesc { shader: line.rule.to_string(), params })
+    }).collect()
+}
+
+/// Parses an Adobe `.cube` 3D lookup table into its size and rgb values, red changing fastest.
+pub fn parse_cube(name: &str, source: &str) -> Result<(u32, Vec<f32>), Error> {
+    let mut size = None;
+    let mut values = vec![];
+
+    for (i, line) in source.lines().enumerate() {
+        let error = |message: String| Error::Parse { name: name.into(), line: i + 1, message };
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("LUT_3D_SIZE") => {
+                size = words.next().and_then(|s| s.parse::<u32>().ok());
+                match size {
+                    None => return Err(error("LUT_3D_SIZE needs a whole number".into())),
+                    // a table needs both ends of every axis
+                    Some(n) if n < 2 => return Err(error(format!("LUT_3D_SIZE {} is below 2", n))),
+                    _ => {},
+                }
+            },
+            // keywords this loader doesn't need; the domain is assumed to be 0..1
+            Some("TITLE") | Some("DOMAIN_MIN") | Some("DOMAIN_MAX") => {},
+            Some(_) => {
+                let rgb = line.split_whitespace()
+                    .map(|v| v.parse::<f32>())
+                    .collect::<Result<Vec<f32>, _>>()
+                    .map_err(|_| error(format!("unexpected {}", line)))?;
+
+                if rgb.len() != 3 {
+                    return Err(error(format!("expected r g b, found {}", line)));
+                }
+
+                values.extend(rgb);
+            },
+            None => {},
+        }
+    }
+
+    let size = size.ok_or_else(|| Error::Parse { name: name.into(), line: 0, message: "missing LUT_3D_SIZE".into() })?;
+
+    // a huge size would overflow, and there could never be that many entries anyway
+    let entries = (size as usize).checked_mul(size as usize)
+        .and_then(|n| n.checked_mul(size as usize))
+        .filter(|n| n.checked_mul(3).is_some())
+        .ok_or_else(|| Error::Parse { name: name.into(), line: 0, message: format!("LUT_3D_SIZE {} is too large", size) })?;
+
+    if values.len() != entries * 3 {
+        return Err(Error::Parse {
+            name: name.into(),
+            line: 0,
+            message: format!("expected {} entries, found {}", entries, values.len() / 3),
+        });
+    }
+
+    Ok((size, values))
+}
+
+struct Pass {
+    program: Program,
+    uniforms: Vec<(String, Vec<f32>)>,
+    lut: Option<Texture>,
+}
+
+/// Fullscreen passes applied to the rendered scene on its way to the window, declared in a chain
+/// file (see `parse_chain`) with one fragment shader in `shaders/post` per pass. Passes read the
+/// previous result from the `input_image` sampler and alternate between two buffers; the last one
+/// draws into the window.
+pub struct PostProcess {
+    chain: String,
+    passes: Vec<Pass>,
+    targets: [RenderTarget; 2],
+    // fullscreen triangles are generated from gl_VertexID, but drawing needs some vertex array
+    vert_array: VertexArray,
+    gl: gl::Gl,
+    device: Device,
+}
+
+impl PostProcess {
+    /// Loads the chain file resource `chain`, with buffers of the window's size.
+    pub fn new(gl: &gl::Gl, device: &Device, res: &Resources, chain: &str, width: u32, height: u32) -> Result<PostProcess, Error> {
+        let target = || RenderTarget::new(gl, width, height, &[gl::RGBA16F], None, 0);
+
+        Ok(PostProcess {
+            chain: chain.into(),
+            passes: load_passes(gl, device, res, chain)?,
+            targets: [target()?, target()?],
+            vert_array: VertexArray::new(device),
+            gl: gl.clone(),
+            device: device.clone(),
+        })
+    }
+
+    /// Reads the chain file and its shaders again. On failure the passes loaded before stay in
+    /// use. The old programs are deleted, so a `GlState` in use needs a `reset` afterwards.
+    pub fn reload(&mut self, res: &Resources) -> Result<(), Error> {
+        self.passes = load_passes(&self.gl, &self.device, res, &self.chain)?;
+        Ok(())
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), Error> {
+        for target in self.targets.iter_mut() {
+            target.resize(width, height)?;
+        }
+        Ok(())
+    }
+
+    /// Runs every pass over the resolved `scene` and leaves the result in the window's
+    /// framebuffer, which is left bound.
+    #[allow(dead_code)]
+    pub fn run(&self, state: &mut GlState, scene: &RenderTarget, window_size: (u32, u32)) {
+        self.run_into(state, scene, None, window_size);
+    }
+
+    /// Like `run`, with the result going into the first color attachment of `output` instead
+    /// of the window when given, e.g. when there is no window.
+    pub fn run_into(&self, state: &mut GlState, scene: &RenderTarget, output: Option<&RenderTarget>, size: (u32, u32)) {
+        let output_framebuffer = output.map(|target| target.framebuffer().id()).unwrap_or(0);
+
+        if self.passes.is_empty() {
+            Framebuffer::bind_default(&self.gl);
+            scene.blit_to(0, output_framebuffer, size.0, size.1);
+            return;
+        }
+
+        let render_state = RenderState { depth: None, ..RenderState::opaque() };
+        let last = self.passes.len() - 1;
+
+        for (i, pass) in self.passes.iter().enumerate() {
+            let input = if i == 0 { scene.color(0) } else { self.targets[(i - 1) % 2].color(0) };
+
+            if i == last {
+                match output {
+                    Some(target) => target.bind(state),
+                    None => {
+                        Framebuffer::bind_default(&self.gl);
+                        state.viewport(0, 0, size.0 as i32, size.1 as i32);
+                    },
+                }
+            } else {
+                self.targets[i % 2].bind(state);
+            }
+
+            state.bind_texture_unit(INPUT_UNIT, input.id());
+            if let Some(ref lut) = pass.lut {
+                state.bind_texture_unit(LUT_UNIT, lut.id());
+            }
+
+            let program = &pass.program;
+            program.set_vec2("texel_size", &glm::vec2(1.0 / input.width() as f32, 1.0 / input.height() as f32));
+
+            for (name, value) in pass.uniforms.iter() {
+                match value.len() {
+                    1 => program.set_float(name, value[0]),
+                    2 => program.set_vec2(name, &glm::vec2(value[0], value[1])),
+                    3 => program.set_vec3(name, &glm::vec3(value[0], value[1], value[2])),
+                    _ => program.set_vec4(name, &glm::vec4(value[0], value[1], value[2], value[3])),
+                }
+            }
+
+            render_state.apply(state);
+            state.use_program(program.id());
+            state.bind_vertex_array(self.vert_array.id());
+
+            self.device.draw_arrays(gl::TRIANGLES, 0, 3);
+        }
+    }
+}
+
+fn load_passes(gl: &gl::Gl, device: &Device, res: &Resources, chain: &str) -> Result<Vec<Pass>, Error> {
+    let load = |name: &str| res.load_string(name).map_err(|e| Error::ResourceLoad { name: name.into(), inner: e });
+
+    parse_chain(chain, &load(chain)?)?.into_iter().map(|desc| {
+        let shader_error = |e: shader::Error| Error::Shader { name: desc.shader.clone(), inner: e };
+
+        let vertex = Shader::from_res(device, res, "shaders/post/fullscreen.vert").map_err(shader_error)?;
+        let fragment = Shader::from_res(device, res, &format!("shaders/post/{}.frag", desc.shader)).map_err(shader_error)?;
+        let program = Program::make(device, &[vertex, fragment])
+            .map_err(|message| shader_error(shader::Error::LinkError { name: desc.shader.clone(), message }))?;
+
+        let mut uniforms = vec![];
+        let mut lut = None;
+
+        for (name, param) in desc.params.iter() {
+            match param {
+                Param::Value(value) => uniforms.push((name.clone(), value.clone())),
+                Param::Lut(resource) => {
+                    let (size, values) = parse_cube(resource, &load(resource)?)?;
+                    lut = Some(Texture::new_3d(gl, gl::RGB32F, (size, size, size), gl::RGB, gl::FLOAT, &values));
+                    uniforms.push(("lut_size".into(), vec![size as f32]));
+                },
+            }
+        }
+
+        Ok(Pass { program, uniforms, lut })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(error: Error) -> (usize, String) {
+        match error {
+            Error::Parse { line, message, .. } => (line, message),
+            other => panic!("expected a parse error, got {}", other),
+        }
+    }
+
+    #[test]
+    fn chains_skip_comments_and_blank_lines() {
+        let passes = parse_chain("test.chain", "# tone mapping first\n\ntonemap exposure=1.5 # brighter\n  fxaa\n").unwrap();
+
+        assert_eq!(passes, vec![
+            PassDesc { shader: "tonemap".into(), params: vec![("exposure".into(), Param::Value(vec![1.5]))] },
+            PassDesc { shader: "fxaa".into(), params: vec![] },
+        ]);
+    }
+
+    #[test]
+    fn chains_take_vectors_and_luts() {
+        let passes = parse_chain("test.chain", "grade tint=1,0.5,0.25 lut=luts/warm.cube").unwrap();
+
+        assert_eq!(passes[0].params, vec![
+            ("tint".into(), Param::Value(vec![1.0, 0.5, 0.25])),
+            ("lut".into(), Param::Lut("luts/warm.cube".into())),
+        ]);
+    }
+
+    #[test]
+    fn chains_reject_bad_params() {
+        let cases = [
+            ("vignette\nvignette strength", 2, "expected name=value, found strength"),
+            ("vignette strength=", 1, "expected name=value, found strength="),
+            ("vignette strength=lots", 1, "lots is not a number or vector"),
+            ("vignette strength=nan", 1, "nan is not a number or vector"),
+            ("grade tint=1,2,3,4,5", 1, "tint has more than 4 components"),
+        ];
+
+        for &(source, line, expected) in cases.iter() {
+            assert_eq!(message(parse_chain("test.chain", source).unwrap_err()), (line, expected.to_string()));
+        }
+    }
+
+    #[test]
+    fn cubes_read_their_header_and_entries() {
+        let source = "TITLE \"identity\"\n# comment\nLUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 1 1 1\n\
+            0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n";
+
+        let (size, values) = parse_cube("test.cube", source).unwrap();
+
+        assert_eq!(size, 2);
+        assert_eq!(values.len(), 24);
+        assert_eq!(&values[3..6], &[1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn cubes_reject_bad_lines_and_sizes() {
+        let cases = [
+            ("LUT_3D_SIZE two\n", 1, "LUT_3D_SIZE needs a whole number"),
+            ("LUT_3D_SIZE 0\n", 1, "LUT_3D_SIZE 0 is below 2"),
+            ("# one entry\nLUT_3D_SIZE 1\n0 0 0\n", 2, "LUT_3D_SIZE 1 is below 2"),
+            ("LUT_3D_SIZE 2\n0 0 zero\n", 2, "unexpected 0 0 zero"),
+            ("LUT_3D_SIZE 2\n0 0\n", 2, "expected r g b, found 0 0"),
+            ("0 0 0\n", 0, "missing LUT_3D_SIZE"),
+            ("LUT_3D_SIZE 2\n0 0 0\n", 0, "expected 8 entries, found 1"),
+            ("LUT_3D_SIZE 4000000000\n0 0 0\n", 0, "LUT_3D_SIZE 4000000000 is too large"),
+        ];
+
+        for &(source, line, expected) in cases.iter() {
+            assert_eq!(message(parse_cube("test.cube", source).unwrap_err()), (line, expected.to_string()));
+        }
+    }
+}
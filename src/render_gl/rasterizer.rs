@@ -0,0 +1,366 @@
+extern crate nalgebra_glm as glm;
+
+use gl;
+use crate::render_gl::camera::{Camera};
+use crate::render_gl::mesh::{Mesh};
+use crate::render_gl::object::{Object};
+use crate::render_gl::render_state::{RenderState};
+
+/// Draws meshes on the CPU, without any GL driver, into an RGBA8 image. Follows GL's rules where
+/// it matters for comparing images: clipping against the view volume, pixel centers at .5,
+/// top-left fill convention, depth in 0..1 and perspective-correct interpolation.
+///
+/// Only vertex colors are drawn, unlit. Depth testing and face culling come from the render
+/// state, everything else in it is ignored, as are materials and instances. The result is the
+/// same on every machine, so images can be compared byte for byte.
+#[allow(dead_code)]
+pub struct Rasterizer {
+    width: u32,
+    height: u32,
+    // top row first, like `screenshot::read_pixels`
+    pixels: Vec<u8>,
+    depth: Vec<f32>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct ClipVertex {
+    position: glm::Vec4,
+    color: glm::Vec3,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct WindowVertex {
+    x: f32,
+    y: f32,
+    z: f32,
+    // 1/w, interpolated linearly across the screen for perspective correction
+    inv_w: f32,
+    color_over_w: glm::Vec3,
+}
+
+#[allow(dead_code)]
+impl Rasterizer {
+    /// Image cleared to transparent black, with depth at 1.0.
+    pub fn new(width: u32, height: u32) -> Rasterizer {
+        let size = (width * height) as usize;
+
+        Rasterizer {
+            width,
+            height,
+            pixels: vec![0; size * 4],
+            depth: vec![1.0; size],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// RGBA8 pixels, top row first, ready for `screenshot::save_png`.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// RGBA8 color at (x, y), with y = 0 the top row.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * self.width + x) * 4) as usize;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    /// Sets every pixel to `color` and the depth to 1.0.
+    pub fn clear(&mut self, color: (f32, f32, f32, f32)) {
+        let rgba = [to_byte(color.0), to_byte(color.1), to_byte(color.2), to_byte(color.3)];
+
+        for pixel in self.pixels.chunks_mut(4) {
+            pixel.copy_from_slice(&rgba);
+        }
+        for depth in self.depth.iter_mut() {
+            *depth = 1.0;
+        }
+    }
+
+    /// Draws `obj` as `Camera::draw` would, with its model matrix and render state.
+    pub fn draw(&mut self, camera: &Camera, obj: &Object) {
+        self.draw_mesh(obj.mesh(), &obj.matrix, &camera.matrix, camera.projection(), &obj.render_state);
+    }
+
+    pub fn draw_mesh(
+        &mut self,
+        mesh: &Mesh,
+        model: &glm::Mat4,
+        view: &glm::Mat4,
+        projection: &glm::Mat4,
+        render_state: &RenderState,
+    ) {
+        let transform = projection * view * model;
+
+        for triangle in mesh.triangle_indices() {
+            let corners: Vec<ClipVertex> = triangle.iter().map(|&i| {
+                let position = mesh.position(i);
+                let color = mesh.colors.get(i).map(|&(r, g, b)| glm::vec3(r, g, b)).unwrap_or(glm::vec3(1.0, 1.0, 1.0));
+
+                ClipVertex { position: transform * glm::vec4(position.x, position.y, position.z, 1.0), color }
+            }).collect();
+
+            let polygon = clip_polygon(corners);
+
+            // the clipped polygon is convex, so a fan around its first corner covers it
+            for i in 1..polygon.len().saturating_sub(1) {
+                let window = [
+                    self.to_window(&polygon[0]),
+                    self.to_window(&polygon[i]),
+                    self.to_window(&polygon[i + 1]),
+                ];
+                self.fill_triangle(&window, render_state);
+            }
+        }
+    }
+
+    fn to_window(&self, vertex: &ClipVertex) -> WindowVertex {
+        let inv_w = 1.0 / vertex.position.w;
+        let ndc = vertex.position.xyz() * inv_w;
+
+        WindowVertex {
+            x: (ndc.x * 0.5 + 0.5) * self.width as f32,
+            y: (ndc.y * 0.5 + 0.5) * self.height as f32,
+            z: ndc.z * 0.5 + 0.5,
+            inv_w,
+            color_over_w: vertex.color * inv_w,
+        }
+    }
+
+    fn fill_triangle(&mut self, v: &[WindowVertex; 3], render_state: &RenderState) {
+        // window y points up like in GL, so counter-clockwise triangles have a positive area
+        let area = edge(&v[0], &v[1], v[2].x, v[2].y);
+        if area == 0.0 {
+            return;
+        }
+
+        if let Some(cull) = render_state.cull {
+            let front = (area > 0.0) == (cull.front == gl::CCW);
+            let culled = match cull.face {
+                gl::FRONT => front,
+                gl::BACK => !front,
+                _ => true,
+            };
+            if culled {
+                return;
+            }
+        }
+
+        // walk the edges counter-clockwise so the inside is always to their left
+        let v = if area > 0.0 { [v[0], v[1], v[2]] } else { [v[0], v[2], v[1]] };
+        let area = area.abs();
+
+        let min_x = v.iter().map(|p| p.x).fold(std::f32::MAX, f32::min).floor().max(0.0) as u32;
+        let max_x = v.iter().map(|p| p.x).fold(std::f32::MIN, f32::max).ceil().min(self.width as f32) as u32;
+        let min_y = v.iter().map(|p| p.y).fold(std::f32::MAX, f32::min).floor().max(0.0) as u32;
+        let max_y = v.iter().map(|p| p.y).fold(std::f32::MIN, f32::max).ceil().min(self.height as f32) as u32;
+
+        let edges = [(1, 2), (2, 0), (0, 1)];
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+
+                // each weight is the area opposite its corner, so it belongs to that corner
+                let mut weights = [0.0; 3];
+                let mut inside = true;
+
+                for (corner, &(a, b)) in edges.iter().enumerate() {
+                    let e = edge(&v[a], &v[b], px, py);
+                    inside &= e > 0.0 || (e == 0.0 && is_top_left(&v[a], &v[b]));
+                    weights[corner] = e / area;
+                }
+
+                if inside {
+                    self.shade(x, y, &v, &weights, render_state);
+                }
+            }
+        }
+    }
+
+    fn shade(&mut self, x: u32, y: u32, v: &[WindowVertex; 3], weights: &[f32; 3], render_state: &RenderState) {
+        let index = ((self.height - 1 - y) * self.width + x) as usize;
+
+        // depth is linear in window space, other attributes only after dividing by w
+        let z = weights[0] * v[0].z + weights[1] * v[1].z + weights[2] * v[2].z;
+
+        if let Some(depth) = render_state.depth {
+            if !depth_test(depth.func, z, self.depth[index]) {
+                return;
+            }
+            if depth.write {
+                self.depth[index] = z;
+            }
+        }
+
+        let inv_w = weights[0] * v[0].inv_w + weights[1] * v[1].inv_w + weights[2] * v[2].inv_w;
+        let color = (v[0].color_over_w * weights[0] + v[1].color_over_w * weights[1] + v[2].color_over_w * weights[2]) / inv_w;
+
+        self.pixels[index * 4..index * 4 + 4]
+            .copy_from_slice(&[to_byte(color.x), to_byte(color.y), to_byte(color.z), 255]);
+    }
+}
+
+/// Twice the signed area of (a, b, p), positive when p is left of the line from a to b.
+fn edge(a: &WindowVertex, b: &WindowVertex, px: f32, py: f32) -> f32 {
+    (b.x - a.x) * (py - a.y) - (b.y - a.y) * (px - a.x)
+}
+
+/// Whether a counter-clockwise edge is a top or left one; pixel centers exactly on those count
+/// as inside, so triangles sharing an edge never both draw a pixel.
+fn is_top_left(a: &WindowVertex, b: &WindowVertex) -> bool {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    dy < 0.0 || (dy == 0.0 && dx < 0.0)
+}
+
+fn depth_test(func: gl::types::GLenum, z: f32, stored: f32) -> bool {
+    match func {
+        gl::NEVER => false,
+        gl::LESS => z < stored,
+        gl::EQUAL => z == stored,
+        gl::LEQUAL => z <= stored,
+        gl::GREATER => z > stored,
+        gl::NOTEQUAL => z != stored,
+        gl::GEQUAL => z >= stored,
+        _ => true,
+    }
+}
+
+fn to_byte(value: f32) -> u8 {
+    (value.max(0.0).min(1.0) * 255.0).round() as u8
+}
+
+/// Cuts a triangle in clip space down to the part inside -w <= x, y, z <= w, giving a convex
+/// polygon in the same winding, or nothing.
+fn clip_polygon(polygon: Vec<ClipVertex>) -> Vec<ClipVertex> {
+    // distance to each plane, at least 0 inside
+    let planes: [fn(&glm::Vec4) -> f32; 6] = [
+        |p| p.w + p.x,
+        |p| p.w - p.x,
+        |p| p.w + p.y,
+        |p| p.w - p.y,
+        |p| p.w + p.z,
+        |p| p.w - p.z,
+    ];
+
+    planes.iter().fold(polygon, |polygon, distance| {
+        let mut clipped = vec![];
+
+        for i in 0..polygon.len() {
+            let (a, b) = (&polygon[i], &polygon[(i + 1) % polygon.len()]);
+            let (da, db) = (distance(&a.position), distance(&b.position));
+
+            if da >= 0.0 {
+                clipped.push(*a);
+            }
+            if (da >= 0.0) != (db >= 0.0) {
+                // clip space is still linear, so plain interpolation is right here
+                let t = da / (da - db);
+                clipped.push(ClipVertex {
+                    position: glm::lerp(&a.position, &b.position, t),
+                    color: glm::lerp(&a.color, &b.color, t),
+                });
+            }
+        }
+
+        clipped
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_gl::render_state::{Cull};
+
+    fn mesh(positions: Vec<(f32, f32, f32)>, colors: Vec<(f32, f32, f32)>) -> Mesh {
+        Mesh::new(positions, colors, vec![])
+    }
+
+    fn draw(rasterizer: &mut Rasterizer, mesh: &Mesh, projection: &glm::Mat4, render_state: &RenderState) {
+        let identity = glm::identity();
+        rasterizer.draw_mesh(mesh, &identity, &identity, projection, render_state);
+    }
+
+    #[test]
+    fn quad_leaves_no_gaps() {
+        // two triangles sharing a diagonal that runs exactly through the pixel centers, which
+        // the fill convention has to give to one of them
+        let quad = mesh(
+            vec![(-1.0, -1.0, 0.0), (1.0, -1.0, 0.0), (1.0, 1.0, 0.0), (-1.0, -1.0, 0.0), (1.0, 1.0, 0.0), (-1.0, 1.0, 0.0)],
+            vec![(1.0, 0.0, 0.0); 6],
+        );
+
+        let mut rasterizer = Rasterizer::new(4, 4);
+        let render_state = RenderState { depth: None, ..RenderState::opaque() };
+        draw(&mut rasterizer, &quad, &glm::identity(), &render_state);
+
+        for pixel in rasterizer.pixels().chunks(4) {
+            assert_eq!(pixel, &[255, 0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn nearer_triangle_wins_regardless_of_order() {
+        let far = mesh(vec![(-1.0, -1.0, 0.5), (3.0, -1.0, 0.5), (-1.0, 3.0, 0.5)], vec![(0.0, 0.0, 1.0); 3]);
+        let near = mesh(vec![(-1.0, -1.0, -0.5), (3.0, -1.0, -0.5), (-1.0, 3.0, -0.5)], vec![(0.0, 1.0, 0.0); 3]);
+
+        let mut rasterizer = Rasterizer::new(4, 4);
+        draw(&mut rasterizer, &near, &glm::identity(), &RenderState::opaque());
+        draw(&mut rasterizer, &far, &glm::identity(), &RenderState::opaque());
+
+        assert_eq!(rasterizer.pixel(1, 1), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn back_faces_are_culled() {
+        let clockwise = mesh(vec![(-1.0, -1.0, 0.0), (-1.0, 3.0, 0.0), (3.0, -1.0, 0.0)], vec![(1.0, 1.0, 1.0); 3]);
+
+        let mut rasterizer = Rasterizer::new(4, 4);
+        let render_state = RenderState { cull: Some(Cull { face: gl::BACK, front: gl::CCW }), ..RenderState::opaque() };
+        draw(&mut rasterizer, &clockwise, &glm::identity(), &render_state);
+
+        assert!(rasterizer.pixels().iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn triangle_crossing_the_near_plane_is_clipped() {
+        // one corner behind the camera, which without clipping would flip to the other side
+        let projection = glm::perspective(1.0, glm::half_pi(), 0.1, 10.0);
+        let triangle = mesh(vec![(-1.0, -1.0, -2.0), (1.0, -1.0, -2.0), (0.0, -1.0, 5.0)], vec![(1.0, 1.0, 1.0); 3]);
+
+        let mut rasterizer = Rasterizer::new(16, 16);
+        draw(&mut rasterizer, &triangle, &projection, &RenderState::opaque());
+
+        // everything visible lies below the horizon, the top half stays empty
+        for y in 0..8 {
+            for x in 0..16 {
+                assert_eq!(rasterizer.pixel(x, y), [0, 0, 0, 0]);
+            }
+        }
+        assert_eq!(rasterizer.pixel(8, 15), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn colors_are_interpolated_perspective_correctly() {
+        // a floor going into the distance, black at the near edge and white at the far one
+        let projection = glm::perspective(1.0, glm::half_pi(), 0.1, 100.0);
+        let floor = mesh(
+            vec![(-50.0, -1.0, -1.0), (50.0, -1.0, -1.0), (50.0, -1.0, -101.0), (-50.0, -1.0, -1.0), (50.0, -1.0, -101.0), (-50.0, -1.0, -101.0)],
+            vec![(0.0, 0.0, 0.0), (0.0, 0.0, 0.0), (1.0, 1.0, 1.0), (0.0, 0.0, 0.0), (1.0, 1.0, 1.0), (1.0, 1.0, 1.0)],
+        );
+
+        let mut rasterizer = Rasterizer::new(64, 64);
+        draw(&mut rasterizer, &floor, &projection, &RenderState::opaque());
+
+        // halfway down the lower half of the screen is 2 units away, 1% of the way to the far
+        // edge; screen space interpolation would have given about half the way instead
+        let value = rasterizer.pixel(32, 48)[0];
+        assert!(value <= 8, "{}", value);
+    }
+}
@@ -0,0 +1,294 @@
+use gl;
+use std::cell::{Cell, RefCell};
+use std::ffi::{CStr};
+use crate::render_gl::device::{RenderDevice, Uniform};
+
+/// One command issued to a `RecordingDevice`, named after the GL call it stands for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Call {
+    CreateBuffer { buffer: gl::types::GLuint },
+    BufferData { buffer: gl::types::GLuint, data: Vec<u8>, usage: gl::types::GLenum },
+    BufferSubData { buffer: gl::types::GLuint, offset: usize, data: Vec<u8> },
+    BindBufferBase { target: gl::types::GLenum, index: u32, buffer: gl::types::GLuint },
+    CreateMappedBuffer { buffer: gl::types::GLuint, size: usize },
+    UnmapBuffer { buffer: gl::types::GLuint },
+    DeleteBuffer { buffer: gl::types::GLuint },
+    FenceSync,
+    WaitSync,
+    DeleteSync,
+    CreateVertexArray { vertex_array: gl::types::GLuint },
+    VertexArrayVertexBuffer { vertex_array: gl::types::GLuint, binding: u32, buffer: gl::types::GLuint, offset: usize, stride: usize },
+    VertexArrayElementBuffer { vertex_array: gl::types::GLuint, buffer: gl::types::GLuint },
+    VertexArrayAttribFormat { vertex_array: gl::types::GLuint, location: u32, components: usize, binding: u32, relative_offset: usize },
+    VertexArrayBindingDivisor { vertex_array: gl::types::GLuint, binding: u32, divisor: u32 },
+    DeleteVertexArray { vertex_array: gl::types::GLuint },
+    CompileShader { shader: gl::types::GLuint, kind: gl::types::GLenum },
+    DeleteShader { shader: gl::types::GLuint },
+    LinkProgram { program: gl::types::GLuint, shaders: Vec<gl::types::GLuint> },
+    DeleteProgram { program: gl::types::GLuint },
+    SetUniform { program: gl::types::GLuint, name: String, value: Uniform },
+    UseProgram { program: gl::types::GLuint },
+    BindVertexArray { vertex_array: gl::types::GLuint },
+    BindTextureUnit { unit: u32, texture: gl::types::GLuint },
+    SetEnabled { capability: gl::types::GLenum, enabled: bool },
+    BlendEquation { equation: gl::types::GLenum },
+    BlendFunc { source: gl::types::GLenum, destination: gl::types::GLenum },
+    DepthFunc { func: gl::types::GLenum },
+    DepthMask { write: bool },
+    CullFace { face: gl::types::GLenum },
+    FrontFace { winding: gl::types::GLenum },
+    PolygonMode { mode: gl::types::GLenum },
+    PolygonOffset { factor: f32, units: f32 },
+    StencilFunc { func: gl::types::GLenum, reference: i32, mask: u32 },
+    StencilOp { stencil_fail: gl::types::GLenum, depth_fail: gl::types::GLenum, pass: gl::types::GLenum },
+    StencilMask { mask: u32 },
+    Scissor { x: i32, y: i32, width: i32, height: i32 },
+    Viewport { x: i32, y: i32, width: i32, height: i32 },
+    ActiveTexture { unit: u32 },
+    DrawArrays { mode: gl::types::GLenum, first: i32, count: i32 },
+    DrawArraysInstanced { mode: gl::types::GLenum, first: i32, count: i32, instances: i32 },
+    DrawElementsBaseVertex { mode: gl::types::GLenum, count: i32, base_vertex: i32 },
+    DrawElementsInstancedBaseVertex { mode: gl::types::GLenum, count: i32, instances: i32, base_vertex: i32 },
+}
+
+/// Device that talks to no driver and keeps a log of every command instead. Ids are handed out
+/// counting up from 1, shaders always compile and programs always link, mapped buffers are plain
+/// memory, fences are null and queries answer with zeros. Queries aren't logged.
+#[allow(dead_code)]
+pub struct RecordingDevice {
+    calls: RefCell<Vec<Call>>,
+    next_id: Cell<gl::types::GLuint>,
+    // backing memory of mapped buffers, boxed so their addresses stay put
+    mapped: RefCell<Vec<Box<[u8]>>>,
+}
+
+#[allow(dead_code)]
+impl RecordingDevice {
+    pub fn new() -> RecordingDevice {
+        RecordingDevice {
+            calls: RefCell::new(vec![]),
+            next_id: Cell::new(1),
+            mapped: RefCell::new(vec![]),
+        }
+    }
+
+    /// Everything issued so far.
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.borrow().clone()
+    }
+
+    /// Everything issued since the last `take_calls`, e.g. to look at one frame at a time.
+    pub fn take_calls(&self) -> Vec<Call> {
+        self.calls.replace(vec![])
+    }
+
+    fn record(&self, call: Call) {
+        self.calls.borrow_mut().push(call);
+    }
+
+    fn new_id(&self) -> gl::types::GLuint {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        id
+    }
+}
+
+impl RenderDevice for RecordingDevice {
+    fn create_buffer(&self) -> gl::types::GLuint {
+        let buffer = self.new_id();
+        self.record(Call::CreateBuffer { buffer });
+        buffer
+    }
+
+    fn buffer_data(&self, buffer: gl::types::GLuint, data: &[u8], usage: gl::types::GLenum) {
+        self.record(Call::BufferData { buffer, data: data.to_vec(), usage });
+    }
+
+    fn buffer_sub_data(&self, buffer: gl::types::GLuint, offset: usize, data: &[u8]) {
+        self.record(Call::BufferSubData { buffer, offset, data: data.to_vec() });
+    }
+
+    fn bind_buffer_base(&self, target: gl::types::GLenum, index: u32, buffer: gl::types::GLuint) {
+        self.record(Call::BindBufferBase { target, index, buffer });
+    }
+
+    fn create_mapped_buffer(&self, size: usize) -> (gl::types::GLuint, *mut u8) {
+        let buffer = self.new_id();
+        self.record(Call::CreateMappedBuffer { buffer, size });
+
+        let mut memory = vec![0u8; size].into_boxed_slice();
+        let ptr = memory.as_mut_ptr();
+        self.mapped.borrow_mut().push(memory);
+
+        (buffer, ptr)
+    }
+
+    fn unmap_buffer(&self, buffer: gl::types::GLuint) {
+        self.record(Call::UnmapBuffer { buffer });
+    }
+
+    fn delete_buffer(&self, buffer: gl::types::GLuint) {
+        self.record(Call::DeleteBuffer { buffer });
+    }
+
+    fn fence_sync(&self) -> gl::types::GLsync {
+        self.record(Call::FenceSync);
+        std::ptr::null()
+    }
+
+    fn wait_sync(&self, _fence: gl::types::GLsync) {
+        self.record(Call::WaitSync);
+    }
+
+    fn delete_sync(&self, _fence: gl::types::GLsync) {
+        self.record(Call::DeleteSync);
+    }
+
+    fn create_vertex_array(&self) -> gl::types::GLuint {
+        let vertex_array = self.new_id();
+        self.record(Call::CreateVertexArray { vertex_array });
+        vertex_array
+    }
+
+    fn vertex_array_vertex_buffer(&self, vertex_array: gl::types::GLuint, binding: u32, buffer: gl::types::GLuint, offset: usize, stride: usize) {
+        self.record(Call::VertexArrayVertexBuffer { vertex_array, binding, buffer, offset, stride });
+    }
+
+    fn vertex_array_element_buffer(&self, vertex_array: gl::types::GLuint, buffer: gl::types::GLuint) {
+        self.record(Call::VertexArrayElementBuffer { vertex_array, buffer });
+    }
+
+    fn vertex_array_attrib_format(&self, vertex_array: gl::types::GLuint, location: u32, components: usize, binding: u32, relative_offset: usize) {
+        self.record(Call::VertexArrayAttribFormat { vertex_array, location, components, binding, relative_offset });
+    }
+
+    fn vertex_array_binding_divisor(&self, vertex_array: gl::types::GLuint, binding: u32, divisor: u32) {
+        self.record(Call::VertexArrayBindingDivisor { vertex_array, binding, divisor });
+    }
+
+    fn delete_vertex_array(&self, vertex_array: gl::types::GLuint) {
+        self.record(Call::DeleteVertexArray { vertex_array });
+    }
+
+    fn compile_shader(&self, kind: gl::types::GLenum, _source: &CStr) -> Result<gl::types::GLuint, String> {
+        let shader = self.new_id();
+        self.record(Call::CompileShader { shader, kind });
+        Ok(shader)
+    }
+
+    fn delete_shader(&self, shader: gl::types::GLuint) {
+        self.record(Call::DeleteShader { shader });
+    }
+
+    fn link_program(&self, shaders: &[gl::types::GLuint]) -> Result<gl::types::GLuint, String> {
+        let program = self.new_id();
+        self.record(Call::LinkProgram { program, shaders: shaders.to_vec() });
+        Ok(program)
+    }
+
+    fn delete_program(&self, program: gl::types::GLuint) {
+        self.record(Call::DeleteProgram { program });
+    }
+
+    fn set_uniform(&self, program: gl::types::GLuint, name: &str, value: &Uniform) {
+        self.record(Call::SetUniform { program, name: name.into(), value: value.clone() });
+    }
+
+    fn use_program(&self, program: gl::types::GLuint) {
+        self.record(Call::UseProgram { program });
+    }
+
+    fn bind_vertex_array(&self, vertex_array: gl::types::GLuint) {
+        self.record(Call::BindVertexArray { vertex_array });
+    }
+
+    fn bind_texture_unit(&self, unit: u32, texture: gl::types::GLuint) {
+        self.record(Call::BindTextureUnit { unit, texture });
+    }
+
+    fn set_enabled(&self, capability: gl::types::GLenum, enabled: bool) {
+        self.record(Call::SetEnabled { capability, enabled });
+    }
+
+    fn blend_equation(&self, equation: gl::types::GLenum) {
+        self.record(Call::BlendEquation { equation });
+    }
+
+    fn blend_func(&self, source: gl::types::GLenum, destination: gl::types::GLenum) {
+        self.record(Call::BlendFunc { source, destination });
+    }
+
+    fn depth_func(&self, func: gl::types::GLenum) {
+        self.record(Call::DepthFunc { func });
+    }
+
+    fn depth_mask(&self, write: bool) {
+        self.record(Call::DepthMask { write });
+    }
+
+    fn cull_face(&self, face: gl::types::GLenum) {
+        self.record(Call::CullFace { face });
+    }
+
+    fn front_face(&self, winding: gl::types::GLenum) {
+        self.record(Call::FrontFace { winding });
+    }
+
+    fn polygon_mode(&self, mode: gl::types::GLenum) {
+        self.record(Call::PolygonMode { mode });
+    }
+
+    fn polygon_offset(&self, factor: f32, units: f32) {
+        self.record(Call::PolygonOffset { factor, units });
+    }
+
+    fn stencil_func(&self, func: gl::types::GLenum, reference: i32, mask: u32) {
+        self.record(Call::StencilFunc { func, reference, mask });
+    }
+
+    fn stencil_op(&self, stencil_fail: gl::types::GLenum, depth_fail: gl::types::GLenum, pass: gl::types::GLenum) {
+        self.record(Call::StencilOp { stencil_fail, depth_fail, pass });
+    }
+
+    fn stencil_mask(&self, mask: u32) {
+        self.record(Call::StencilMask { mask });
+    }
+
+    fn scissor(&self, x: i32, y: i32, width: i32, height: i32) {
+        self.record(Call::Scissor { x, y, width, height });
+    }
+
+    fn viewport(&self, x: i32, y: i32, width: i32, height: i32) {
+        self.record(Call::Viewport { x, y, width, height });
+    }
+
+    fn active_texture(&self, unit: u32) {
+        self.record(Call::ActiveTexture { unit });
+    }
+
+    fn draw_arrays(&self, mode: gl::types::GLenum, first: i32, count: i32) {
+        self.record(Call::DrawArrays { mode, first, count });
+    }
+
+    fn draw_arrays_instanced(&self, mode: gl::types::GLenum, first: i32, count: i32, instances: i32) {
+        self.record(Call::DrawArraysInstanced { mode, first, count, instances });
+    }
+
+    fn draw_elements_base_vertex(&self, mode: gl::types::GLenum, count: i32, base_vertex: i32) {
+        self.record(Call::DrawElementsBaseVertex { mode, count, base_vertex });
+    }
+
+    fn draw_elements_instanced_base_vertex(&self, mode: gl::types::GLenum, count: i32, instances: i32, base_vertex: i32) {
+        self.record(Call::DrawElementsInstancedBaseVertex { mode, count, instances, base_vertex });
+    }
+
+    fn get_integers(&self, _name: gl::types::GLenum, values: &mut [gl::types::GLint]) {
+        for value in values.iter_mut() {
+            *value = 0;
+        }
+    }
+
+    fn is_enabled(&self, _capability: gl::types::GLenum) -> bool {
+        false
+    }
+}
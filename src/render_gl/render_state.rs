@@ -0,0 +1,175 @@
+use gl;
+use crate::render_gl::state::{GlState};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Blend {
+    pub equation: gl::types::GLenum,
+    pub source: gl::types::GLenum,
+    pub destination: gl::types::GLenum,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Depth {
+    pub func: gl::types::GLenum,
+    pub write: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Cull {
+    /// which faces get dropped, `gl::BACK`, `gl::FRONT` or `gl::FRONT_AND_BACK`
+    pub face: gl::types::GLenum,
+    /// winding of front faces, `gl::CCW` or `gl::CW`
+    pub front: gl::types::GLenum,
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PolygonMode {
+    Fill,
+    Line,
+    Point,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stencil {
+    pub func: gl::types::GLenum,
+    pub reference: i32,
+    pub read_mask: u32,
+    pub write_mask: u32,
+    pub stencil_fail: gl::types::GLenum,
+    pub depth_fail: gl::types::GLenum,
+    pub pass: gl::types::GLenum,
+}
+
+/// Fixed function pipeline state for a draw. Any part set to `None` is switched off.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RenderState {
+    pub blend: Option<Blend>,
+    pub depth: Option<Depth>,
+    pub cull: Option<Cull>,
+    pub polygon_mode: PolygonMode,
+    /// depth bias as (factor, units), negative values pull geometry towards the camera
+    pub polygon_offset: Option<(f32, f32)>,
+    pub stencil: Option<Stencil>,
+    /// (x, y, width, height) in window pixels
+    pub scissor: Option<(i32, i32, i32, i32)>,
+}
+
+impl RenderState {
+    /// Depth tested and written, no blending or culling.
+    pub fn opaque() -> RenderState {
+        RenderState {
+            blend: None,
+            depth: Some(Depth { func: gl::LESS, write: true }),
+            cull: None,
+            polygon_mode: PolygonMode::Fill,
+            polygon_offset: None,
+            stencil: None,
+            scissor: None,
+        }
+    }
+
+    /// Alpha blended over what is already drawn. Depth is tested but not written, so these
+    /// should be drawn after everything opaque, back to front.
+    #[allow(dead_code)]
+    pub fn transparent() -> RenderState {
+        RenderState {
+            blend: Some(Blend { equation: gl::FUNC_ADD, source: gl::SRC_ALPHA, destination: gl::ONE_MINUS_SRC_ALPHA }),
+            depth: Some(Depth { func: gl::LESS, write: false }),
+            ..RenderState::opaque()
+        }
+    }
+
+    /// Triangle edges only, pulled slightly towards the camera so they can be drawn over the
+    /// same mesh rendered filled.
+    pub fn wireframe() -> RenderState {
+        RenderState {
+            depth: Some(Depth { func: gl::LEQUAL, write: false }),
+            polygon_mode: PolygonMode::Line,
+            polygon_offset: Some((-1.0, -1.0)),
+            ..RenderState::opaque()
+        }
+    }
+
+    /// The same state for geometry drawn through a mirror, which turns the winding of every
+    /// triangle around.
+    pub fn mirrored(&self) -> RenderState {
+        let flip = |front| if front == gl::CW { gl::CCW } else { gl::CW };
+
+        RenderState {
+            cull: self.cull.map(|cull| Cull { front: flip(cull.front), ..cull }),
+            ..*self
+        }
+    }
+
+    pub fn apply(&self, state: &mut GlState) {
+        state.set_enabled(gl::BLEND, self.blend.is_some());
+        if let Some(blend) = self.blend {
+            state.blend_equation(blend.equation);
+            state.blend_func(blend.source, blend.destination);
+        }
+
+        state.set_enabled(gl::DEPTH_TEST, self.depth.is_some());
+        if let Some(depth) = self.depth {
+            state.depth_func(depth.func);
+            state.depth_mask(depth.write);
+        }
+
+        state.set_enabled(gl::CULL_FACE, self.cull.is_some());
+        if let Some(cull) = self.cull {
+            state.cull_face(cull.face);
+            state.front_face(cull.front);
+        }
+
+        state.polygon_mode(match self.polygon_mode {
+            PolygonMode::Fill => gl::FILL,
+            PolygonMode::Line => gl::LINE,
+            PolygonMode::Point => gl::POINT,
+        });
+
+        // offset applies to whichever mode is rasterized, the others stay off
+        let offset_capability = match self.polygon_mode {
+            PolygonMode::Fill => gl::POLYGON_OFFSET_FILL,
+            PolygonMode::Line => gl::POLYGON_OFFSET_LINE,
+            PolygonMode::Point => gl::POLYGON_OFFSET_POINT,
+        };
+        for &capability in [gl::POLYGON_OFFSET_FILL, gl::POLYGON_OFFSET_LINE, gl::POLYGON_OFFSET_POINT].iter() {
+            state.set_enabled(capability, self.polygon_offset.is_some() && capability == offset_capability);
+        }
+        if let Some((factor, units)) = self.polygon_offset {
+            state.polygon_offset(factor, units);
+        }
+
+        state.set_enabled(gl::STENCIL_TEST, self.stencil.is_some());
+        if let Some(stencil) = self.stencil {
+            state.stencil_func(stencil.func, stencil.reference, stencil.read_mask);
+            state.stencil_op(stencil.stencil_fail, stencil.depth_fail, stencil.pass);
+            state.stencil_mask(stencil.write_mask);
+        }
+
+        state.set_enabled(gl::SCISSOR_TEST, self.scissor.is_some());
+        if let Some((x, y, width, height)) = self.scissor {
+            state.scissor(x, y, width, height);
+        }
+    }
+}
+
+impl Default for RenderState {
+    fn default() -> RenderState {
+        RenderState::opaque()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirroring_swaps_the_front_faces_only() {
+        let culled = RenderState { cull: Some(Cull { face: gl::BACK, front: gl::CCW }), ..RenderState::opaque() };
+
+        assert_eq!(culled.mirrored(), RenderState { cull: Some(Cull { face: gl::BACK, front: gl::CW }), ..culled });
+        assert_eq!(culled.mirrored().mirrored(), culled);
+        assert_eq!(RenderState::transparent().mirrored(), RenderState::transparent());
+    }
+}
@@ -0,0 +1,229 @@
+use gl;
+use crate::render_gl::framebuffer::{Error, Framebuffer, Renderbuffer};
+use crate::render_gl::state::{GlState};
+use crate::render_gl::texture::{Texture};
+
+/// Something to render into other than the window: any number of color attachments plus an
+/// optional depth (or depth/stencil) attachment, all the same size.
+///
+/// With `samples` above 0 the rendering goes into multisampled renderbuffers, and `resolve`
+/// averages them into the textures that `color` and `depth` return. Without samples it renders
+/// into those textures directly and `resolve` does nothing.
+#[allow(dead_code)]
+pub struct RenderTarget {
+    width: u32,
+    height: u32,
+    samples: u32,
+    color_formats: Vec<gl::types::GLenum>,
+    depth_format: Option<gl::types::GLenum>,
+    // the sampleable result, also what gets rendered into without multisampling
+    framebuffer: Framebuffer,
+    colors: Vec<Texture>,
+    depth: Option<Texture>,
+    // only with multisampling
+    multisample_framebuffer: Option<Framebuffer>,
+    multisample_buffers: Vec<Renderbuffer>,
+    gl: gl::Gl,
+}
+
+#[allow(dead_code)]
+impl RenderTarget {
+    /// `color_formats` are sized internal formats such as `gl::RGBA8` or `gl::RGBA16F`, one per
+    /// attachment; `depth_format` is e.g. `gl::DEPTH_COMPONENT24` or `gl::DEPTH24_STENCIL8`.
+    pub fn new(
+        gl: &gl::Gl,
+        width: u32,
+        height: u32,
+        color_formats: &[gl::types::GLenum],
+        depth_format: Option<gl::types::GLenum>,
+        samples: u32,
+    ) -> Result<RenderTarget, Error> {
+        let attachments = color_attachments(color_formats.len());
+
+        let framebuffer = Framebuffer::new(gl);
+
+        let colors: Vec<Texture> = color_formats.iter()
+            .map(|&format| Texture::new_2d(gl, format, width, height, 1))
+            .collect();
+        let depth = depth_format.map(|format| Texture::new_2d(gl, format, width, height, 1));
+
+        for (texture, &attachment) in colors.iter().zip(attachments.iter()) {
+            framebuffer.attach_texture(attachment, texture, 0);
+        }
+        if let (Some(texture), Some(format)) = (&depth, depth_format) {
+            framebuffer.attach_texture(depth_attachment(format), texture, 0);
+        }
+        framebuffer.draw_buffers(&attachments);
+        framebuffer.check()?;
+
+        let mut multisample_buffers = vec![];
+        let multisample_framebuffer = if samples > 0 {
+            let multisample_framebuffer = Framebuffer::new(gl);
+
+            for (&format, &attachment) in color_formats.iter().zip(attachments.iter()) {
+                let buffer = Renderbuffer::new(gl, format, width, height, samples);
+                multisample_framebuffer.attach_renderbuffer(attachment, &buffer);
+                multisample_buffers.push(buffer);
+            }
+            if let Some(format) = depth_format {
+                let buffer = Renderbuffer::new(gl, format, width, height, samples);
+                multisample_framebuffer.attach_renderbuffer(depth_attachment(format), &buffer);
+                multisample_buffers.push(buffer);
+            }
+            multisample_framebuffer.draw_buffers(&attachments);
+            multisample_framebuffer.check()?;
+
+            Some(multisample_framebuffer)
+        } else {
+            None
+        };
+
+        Ok(RenderTarget {
+            width,
+            height,
+            samples,
+            color_formats: color_formats.to_vec(),
+            depth_format,
+            framebuffer,
+            colors,
+            depth,
+            multisample_framebuffer,
+            multisample_buffers,
+            gl: gl.clone(),
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Color attachment `index` after `resolve`, for sampling in a later pass.
+    pub fn color(&self, index: usize) -> &Texture {
+        &self.colors[index]
+    }
+
+    pub fn depth(&self) -> Option<&Texture> {
+        self.depth.as_ref()
+    }
+
+    /// Framebuffer holding the resolved textures, e.g. to read pixels back from.
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /// Recreates every attachment at the new size; their contents are lost. Does nothing when
+    /// the size stays the same.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), Error> {
+        if (width, height) == (self.width, self.height) {
+            return Ok(());
+        }
+
+        *self = RenderTarget::new(&self.gl, width, height, &self.color_formats, self.depth_format, self.samples)?;
+        Ok(())
+    }
+
+    /// Renders into this target from now on, with the viewport covering all of it.
+    pub fn bind(&self, state: &mut GlState) {
+        self.draw_framebuffer().bind();
+        state.viewport(0, 0, self.width as i32, self.height as i32);
+    }
+
+    /// Clears every color attachment to `color`, and depth to 1.0 and stencil to 0. Honours the
+    /// color and depth write masks like `glClear`.
+    pub fn clear(&self, color: (f32, f32, f32, f32)) {
+        let id = self.draw_framebuffer().id();
+        let color = [color.0, color.1, color.2, color.3];
+
+        unsafe {
+            for i in 0..self.colors.len() {
+                self.gl.ClearNamedFramebufferfv(id, gl::COLOR, i as gl::types::GLint, color.as_ptr());
+            }
+
+            match self.depth_format {
+                Some(format) if depth_attachment(format) == gl::DEPTH_STENCIL_ATTACHMENT =>
+                    self.gl.ClearNamedFramebufferfi(id, gl::DEPTH_STENCIL, 0, 1.0, 0),
+                Some(_) => self.gl.ClearNamedFramebufferfv(id, gl::DEPTH, 0, &1.0),
+                None => {},
+            }
+        }
+    }
+
+    /// Averages the multisampled attachments into the textures. Call after rendering and before
+    /// sampling from `color` or `depth`.
+    pub fn resolve(&self) {
+        let multisampled = match self.multisample_framebuffer {
+            Some(ref framebuffer) => framebuffer,
+            None => return,
+        };
+
+        let attachments = color_attachments(self.colors.len());
+
+        unsafe {
+            // blits copy from the read buffer to the draw buffers, so one attachment at a time
+            for &attachment in attachments.iter() {
+                self.gl.NamedFramebufferReadBuffer(multisampled.id(), attachment);
+                self.gl.NamedFramebufferDrawBuffer(self.framebuffer.id(), attachment);
+                self.blit(multisampled.id(), self.framebuffer.id(), gl::COLOR_BUFFER_BIT);
+            }
+
+            if let Some(format) = self.depth_format {
+                let mask = if depth_attachment(format) == gl::DEPTH_STENCIL_ATTACHMENT {
+                    gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT
+                } else {
+                    gl::DEPTH_BUFFER_BIT
+                };
+                self.blit(multisampled.id(), self.framebuffer.id(), mask);
+            }
+        }
+
+        self.framebuffer.draw_buffers(&attachments);
+    }
+
+    /// Copies resolved color attachment `index` into the window's framebuffer, scaled to
+    /// `width` x `height`. Like every blit it is clipped by the scissor test if enabled.
+    pub fn blit_to_default(&self, index: usize, width: u32, height: u32) {
+        self.blit_to(index, 0, width, height);
+    }
+
+    /// Like `blit_to_default`, into the draw buffers of any `framebuffer`.
+    pub fn blit_to(&self, index: usize, framebuffer: gl::types::GLuint, width: u32, height: u32) {
+        unsafe {
+            self.gl.NamedFramebufferReadBuffer(self.framebuffer.id(), gl::COLOR_ATTACHMENT0 + index as u32);
+            self.gl.BlitNamedFramebuffer(
+                self.framebuffer.id(), framebuffer,
+                0, 0, self.width as i32, self.height as i32,
+                0, 0, width as i32, height as i32,
+                gl::COLOR_BUFFER_BIT, gl::LINEAR,
+            );
+        }
+    }
+
+    fn draw_framebuffer(&self) -> &Framebuffer {
+        self.multisample_framebuffer.as_ref().unwrap_or(&self.framebuffer)
+    }
+
+    unsafe fn blit(&self, from: gl::types::GLuint, to: gl::types::GLuint, mask: gl::types::GLbitfield) {
+        // resolving needs matching sizes and, for depth and stencil, nearest filtering
+        self.gl.BlitNamedFramebuffer(
+            from, to,
+            0, 0, self.width as i32, self.height as i32,
+            0, 0, self.width as i32, self.height as i32,
+            mask, gl::NEAREST,
+        );
+    }
+}
+
+fn color_attachments(count: usize) -> Vec<gl::types::GLenum> {
+    (0..count).map(|i| gl::COLOR_ATTACHMENT0 + i as u32).collect()
+}
+
+fn depth_attachment(format: gl::types::GLenum) -> gl::types::GLenum {
+    match format {
+        gl::DEPTH24_STENCIL8 | gl::DEPTH32F_STENCIL8 => gl::DEPTH_STENCIL_ATTACHMENT,
+        _ => gl::DEPTH_ATTACHMENT,
+    }
+}
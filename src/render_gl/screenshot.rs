@@ -0,0 +1,92 @@
+extern crate png;
+
+use gl;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to access {}", path)]
+    Io { path: String, #[cause] inner: io::Error },
+    #[fail(display = "Failed to encode {} as PNG", path)]
+    Encoding { path: String, #[cause] inner: png::EncodingError },
+    #[fail(display = "Failed to decode {} as PNG", path)]
+    Decoding { path: String, #[cause] inner: png::DecodingError },
+    #[fail(display = "{} is not an 8 bit RGBA image", path)]
+    UnsupportedFormat { path: String },
+}
+
+/// RGBA8 pixels of the read buffer of `framebuffer` (0 for the window), top row first.
+pub fn read_pixels(gl: &gl::Gl, framebuffer: gl::types::GLuint, width: u32, height: u32) -> Vec<u8> {
+    let mut pixels = vec![0u8; (width * height * 4) as usize];
+
+    unsafe {
+        gl.BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer);
+        gl.ReadnPixels(
+            0, 0,
+            width as gl::types::GLsizei,
+            height as gl::types::GLsizei,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            pixels.len() as gl::types::GLsizei,
+            pixels.as_mut_ptr() as *mut gl::types::GLvoid,
+        );
+    }
+
+    // GL starts at the bottom row, images at the top
+    flip_rows(&mut pixels, (width * 4) as usize);
+
+    pixels
+}
+
+/// Reverses the order of the rows of `row_size` bytes each.
+pub fn flip_rows(pixels: &mut [u8], row_size: usize) {
+    let rows = pixels.len() / row_size;
+
+    for row in 0..rows / 2 {
+        let (top, bottom) = pixels.split_at_mut((rows - row - 1) * row_size);
+        top[row * row_size..(row + 1) * row_size].swap_with_slice(&mut bottom[..row_size]);
+    }
+}
+
+/// Writes top-down RGBA8 `pixels` as a PNG file.
+pub fn save_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), Error> {
+    let io_error = |inner: io::Error| Error::Io { path: path.display().to_string(), inner };
+    let encoding_error = |inner: png::EncodingError| Error::Encoding { path: path.display().to_string(), inner };
+
+    let file = File::create(path).map_err(io_error)?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .map_err(encoding_error)
+}
+
+/// Reads a PNG file as written by `save_png`, returning its size and top-down RGBA8 pixels.
+#[allow(dead_code)]
+pub fn load_png(path: &Path) -> Result<(u32, u32, Vec<u8>), Error> {
+    let io_error = |inner: io::Error| Error::Io { path: path.display().to_string(), inner };
+    let decoding_error = |inner: png::DecodingError| Error::Decoding { path: path.display().to_string(), inner };
+
+    let file = File::open(path).map_err(io_error)?;
+
+    let (info, mut reader) = png::Decoder::new(file).read_info().map_err(decoding_error)?;
+
+    if info.color_type != png::ColorType::RGBA || info.bit_depth != png::BitDepth::Eight {
+        return Err(Error::UnsupportedFormat { path: path.display().to_string() });
+    }
+
+    let mut pixels = vec![0u8; info.buffer_size()];
+    reader.next_frame(&mut pixels).map_err(decoding_error)?;
+
+    Ok((info.width, info.height, pixels))
+}
+
+/// Reads back `framebuffer` and saves it as a PNG file.
+pub fn capture(gl: &gl::Gl, framebuffer: gl::types::GLuint, width: u32, height: u32, path: &Path) -> Result<(), Error> {
+    save_png(path, width, height, &read_pixels(gl, framebuffer, width, height))
+}
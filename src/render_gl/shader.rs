@@ -2,6 +2,7 @@ use gl;
 use std;
 use std::ffi::{CString, CStr};
 use crate::resources::{self, Resources};
+use crate::render_gl::device::{Device, Uniform};
 extern crate nalgebra_glm as glm;
 
 #[derive(Debug, Fail)]
@@ -17,12 +18,18 @@ pub enum Error {
 }
 
 pub struct Program {
-    gl: gl::Gl,
+    device: Device,
     id: gl::types::GLuint,
 }
 
 impl Program {
-    pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Program, Error> {
+    pub fn from_res(device: &Device, res: &Resources, name: &str) -> Result<Program, Error> {
+        Program::from_res_with_defines(device, res, name, &[])
+    }
+
+    /// Like `from_res`, with `#define NAME VALUE` lines added to every shader source right
+    /// after its `#version` line, e.g. for array sizes picked at runtime.
+    pub fn from_res_with_defines(device: &Device, res: &Resources, name: &str, defines: &[(&str, String)]) -> Result<Program, Error> {
         const POSSIBLE_EXT: [&str; 2] = [".vert", ".frag"];
 
         let resource_names = POSSIBLE_EXT
@@ -30,94 +37,82 @@ impl Program {
             .collect::<Vec<String>>();
 
         let shaders = resource_names
-            .iter().map(|resource_name| Shader::from_res(gl, res, resource_name))
+            .iter().map(|resource_name| Shader::from_res_with_defines(device, res, resource_name, defines))
             .collect::<Result<Vec<Shader>, Error>>()?;
 
-        Program::make(gl, &shaders[..]).map_err(|message| Error::LinkError {
+        Program::make(device, &shaders[..]).map_err(|message| Error::LinkError {
             name: name.into(),
             message,
         })
     }
 
-    pub fn make(gl: &gl::Gl, shaders: &[Shader]) -> Result<Program, String> {
-        let program_id = unsafe { gl.CreateProgram() };
-
-        for shader in shaders {
-            unsafe { gl.AttachShader(program_id, shader.id()); }
-        }
-
-        unsafe { gl.LinkProgram(program_id); }
+    pub fn make(device: &Device, shaders: &[Shader]) -> Result<Program, String> {
+        let ids: Vec<gl::types::GLuint> = shaders.iter().map(|shader| shader.id()).collect();
+        let id = device.link_program(&ids)?;
 
-        let mut success: gl::types::GLint = 1;
-
-        unsafe {
-            gl.GetProgramiv(program_id, gl::LINK_STATUS, &mut success);
-        }
-
-        if success == 0 {
-            let mut len: gl::types::GLint = 0;
+        Ok(Program { device: device.clone(), id })
+    }
 
-            unsafe {
-                gl.GetProgramiv(program_id, gl::INFO_LOG_LENGTH, &mut len)
-            }
+    pub fn set_mat4(&self, name: &str, mat4: &glm::Mat4) {
+        self.device.set_uniform(self.id, name, &Uniform::Mat4(*mat4));
+    }
 
-            let error = create_filled_cstring(len as usize, b' ');
+    /// Sets `count` consecutive elements of a `mat4 name[]` array, starting at the first.
+    pub fn set_mat4_array(&self, name: &str, mat4s: &[glm::Mat4]) {
+        self.device.set_uniform(self.id, name, &Uniform::Mat4Array(mat4s.to_vec()));
+    }
 
-            unsafe {
-                gl.GetProgramInfoLog(
-                    program_id,
-                    len,
-                    std::ptr::null_mut(),
-                    error.as_ptr() as *mut gl::types::GLchar
-                );
-            }
+    pub fn set_float_array(&self, name: &str, values: &[f32]) {
+        self.device.set_uniform(self.id, name, &Uniform::FloatArray(values.to_vec()));
+    }
 
-            return Err(error.to_string_lossy().into_owned());
-        }
+    pub fn set_float(&self, name: &str, value: f32) {
+        self.device.set_uniform(self.id, name, &Uniform::Float(value));
+    }
 
-        for shader in shaders {
-            unsafe { gl.DetachShader(program_id, shader.id()); }
-        }
+    pub fn set_int(&self, name: &str, value: i32) {
+        self.device.set_uniform(self.id, name, &Uniform::Int(value));
+    }
 
-        Ok(Program { gl: gl.clone(), id: program_id })
+    pub fn set_vec2(&self, name: &str, vec2: &glm::Vec2) {
+        self.device.set_uniform(self.id, name, &Uniform::Vec2(*vec2));
     }
 
-    pub fn set_mat4(&self, gl: &gl::Gl, name: &str, mat4: &glm::Mat4) {
-        let mut _loc: gl::types::GLint = 0;
-        let c_str = CString::new(name).unwrap();
+    pub fn set_vec3(&self, name: &str, vec3: &glm::Vec3) {
+        self.device.set_uniform(self.id, name, &Uniform::Vec3(*vec3));
+    }
 
-        unsafe {
-            _loc = gl.GetUniformLocation(self.id(), c_str.as_ptr());
-            gl.UniformMatrix4fv(_loc, 1, gl::FALSE, mat4.as_ptr());
-        }
+    pub fn set_vec4(&self, name: &str, vec4: &glm::Vec4) {
+        self.device.set_uniform(self.id, name, &Uniform::Vec4(*vec4));
     }
 
     pub fn id(&self) -> gl::types::GLuint {
         self.id
     }
 
+    #[allow(dead_code)]
     pub fn activate(&self) {
-        unsafe {
-            self.gl.UseProgram(self.id)
-        }
+        self.device.use_program(self.id);
     }
 }
 
 impl Drop for Program {
     fn drop(&mut self) {
-        unsafe {
-            self.gl.DeleteProgram(self.id)
-        }
+        self.device.delete_program(self.id);
     }
 }
 
 pub struct Shader {
-    gl: gl::Gl,
+    device: Device,
     id: gl::types::GLuint,
 }
 
 impl Shader {
-    pub fn from_res(gl: &gl::Gl, res: &Resources, name: &str) -> Result<Shader, Error> {
+    pub fn from_res(device: &Device, res: &Resources, name: &str) -> Result<Shader, Error> {
+        Shader::from_res_with_defines(device, res, name, &[])
+    }
+
+    pub fn from_res_with_defines(device: &Device, res: &Resources, name: &str, defines: &[(&str, String)]) -> Result<Shader, Error> {
         const POSSIBLE_EXT: [(&str, gl::types::GLenum); 2] = 
             [(".vert", gl::VERTEX_SHADER), (".frag", gl::FRAGMENT_SHADER)];
 
@@ -133,29 +128,31 @@ impl Shader {
             inner: e,
         })?;
 
-        Shader::make(gl, &source, shader_kind).map_err(|message| Error::CompileError {
+        let source = add_defines(source, defines);
+
+        Shader::make(device, &source, shader_kind).map_err(|message| Error::CompileError {
             name: name.into(),
             message,
         })
     }
 
     fn make(
-        gl: &gl::Gl,
+        device: &Device,
         source: &CStr,
         kind: gl::types::GLenum
     ) -> Result<Shader, String> {
-        let id = shader_from_source(gl, source, kind)?;
-        Ok(Shader { gl: gl.clone(), id })
+        let id = device.compile_shader(kind, source)?;
+        Ok(Shader { device: device.clone(), id })
     }
 
     #[allow(dead_code)]
-    pub fn vertex_shader(gl: &gl::Gl, source: &CStr) -> Result<Shader, String> {
-        Shader::make(gl, source, gl::VERTEX_SHADER)
+    pub fn vertex_shader(device: &Device, source: &CStr) -> Result<Shader, String> {
+        Shader::make(device, source, gl::VERTEX_SHADER)
     }
 
     #[allow(dead_code)]
-    pub fn fragment_shader(gl: &gl::Gl, source: &CStr) -> Result<Shader, String> {
-        Shader::make(gl, source, gl::FRAGMENT_SHADER)
+    pub fn fragment_shader(device: &Device, source: &CStr) -> Result<Shader, String> {
+        Shader::make(device, source, gl::FRAGMENT_SHADER)
     }
 
     pub fn id(&self) -> gl::types::GLuint {
@@ -165,61 +162,26 @@ impl Shader {
 
 impl Drop for Shader {
     fn drop(&mut self) {
-        unsafe {
-            self.gl.DeleteShader(self.id)
-        }
+        self.device.delete_shader(self.id);
     }
 }
 
-fn shader_from_source(
-    gl: &gl::Gl,
-    source: &CStr,
-    kind: gl::types::GLuint
-) -> Result<gl::types::GLuint, String> {
-    let id = unsafe { gl.CreateShader(kind) };
-
-    unsafe {
-        gl.ShaderSource(id, 1, &source.as_ptr(), std::ptr::null());
-        gl.CompileShader(id);
+fn add_defines(source: CString, defines: &[(&str, String)]) -> CString {
+    if defines.is_empty() {
+        return source;
     }
 
-    let mut success: gl::types::GLint = 1;
-
-    unsafe {
-        gl.GetShaderiv(id, gl::COMPILE_STATUS, &mut success);
-    }
+    let source = source.into_bytes();
 
-    if success == 0 {
-        let mut len: gl::types::GLint = 0;
+    // #version has to stay the first line
+    let split = source.iter().position(|&c| c == b'\n').map(|i| i + 1).unwrap_or(source.len());
 
-        unsafe {
-            gl.GetShaderiv(id, gl::INFO_LOG_LENGTH, &mut len);
-        }
-
-        let error = create_filled_cstring(len as usize, b' ');
-
-        // write OpenGL error
-        unsafe {
-            gl.GetShaderInfoLog(
-                id,
-                len,
-                std::ptr::null_mut(),
-                error.as_ptr() as *mut gl::types::GLchar
-            );
-        }
-
-        return Err(error.to_string_lossy().into_owned());
+    let mut result: Vec<u8> = source[..split].to_vec();
+    for (name, value) in defines {
+        result.extend(format!("#define {} {}\n", name, value).bytes());
     }
+    result.extend(&source[split..]);
 
-    Ok(id)
+    // names and values come from our own code, the source was checked for nul bytes on load
+    CString::new(result).expect("shader define contains a nul byte")
 }
-
-fn create_filled_cstring(len: usize, fill: u8) -> CString {
-    // make error buffer with correct size
-    let mut buffer: Vec<u8> = Vec::with_capacity(len as usize + 1);
-
-    // fill it with spaces
-    buffer.extend([fill].iter().cycle().take(len as usize));
-
-    unsafe { CString::from_vec_unchecked(buffer) }
-}
\ No newline at end of file
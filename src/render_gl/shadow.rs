@@ -0,0 +1,274 @@
+extern crate nalgebra_glm as glm;
+
+use gl;
+use crate::resources::Resources;
+use crate::render_gl::camera::{Camera};
+use crate::render_gl::device::{Device};
+use crate::render_gl::framebuffer::{Framebuffer};
+use crate::render_gl::object::{Object};
+use crate::render_gl::render_state::{RenderState};
+use crate::render_gl::shader::{Error, Program};
+use crate::render_gl::state::{GlState};
+use crate::render_gl::texture::{Texture};
+
+/// `layout (binding = ..)` of the `shadow_map` sampler in the lit shaders.
+pub const SHADOW_TEXTURE_UNIT: u32 = 1;
+
+/// Distances from the camera where each cascade starts and ends, `count + 1` values from `near`
+/// to `far`. `lambda` blends between evenly spaced splits (0.0) and logarithmic ones (1.0),
+/// which keep the shadow texel size on screen roughly constant but leave the far cascades huge.
+/// `near` has to be above zero.
+pub fn cascade_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (0..=count).map(|i| {
+        let fraction = i as f32 / count as f32;
+        let logarithmic = near * (far / near).powf(fraction);
+        let uniform = near + (far - near) * fraction;
+
+        lambda * logarithmic + (1.0 - lambda) * uniform
+    }).collect()
+}
+
+/// World space corners of the part of the camera frustum between the distances `from` and `to`.
+pub fn frustum_slice_corners(view: &glm::Mat4, projection: &glm::Mat4, near: f32, far: f32, from: f32, to: f32) -> Vec<glm::Vec3> {
+    let inverse = glm::inverse(&(projection * view));
+    let unproject = |x: f32, y: f32, z: f32| {
+        let world = inverse * glm::vec4(x, y, z, 1.0);
+        world.xyz() / world.w
+    };
+
+    let mut corners = Vec::with_capacity(8);
+
+    for &(x, y) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].iter() {
+        let near_corner = unproject(x, y, -1.0);
+        let far_corner = unproject(x, y, 1.0);
+
+        // depth grows linearly along the ray from the near to the far plane
+        for &distance in [from, to].iter() {
+            let t = (distance - near) / (far - near);
+            corners.push(near_corner + (far_corner - near_corner) * t);
+        }
+    }
+
+    corners
+}
+
+/// Orthographic projection * view looking along `light_direction`, covering `points` as far as
+/// they are inside `scene_bounds`, and deep enough to catch everything in the scene that could
+/// cast a shadow onto them.
+pub fn fit_light_projection(light_direction: &glm::Vec3, points: &[glm::Vec3], scene_bounds: &(glm::Vec3, glm::Vec3)) -> glm::Mat4 {
+    let direction = glm::normalize(light_direction);
+    let center = points.iter().fold(glm::vec3(0.0, 0.0, 0.0), |sum, p| sum + p) / points.len() as f32;
+
+    // any up vector works as long as it isn't parallel to the light
+    let up = if direction.y.abs() > 0.99 { glm::vec3(0.0, 0.0, 1.0) } else { glm::vec3(0.0, 1.0, 0.0) };
+    let view = glm::look_at(&(center - direction), &center, &up);
+
+    let light_space_bounds = |points: &mut dyn Iterator<Item = glm::Vec3>| {
+        let mut min = glm::vec3(std::f32::MAX, std::f32::MAX, std::f32::MAX);
+        let mut max = glm::vec3(std::f32::MIN, std::f32::MIN, std::f32::MIN);
+
+        for p in points {
+            let p = (view * glm::vec4(p.x, p.y, p.z, 1.0)).xyz();
+            min = glm::min2(&min, &p);
+            max = glm::max2(&max, &p);
+        }
+
+        (min, max)
+    };
+
+    let (scene_min, scene_max) = scene_bounds;
+    let mut scene_corners = (0..8).map(|i| glm::vec3(
+        if i & 1 == 0 { scene_min.x } else { scene_max.x },
+        if i & 2 == 0 { scene_min.y } else { scene_max.y },
+        if i & 4 == 0 { scene_min.z } else { scene_max.z },
+    ));
+
+    let (slice_min, slice_max) = light_space_bounds(&mut points.iter().cloned());
+    let (scene_min, scene_max) = light_space_bounds(&mut scene_corners);
+
+    // no texels are spent on the part of the slice where there is nothing to shade
+    let mut min = glm::max2(&slice_min, &scene_min);
+    let mut max = glm::min2(&slice_max, &scene_max);
+    if min.x >= max.x || min.y >= max.y {
+        min = scene_min;
+        max = scene_max;
+    }
+
+    // the light looks down -z, so the scene's largest z is the closest to it
+    glm::ortho(min.x, max.x, min.y, max.y, -scene_max.z, -scene_min.z) * view
+}
+
+/// Cascaded shadow map for one directional light. Every frame `update` fits the cascades to the
+/// camera, `render` fills the depth layers, and `bind` hands them to a lit program.
+#[allow(dead_code)]
+pub struct ShadowMap {
+    /// blend between uniform and logarithmic cascade splits, see `cascade_splits`
+    pub lambda: f32,
+    pub render_state: RenderState,
+    cascades: usize,
+    resolution: u32,
+    depth: Texture,
+    framebuffers: Vec<Framebuffer>,
+    program: Program,
+    light_spaces: Vec<glm::Mat4>,
+    splits: Vec<f32>,
+    gl: gl::Gl,
+}
+
+#[allow(dead_code)]
+impl ShadowMap {
+    pub fn new(gl: &gl::Gl, device: &Device, res: &Resources, resolution: u32, cascades: usize) -> Result<ShadowMap, Error> {
+        let program = Program::from_res(device, res, "shaders/shadow_depth")?;
+
+        let depth = Texture::new_2d_array(gl, gl::DEPTH_COMPONENT32F, resolution, resolution, cascades as u32, 1);
+        depth.set_depth_compare(gl::LEQUAL);
+
+        let framebuffers = (0..cascades).map(|layer| {
+            let framebuffer = Framebuffer::new(gl);
+            framebuffer.attach_texture_layer(gl::DEPTH_ATTACHMENT, &depth, 0, layer as u32);
+            framebuffer.draw_buffers(&[]);
+            framebuffer
+        }).collect();
+
+        Ok(ShadowMap {
+            lambda: 0.75,
+            // slope scaled bias against shadow acne; no culling since terrain is single sided
+            render_state: RenderState { polygon_offset: Some((2.0, 4.0)), ..RenderState::opaque() },
+            cascades,
+            resolution,
+            depth,
+            framebuffers,
+            program,
+            light_spaces: vec![glm::identity(); cascades],
+            splits: vec![0.0; cascades + 1],
+            gl: gl.clone(),
+        })
+    }
+
+    /// Defines to compile the lit shaders with, so their cascade arrays fit this map.
+    pub fn defines(&self) -> Vec<(&'static str, String)> {
+        vec![("MAX_CASCADES", self.cascades.to_string())]
+    }
+
+    /// Splits the camera frustum and fits a light projection to each slice.
+    pub fn update(&mut self, camera: &Camera, light_direction: &glm::Vec3, scene_bounds: &(glm::Vec3, glm::Vec3)) {
+        let (near, far) = camera.clip_range();
+        self.splits = cascade_splits(near, far, self.cascades, self.lambda);
+
+        for cascade in 0..self.cascades {
+            let corners = frustum_slice_corners(
+                &camera.matrix, camera.projection(), near, far,
+                self.splits[cascade], self.splits[cascade + 1],
+            );
+            self.light_spaces[cascade] = fit_light_projection(light_direction, &corners, scene_bounds);
+        }
+    }
+
+    /// Draws the depth of `objects` into every cascade. Leaves the default framebuffer bound,
+    /// the caller has to restore its viewport.
+    pub fn render(&self, state: &mut GlState, objects: &[&Object]) {
+        // the clear honours the depth mask, so the state has to be in place first
+        self.render_state.apply(state);
+        state.viewport(0, 0, self.resolution as i32, self.resolution as i32);
+
+        for (framebuffer, light_space) in self.framebuffers.iter().zip(self.light_spaces.iter()) {
+            framebuffer.bind();
+
+            unsafe {
+                self.gl.ClearNamedFramebufferfv(framebuffer.id(), gl::DEPTH, 0, &1.0);
+            }
+
+            self.program.set_mat4("light_space", light_space);
+
+            for obj in objects {
+                obj.draw_with(state, &self.program, &self.render_state);
+            }
+        }
+
+        Framebuffer::bind_default(&self.gl);
+    }
+
+    /// Makes the cascades available to `program`, a lit shader compiled with `defines`.
+    pub fn bind(&self, state: &mut GlState, program: &Program) {
+        state.bind_texture_unit(SHADOW_TEXTURE_UNIT, self.depth.id());
+
+        program.set_mat4_array("light_space", &self.light_spaces);
+        program.set_float_array("cascade_splits", &self.splits[1..]);
+        program.set_int("cascade_count", self.cascades as i32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_cover_the_frustum_in_order() {
+        let splits = cascade_splits(0.1, 1000.0, 4, 0.75);
+
+        assert_eq!(splits.len(), 5);
+        assert!((splits[0] - 0.1).abs() < 1e-4);
+        assert!((splits[4] - 1000.0).abs() < 1e-2);
+        assert!(splits.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn lambda_zero_is_uniform() {
+        let splits = cascade_splits(1.0, 101.0, 4, 0.0);
+
+        for (split, expected) in splits.iter().zip([1.0, 26.0, 51.0, 76.0, 101.0].iter()) {
+            assert!((split - expected).abs() < 1e-4, "{:?}", splits);
+        }
+    }
+
+    #[test]
+    fn lambda_one_is_logarithmic() {
+        let splits = cascade_splits(1.0, 1000.0, 3, 1.0);
+
+        for (split, expected) in splits.iter().zip([1.0, 10.0, 100.0, 1000.0].iter()) {
+            assert!((split - expected).abs() / expected < 1e-4, "{:?}", splits);
+        }
+    }
+
+    #[test]
+    fn slice_corners_lie_at_the_split_distances() {
+        let view = glm::look_at(&glm::vec3(0.0, 0.0, 0.0), &glm::vec3(0.0, 0.0, -1.0), &glm::vec3(0.0, 1.0, 0.0));
+        let projection = glm::perspective(1.0, 1.0, 0.5, 100.0);
+
+        let corners = frustum_slice_corners(&view, &projection, 0.5, 100.0, 10.0, 20.0);
+
+        assert_eq!(corners.len(), 8);
+        for (i, corner) in corners.iter().enumerate() {
+            let expected = if i % 2 == 0 { -10.0 } else { -20.0 };
+            assert!((corner.z - expected).abs() < 1e-3, "{:?}", corner);
+        }
+    }
+
+    #[test]
+    fn fitted_projection_contains_the_slice() {
+        let scene = (glm::vec3(-50.0, 0.0, -50.0), glm::vec3(50.0, 45.0, 50.0));
+        let slice = vec![
+            glm::vec3(-10.0, 5.0, -10.0), glm::vec3(10.0, 5.0, -10.0),
+            glm::vec3(-10.0, 20.0, 10.0), glm::vec3(10.0, 20.0, 10.0),
+        ];
+
+        let light_space = fit_light_projection(&glm::vec3(-0.4, -1.0, -0.3), &slice, &scene);
+
+        for p in slice.iter() {
+            let ndc = light_space * glm::vec4(p.x, p.y, p.z, 1.0);
+            assert!(ndc.x.abs() <= 1.0 + 1e-4 && ndc.y.abs() <= 1.0 + 1e-4 && ndc.z.abs() <= 1.0 + 1e-4, "{:?}", ndc);
+        }
+    }
+
+    #[test]
+    fn fitted_projection_is_clipped_to_the_scene() {
+        let scene = (glm::vec3(-1.0, 0.0, -1.0), glm::vec3(1.0, 1.0, 1.0));
+        let slice = vec![glm::vec3(-100.0, 0.5, -100.0), glm::vec3(100.0, 0.5, 100.0)];
+
+        let light_space = fit_light_projection(&glm::vec3(0.0, -1.0, 0.0), &slice, &scene);
+
+        // the scene corners end up on the edges of the shadow map instead of a tiny spot in it
+        let corner = light_space * glm::vec4(1.0, 0.0, 1.0, 1.0);
+        assert!((corner.x.abs() - 1.0).abs() < 1e-4 && (corner.y.abs() - 1.0).abs() < 1e-4, "{:?}", corner);
+    }
+}
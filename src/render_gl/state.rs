@@ -0,0 +1,281 @@
+use gl;
+use std::collections::HashMap;
+use crate::render_gl::device::{Device};
+
+const TEXTURE_UNITS: usize = 16;
+
+/// Remembers what has been set on the GL context and skips calls that wouldn't change anything.
+/// Every field is an `Option` where `None` means "unknown", so the next call always goes through.
+///
+/// Anything that changes the context behind its back (other code calling `gl` directly, or
+/// deleting a program or vertex array that may still be recorded as bound, since GL reuses the
+/// ids) needs a `reset` afterwards.
+pub struct GlState {
+    device: Device,
+    program: Option<gl::types::GLuint>,
+    vertex_array: Option<gl::types::GLuint>,
+    textures: [Option<gl::types::GLuint>; TEXTURE_UNITS],
+    capabilities: HashMap<gl::types::GLenum, bool>,
+    blend_equation: Option<gl::types::GLenum>,
+    blend_func: Option<(gl::types::GLenum, gl::types::GLenum)>,
+    depth_func: Option<gl::types::GLenum>,
+    depth_mask: Option<bool>,
+    cull_face: Option<gl::types::GLenum>,
+    front_face: Option<gl::types::GLenum>,
+    polygon_mode: Option<gl::types::GLenum>,
+    polygon_offset: Option<(f32, f32)>,
+    stencil_func: Option<(gl::types::GLenum, i32, u32)>,
+    stencil_op: Option<(gl::types::GLenum, gl::types::GLenum, gl::types::GLenum)>,
+    stencil_mask: Option<u32>,
+    scissor: Option<(i32, i32, i32, i32)>,
+    viewport: Option<(i32, i32, i32, i32)>,
+}
+
+impl GlState {
+    pub fn new(device: &Device) -> GlState {
+        GlState {
+            device: device.clone(),
+            program: None,
+            vertex_array: None,
+            textures: [None; TEXTURE_UNITS],
+            capabilities: HashMap::new(),
+            blend_equation: None,
+            blend_func: None,
+            depth_func: None,
+            depth_mask: None,
+            cull_face: None,
+            front_face: None,
+            polygon_mode: None,
+            polygon_offset: None,
+            stencil_func: None,
+            stencil_op: None,
+            stencil_mask: None,
+            scissor: None,
+            viewport: None,
+        }
+    }
+
+    /// Forgets everything, the next call of each kind reaches the driver again.
+    #[allow(dead_code)]
+    pub fn reset(&mut self) {
+        *self = GlState::new(&self.device);
+    }
+
+    pub fn use_program(&mut self, program: gl::types::GLuint) {
+        if self.program != Some(program) {
+            self.device.use_program(program);
+            self.program = Some(program);
+        }
+    }
+
+    pub fn bind_vertex_array(&mut self, vertex_array: gl::types::GLuint) {
+        if self.vertex_array != Some(vertex_array) {
+            self.device.bind_vertex_array(vertex_array);
+            self.vertex_array = Some(vertex_array);
+        }
+    }
+
+    pub fn bind_texture_unit(&mut self, unit: u32, texture: gl::types::GLuint) {
+        let cached = &mut self.textures[unit as usize];
+
+        if *cached != Some(texture) {
+            self.device.bind_texture_unit(unit, texture);
+            *cached = Some(texture);
+        }
+    }
+
+    /// `gl::Enable`/`gl::Disable` for a capability such as `gl::BLEND` or `gl::DEPTH_TEST`.
+    pub fn set_enabled(&mut self, capability: gl::types::GLenum, enabled: bool) {
+        if self.capabilities.get(&capability) != Some(&enabled) {
+            self.device.set_enabled(capability, enabled);
+            self.capabilities.insert(capability, enabled);
+        }
+    }
+
+    pub fn blend_equation(&mut self, equation: gl::types::GLenum) {
+        if self.blend_equation != Some(equation) {
+            self.device.blend_equation(equation);
+            self.blend_equation = Some(equation);
+        }
+    }
+
+    pub fn blend_func(&mut self, source: gl::types::GLenum, destination: gl::types::GLenum) {
+        if self.blend_func != Some((source, destination)) {
+            self.device.blend_func(source, destination);
+            self.blend_func = Some((source, destination));
+        }
+    }
+
+    pub fn depth_func(&mut self, func: gl::types::GLenum) {
+        if self.depth_func != Some(func) {
+            self.device.depth_func(func);
+            self.depth_func = Some(func);
+        }
+    }
+
+    pub fn depth_mask(&mut self, write: bool) {
+        if self.depth_mask != Some(write) {
+            self.device.depth_mask(write);
+            self.depth_mask = Some(write);
+        }
+    }
+
+    pub fn cull_face(&mut self, face: gl::types::GLenum) {
+        if self.cull_face != Some(face) {
+            self.device.cull_face(face);
+            self.cull_face = Some(face);
+        }
+    }
+
+    pub fn front_face(&mut self, winding: gl::types::GLenum) {
+        if self.front_face != Some(winding) {
+            self.device.front_face(winding);
+            self.front_face = Some(winding);
+        }
+    }
+
+    /// Fill, line or point rasterization, for both front and back faces.
+    pub fn polygon_mode(&mut self, mode: gl::types::GLenum) {
+        if self.polygon_mode != Some(mode) {
+            self.device.polygon_mode(mode);
+            self.polygon_mode = Some(mode);
+        }
+    }
+
+    pub fn polygon_offset(&mut self, factor: f32, units: f32) {
+        if self.polygon_offset != Some((factor, units)) {
+            self.device.polygon_offset(factor, units);
+            self.polygon_offset = Some((factor, units));
+        }
+    }
+
+    pub fn stencil_func(&mut self, func: gl::types::GLenum, reference: i32, mask: u32) {
+        if self.stencil_func != Some((func, reference, mask)) {
+            self.device.stencil_func(func, reference, mask);
+            self.stencil_func = Some((func, reference, mask));
+        }
+    }
+
+    pub fn stencil_op(&mut self, stencil_fail: gl::types::GLenum, depth_fail: gl::types::GLenum, pass: gl::types::GLenum) {
+        if self.stencil_op != Some((stencil_fail, depth_fail, pass)) {
+            self.device.stencil_op(stencil_fail, depth_fail, pass);
+            self.stencil_op = Some((stencil_fail, depth_fail, pass));
+        }
+    }
+
+    pub fn stencil_mask(&mut self, mask: u32) {
+        if self.stencil_mask != Some(mask) {
+            self.device.stencil_mask(mask);
+            self.stencil_mask = Some(mask);
+        }
+    }
+
+    pub fn scissor(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        if self.scissor != Some((x, y, width, height)) {
+            self.device.scissor(x, y, width, height);
+            self.scissor = Some((x, y, width, height));
+        }
+    }
+
+    pub fn viewport(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        if self.viewport != Some((x, y, width, height)) {
+            self.device.viewport(x, y, width, height);
+            self.viewport = Some((x, y, width, height));
+        }
+    }
+
+    /// Compares everything known with what the driver reports and panics on the first
+    /// difference. Queries stall the pipeline, so this only does anything in debug builds.
+    #[allow(dead_code)]
+    pub fn verify(&self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let integer = |name: gl::types::GLenum| {
+            let mut value: [gl::types::GLint; 1] = [0];
+            self.device.get_integers(name, &mut value);
+            value[0] as gl::types::GLuint
+        };
+
+        check("program", self.program, integer(gl::CURRENT_PROGRAM));
+        check("vertex array", self.vertex_array, integer(gl::VERTEX_ARRAY_BINDING));
+
+        for (unit, cached) in self.textures.iter().enumerate() {
+            let texture = match *cached {
+                Some(texture) => texture,
+                None => continue,
+            };
+
+            // a unit has a binding point per target and the texture is bound to one of them;
+            // those can only be queried for the active unit
+            self.device.active_texture(unit as u32);
+            let bound: Vec<gl::types::GLuint> = [gl::TEXTURE_BINDING_2D, gl::TEXTURE_BINDING_2D_ARRAY, gl::TEXTURE_BINDING_3D]
+                .iter()
+                .map(|&target| integer(target))
+                .collect();
+
+            let actual = if bound.contains(&texture) { texture } else { bound[0] };
+            check(&format!("texture unit {}", unit), Some(texture), actual);
+        }
+        self.device.active_texture(0);
+
+        for (&capability, &enabled) in self.capabilities.iter() {
+            let actual = self.device.is_enabled(capability);
+            check(&format!("capability 0x{:x}", capability), Some(enabled), actual);
+        }
+
+        check("blend equation", self.blend_equation, integer(gl::BLEND_EQUATION_RGB));
+
+        if let Some((source, destination)) = self.blend_func {
+            check("blend source", Some(source), integer(gl::BLEND_SRC_RGB));
+            check("blend destination", Some(destination), integer(gl::BLEND_DST_RGB));
+        }
+
+        check("depth func", self.depth_func, integer(gl::DEPTH_FUNC));
+        check("depth mask", self.depth_mask, integer(gl::DEPTH_WRITEMASK) != 0);
+        check("cull face", self.cull_face, integer(gl::CULL_FACE_MODE));
+        check("front face", self.front_face, integer(gl::FRONT_FACE));
+
+        if let Some(mode) = self.polygon_mode {
+            // reported for front and back faces, which are always set together here
+            let mut actual: [gl::types::GLint; 2] = [0; 2];
+            self.device.get_integers(gl::POLYGON_MODE, &mut actual);
+            check("polygon mode", Some(mode), actual[0] as gl::types::GLuint);
+        }
+
+        if let Some((func, reference, mask)) = self.stencil_func {
+            check("stencil func", Some(func), integer(gl::STENCIL_FUNC));
+            check("stencil reference", Some(reference), integer(gl::STENCIL_REF) as i32);
+            check("stencil value mask", Some(mask), integer(gl::STENCIL_VALUE_MASK));
+        }
+
+        if let Some((stencil_fail, depth_fail, pass)) = self.stencil_op {
+            check("stencil fail op", Some(stencil_fail), integer(gl::STENCIL_FAIL));
+            check("stencil depth fail op", Some(depth_fail), integer(gl::STENCIL_PASS_DEPTH_FAIL));
+            check("stencil pass op", Some(pass), integer(gl::STENCIL_PASS_DEPTH_PASS));
+        }
+
+        check("stencil write mask", self.stencil_mask, integer(gl::STENCIL_WRITEMASK));
+
+        if let Some(scissor) = self.scissor {
+            let mut actual: [gl::types::GLint; 4] = [0; 4];
+            self.device.get_integers(gl::SCISSOR_BOX, &mut actual);
+            check("scissor", Some(scissor), (actual[0], actual[1], actual[2], actual[3]));
+        }
+
+        if let Some(viewport) = self.viewport {
+            let mut actual: [gl::types::GLint; 4] = [0; 4];
+            self.device.get_integers(gl::VIEWPORT, &mut actual);
+            check("viewport", Some(viewport), (actual[0], actual[1], actual[2], actual[3]));
+        }
+    }
+}
+
+fn check<T: PartialEq + std::fmt::Debug>(what: &str, cached: Option<T>, actual: T) {
+    if let Some(cached) = cached {
+        if cached != actual {
+            panic!("GL state cache is out of sync: {} is cached as {:?}, but is {:?}", what, cached, actual);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use gl;
+
+/// Owned immutable-storage texture, deleted on drop. Storage is allocated up front by the
+/// constructors and filled through direct state access.
+#[allow(dead_code)]
+pub struct Texture {
+    gl: gl::Gl,
+    id: gl::types::GLuint,
+    width: u32,
+    height: u32,
+}
+
+#[allow(dead_code)]
+impl Texture {
+    /// 2D texture with `levels` mip levels of the sized `format` (e.g. `gl::RGBA8`).
+    pub fn new_2d(gl: &gl::Gl, format: gl::types::GLenum, width: u32, height: u32, levels: u32) -> Texture {
+        let mut id: gl::types::GLuint = 0;
+
+        unsafe {
+            gl.CreateTextures(gl::TEXTURE_2D, 1, &mut id);
+            gl.TextureStorage2D(id, levels as gl::types::GLsizei, format, width as gl::types::GLsizei, height as gl::types::GLsizei);
+        }
+
+        let texture = Texture { gl: gl.clone(), id, width, height };
+
+        texture.set_filter(gl::LINEAR, gl::LINEAR);
+        texture.set_wrap(gl::CLAMP_TO_EDGE, gl::CLAMP_TO_EDGE);
+
+        texture
+    }
+
+    /// Multisampled 2D texture, only usable as a render target.
+    pub fn new_2d_multisample(gl: &gl::Gl, format: gl::types::GLenum, width: u32, height: u32, samples: u32) -> Texture {
+        let mut id: gl::types::GLuint = 0;
+
+        unsafe {
+            gl.CreateTextures(gl::TEXTURE_2D_MULTISAMPLE, 1, &mut id);
+            gl.TextureStorage2DMultisample(id, samples as gl::types::GLsizei, format, width as gl::types::GLsizei, height as gl::types::GLsizei, gl::TRUE);
+        }
+
+        Texture { gl: gl.clone(), id, width, height }
+    }
+
+    /// Array of `layers` 2D textures with `levels` mip levels sharing one format and size, e.g.
+    /// for shadow cascades.
+    pub fn new_2d_array(gl: &gl::Gl, format: gl::types::GLenum, width: u32, height: u32, layers: u32, levels: u32) -> Texture {
+        let mut id: gl::types::GLuint = 0;
+
+        unsafe {
+            gl.CreateTextures(gl::TEXTURE_2D_ARRAY, 1, &mut id);
+            gl.TextureStorage3D(id, levels as gl::types::GLsizei, format, width as gl::types::GLsizei, height as gl::types::GLsizei, layers as gl::types::GLsizei);
+        }
+
+        let texture = Texture { gl: gl.clone(), id, width, height };
+
+        texture.set_filter(gl::LINEAR, gl::LINEAR);
+        texture.set_wrap(gl::CLAMP_TO_EDGE, gl::CLAMP_TO_EDGE);
+
+        texture
+    }
+
+    /// 3D texture of `size` (width, height, depth) filled with `data` right away, e.g. a color
+    /// lookup table. `data_format` and `kind` describe the pixels like in `upload`.
+    pub fn new_3d<T: Copy>(
+        gl: &gl::Gl,
+        format: gl::types::GLenum,
+        size: (u32, u32, u32),
+        data_format: gl::types::GLenum,
+        kind: gl::types::GLenum,
+        data: &[T],
+    ) -> Texture {
+        let (width, height, depth) = size;
+        let mut id: gl::types::GLuint = 0;
+
+        check_length(data, size, data_format, kind);
+
+        unsafe {
+            gl.CreateTextures(gl::TEXTURE_3D, 1, &mut id);
+            gl.TextureStorage3D(id, 1, format, width as gl::types::GLsizei, height as gl::types::GLsizei, depth as gl::types::GLsizei);
+            gl.TextureSubImage3D(
+                id, 0,
+                0, 0, 0,
+                width as gl::types::GLsizei, height as gl::types::GLsizei, depth as gl::types::GLsizei,
+                data_format,
+                kind,
+                data.as_ptr() as *const gl::types::GLvoid,
+            );
+            gl.TextureParameteri(id, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as gl::types::GLint);
+        }
+
+        let texture = Texture { gl: gl.clone(), id, width, height };
+
+        texture.set_filter(gl::LINEAR, gl::LINEAR);
+        texture.set_wrap(gl::CLAMP_TO_EDGE, gl::CLAMP_TO_EDGE);
+
+        texture
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Uploads `data` into the whole of mip `level`. `format` and `kind` describe the pixels in
+    /// `data` (e.g. `gl::RGBA` and `gl::UNSIGNED_BYTE`), not the storage format.
+    pub fn upload<T: Copy>(&self, level: u32, format: gl::types::GLenum, kind: gl::types::GLenum, data: &[T]) {
+        let width = (self.width >> level).max(1);
+        let height = (self.height >> level).max(1);
+
+        check_length(data, (width, height, 1), format, kind);
+
+        unsafe {
+            self.gl.TextureSubImage2D(
+                self.id,
+                level as gl::types::GLint,
+                0, 0,
+                width as gl::types::GLsizei,
+                height as gl::types::GLsizei,
+                format,
+                kind,
+                data.as_ptr() as *const gl::types::GLvoid,
+            );
+        }
+    }
+
+    /// Uploads `data` into the top mip level of one layer of an array texture, described like
+    /// in `upload`.
+    pub fn upload_layer<T: Copy>(&self, layer: u32, format: gl::types::GLenum, kind: gl::types::GLenum, data: &[T]) {
+        check_length(data, (self.width, self.height, 1), format, kind);
+
+        unsafe {
+            self.gl.TextureSubImage3D(
+                self.id, 0,
+                0, 0, layer as gl::types::GLint,
+                self.width as gl::types::GLsizei, self.height as gl::types::GLsizei, 1,
+                format,
+                kind,
+                data.as_ptr() as *const gl::types::GLvoid,
+            );
+        }
+    }
+
+    pub fn generate_mipmaps(&self) {
+        unsafe {
+            self.gl.GenerateTextureMipmap(self.id);
+        }
+    }
+
+    pub fn set_filter(&self, min: gl::types::GLenum, mag: gl::types::GLenum) {
+        unsafe {
+            self.gl.TextureParameteri(self.id, gl::TEXTURE_MIN_FILTER, min as gl::types::GLint);
+            self.gl.TextureParameteri(self.id, gl::TEXTURE_MAG_FILTER, mag as gl::types::GLint);
+        }
+    }
+
+    pub fn set_wrap(&self, s: gl::types::GLenum, t: gl::types::GLenum) {
+        unsafe {
+            self.gl.TextureParameteri(self.id, gl::TEXTURE_WRAP_S, s as gl::types::GLint);
+            self.gl.TextureParameteri(self.id, gl::TEXTURE_WRAP_T, t as gl::types::GLint);
+        }
+    }
+
+    /// Turns a depth texture into one sampled with a `sampler*Shadow`, which compares against
+    /// the stored depth with `func` and returns the (filtered) result instead of the depth.
+    pub fn set_depth_compare(&self, func: gl::types::GLenum) {
+        unsafe {
+            self.gl.TextureParameteri(self.id, gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE as gl::types::GLint);
+            self.gl.TextureParameteri(self.id, gl::TEXTURE_COMPARE_FUNC, func as gl::types::GLint);
+        }
+    }
+
+    /// Makes the texture available to samplers bound to texture `unit`.
+    pub fn bind_unit(&self, unit: u32) {
+        unsafe {
+            self.gl.BindTextureUnit(unit, self.id);
+        }
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.DeleteTextures(1, &self.id);
+        }
+    }
+}
+
+/// Bytes GL reads for pixels of `size` (width, height, depth) described by `format` and `kind`,
+/// with every row starting on the default unpack alignment of 4 bytes.
+fn pixel_bytes(size: (u32, u32, u32), format: gl::types::GLenum, kind: gl::types::GLenum) -> usize {
+    let components = match format {
+        gl::RED | gl::DEPTH_COMPONENT | gl::STENCIL_INDEX => 1,
+        gl::RG => 2,
+        gl::RGB | gl::BGR => 3,
+        gl::RGBA | gl::BGRA => 4,
+        _ => panic!("unsupported pixel format 0x{:x}", format),
+    };
+    let component_size = match kind {
+        gl::BYTE | gl::UNSIGNED_BYTE => 1,
+        gl::SHORT | gl::UNSIGNED_SHORT | gl::HALF_FLOAT => 2,
+        gl::INT | gl::UNSIGNED_INT | gl::FLOAT => 4,
+        _ => panic!("unsupported pixel type 0x{:x}", kind),
+    };
+
+    let (width, height, depth) = (size.0 as usize, size.1 as usize, size.2 as usize);
+    let row = width * components * component_size;
+    let rows = height * depth;
+
+    if rows == 0 || row == 0 {
+        return 0;
+    }
+
+    // only the last row goes without its padding
+    (rows - 1) * (row + (4 - row % 4) % 4) + row
+}
+
+// panics unless `data` holds all the pixels GL is about to read from it
+fn check_length<T: Copy>(data: &[T], size: (u32, u32, u32), format: gl::types::GLenum, kind: gl::types::GLenum) {
+    let needed = pixel_bytes(size, format, kind);
+    let actual = std::mem::size_of_val(data);
+
+    assert!(actual >= needed, "texture upload of {} bytes, {}x{}x{} pixels need {}", actual, size.0, size.1, size.2, needed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_bytes_pad_every_row_but_the_last() {
+        assert_eq!(pixel_bytes((4, 4, 1), gl::RGBA, gl::UNSIGNED_BYTE), 64);
+        assert_eq!(pixel_bytes((2, 2, 2), gl::RGB, gl::FLOAT), 96);
+
+        // 3 byte rows are read 4 bytes apart
+        assert_eq!(pixel_bytes((1, 3, 1), gl::RGB, gl::UNSIGNED_BYTE), 11);
+        assert_eq!(pixel_bytes((0, 3, 1), gl::RGB, gl::UNSIGNED_BYTE), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "texture upload of 15 bytes")]
+    fn short_uploads_panic() {
+        check_length(&[0u8; 15], (2, 2, 1), gl::RGBA, gl::UNSIGNED_BYTE);
+    }
+}
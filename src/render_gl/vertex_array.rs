@@ -0,0 +1,51 @@
+use gl;
+use crate::render_gl::device::{Device};
+
+/// Owned vertex array object, deleted on drop. Attribute layout and buffer bindings are set up
+/// through direct state access; it only gets bound right before drawing.
+pub struct VertexArray {
+    device: Device,
+    id: gl::types::GLuint,
+}
+
+impl VertexArray {
+    pub fn new(device: &Device) -> VertexArray {
+        VertexArray { device: device.clone(), id: device.create_vertex_array() }
+    }
+
+    pub fn id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    #[allow(dead_code)]
+    pub fn bind(&self) {
+        self.device.bind_vertex_array(self.id);
+    }
+
+    /// Attaches the buffer with id `buffer` to a binding point; attributes pick the binding
+    /// point they read from in `attrib_format`.
+    pub fn vertex_buffer(&self, binding: u32, buffer: gl::types::GLuint, offset: usize, stride: usize) {
+        self.device.vertex_array_vertex_buffer(self.id, binding, buffer, offset, stride);
+    }
+
+    pub fn element_buffer(&self, buffer: gl::types::GLuint) {
+        self.device.vertex_array_element_buffer(self.id, buffer);
+    }
+
+    /// Enables a float attribute at `location` (layout (location = ..) in the shader) with
+    /// `components` values, read from `binding` at `relative_offset` bytes into each element.
+    pub fn attrib_format(&self, location: u32, components: usize, binding: u32, relative_offset: usize) {
+        self.device.vertex_array_attrib_format(self.id, location, components, binding, relative_offset);
+    }
+
+    /// How many instances share one element of `binding`; 0 advances per vertex.
+    pub fn binding_divisor(&self, binding: u32, divisor: u32) {
+        self.device.vertex_array_binding_divisor(self.id, binding, divisor);
+    }
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        self.device.delete_vertex_array(self.id);
+    }
+}
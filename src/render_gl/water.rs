@@ -0,0 +1,233 @@
+extern crate nalgebra_glm as glm;
+
+use gl;
+use std::rc::Rc;
+
+use crate::resources::Resources;
+use crate::generators::primitives;
+use crate::render_gl::camera::{Camera};
+use crate::render_gl::device::{Device};
+use crate::render_gl::framebuffer::{self, Framebuffer};
+use crate::render_gl::light::{Light};
+use crate::render_gl::material::{Material};
+use crate::render_gl::object::{Object};
+use crate::render_gl::buffer::{BufferUsage};
+use crate::render_gl::render_state::{RenderState};
+use crate::render_gl::render_target::{RenderTarget};
+use crate::render_gl::shader::{self, Program};
+use crate::render_gl::state::{GlState};
+
+// `layout (binding = ..)` of the samplers in the water shader
+const REFLECTION_UNIT: u32 = 9;
+const REFRACTION_UNIT: u32 = 10;
+const REFRACTION_DEPTH_UNIT: u32 = 11;
+
+/// How far the clip planes reach past the surface, so the waves don't pull in gaps at the shore.
+const CLIP_BIAS: f32 = 0.1;
+
+#[derive(Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Failed to build the water shader")]
+    Shader(#[cause] shader::Error),
+    #[fail(display = "Failed to create the water reflection and refraction buffers")]
+    Framebuffer(#[cause] framebuffer::Error),
+}
+
+impl From<shader::Error> for Error {
+    fn from(other: shader::Error) -> Self {
+        Error::Shader(other)
+    }
+}
+
+impl From<framebuffer::Error> for Error {
+    fn from(other: framebuffer::Error) -> Self {
+        Error::Framebuffer(other)
+    }
+}
+
+/// World space planes for `clip_plane` at a water `level`: the first keeps what is above the
+/// water for the reflection, the second what is below for the refraction.
+pub fn clip_planes(level: f32) -> (glm::Vec4, glm::Vec4) {
+    (
+        glm::vec4(0.0, 1.0, 0.0, -(level - CLIP_BIAS)),
+        glm::vec4(0.0, -1.0, 0.0, level + CLIP_BIAS),
+    )
+}
+
+/// A flat sea at height `level`. Every frame `render_passes` draws the scene mirrored in the
+/// surface and the part of it under the surface into half-size targets, and `draw` blends the
+/// surface over the scene with both, fading it out where the water gets shallow.
+#[allow(dead_code)]
+pub struct Water {
+    pub level: f32,
+    /// what the deep water fades to
+    pub color: glm::Vec3,
+    /// depth in world units at which the ground below can't be seen anymore
+    pub clarity: f32,
+    /// depth over which the surface fades in at the shore
+    pub shore_fade: f32,
+    /// how far the waves shift the reflection and refraction, in screen fractions
+    pub wave_strength: f32,
+    /// world units across the longest wave
+    pub wave_scale: f32,
+    pub wave_speed: f32,
+    surface: Object,
+    reflection: RenderTarget,
+    refraction: RenderTarget,
+    time: f32,
+    gl: gl::Gl,
+}
+
+#[allow(dead_code)]
+impl Water {
+    /// A square of water `size` across centered on the origin, for a view of `width` by `height`.
+    pub fn new(gl: &gl::Gl, device: &Device, res: &Resources, level: f32, size: f32, width: u32, height: u32) -> Result<Water, Error> {
+        let program = Rc::new(Program::from_res(device, res, "shaders/water")?);
+
+        let mut surface = Object::from_mesh(
+            device, Rc::new(Material::new(program)), primitives::plane(size, size, 1, 1), BufferUsage::Static
+        );
+        surface.render_state = RenderState::transparent();
+        surface.matrix = glm::translate(&glm::identity(), &glm::vec3(0.0, level, 0.0));
+
+        let (width, height) = target_size(width, height);
+
+        Ok(Water {
+            level,
+            color: glm::vec3(0.05, 0.2, 0.25),
+            clarity: 8.0,
+            shore_fade: 1.5,
+            wave_strength: 0.02,
+            wave_scale: 6.0,
+            wave_speed: 1.0,
+            surface,
+            reflection: RenderTarget::new(gl, width, height, &[gl::RGBA16F], Some(gl::DEPTH_COMPONENT24), 0)?,
+            refraction: RenderTarget::new(gl, width, height, &[gl::RGBA16F], Some(gl::DEPTH_COMPONENT24), 0)?,
+            time: 0.0,
+            gl: gl.clone(),
+        })
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), Error> {
+        let (width, height) = target_size(width, height);
+        self.reflection.resize(width, height)?;
+        self.refraction.resize(width, height)?;
+        Ok(())
+    }
+
+    /// Moves the waves on by `seconds`.
+    pub fn update(&mut self, seconds: f32) {
+        self.time += seconds;
+    }
+
+    /// Renders the reflection and refraction of `objects` as seen by `camera`. Their programs
+    /// need the `clip_plane` uniform. Leaves the default framebuffer bound, the caller has to
+    /// bind its own target again.
+    pub fn render_passes(&mut self, state: &mut GlState, camera: &Camera, clear_color: (f32, f32, f32, f32), objects: &[&Object]) {
+        self.surface.matrix = glm::translate(&glm::identity(), &glm::vec3(0.0, self.level, 0.0));
+
+        let (above, below) = clip_planes(self.level);
+        let mirrored = camera.mirrored(self.level);
+
+        // the clear honours the depth mask, so the state has to be in place first
+        RenderState::opaque().apply(state);
+        state.set_enabled(gl::CLIP_DISTANCE0, true);
+
+        // the mirror turns every triangle around, so what is culled has to swap its front faces
+        for &(target, view, plane, mirror) in [(&self.reflection, &mirrored, &above, true), (&self.refraction, camera, &below, false)].iter() {
+            target.bind(state);
+            target.clear(clear_color);
+
+            for obj in objects {
+                obj.material.program.set_vec4("clip_plane", plane);
+
+                if mirror {
+                    view.draw_as(state, obj, &obj.render_state.mirrored());
+                } else {
+                    view.draw(state, obj);
+                }
+            }
+
+            target.resolve();
+        }
+
+        for obj in objects {
+            obj.material.program.set_vec4("clip_plane", &glm::vec4(0.0, 0.0, 0.0, 0.0));
+        }
+        state.set_enabled(gl::CLIP_DISTANCE0, false);
+
+        Framebuffer::bind_default(&self.gl);
+    }
+
+    /// Blends the surface over what is drawn so far, after `render_passes` for the same camera.
+    pub fn draw(&self, state: &mut GlState, camera: &Camera, sun: &Light) {
+        state.bind_texture_unit(REFLECTION_UNIT, self.reflection.color(0).id());
+        state.bind_texture_unit(REFRACTION_UNIT, self.refraction.color(0).id());
+        if let Some(depth) = self.refraction.depth() {
+            state.bind_texture_unit(REFRACTION_DEPTH_UNIT, depth.id());
+        }
+
+        let (near, far) = camera.clip_range();
+        let program = &self.surface.material.program;
+        program.set_vec2("clip_range", &glm::vec2(near, far));
+        program.set_float("time", self.time);
+        program.set_vec3("water_color", &self.color);
+        program.set_float("clarity", self.clarity);
+        program.set_float("shore_fade", self.shore_fade);
+        program.set_float("wave_strength", self.wave_strength);
+        program.set_float("wave_scale", self.wave_scale);
+        program.set_float("wave_speed", self.wave_speed);
+        program.set_vec3("sun_direction", &sun.direction);
+        program.set_vec3("sun_color", &(sun.color * sun.intensity));
+
+        camera.draw(state, &self.surface);
+    }
+}
+
+// the passes only feed a wobbly surface, half the resolution is plenty
+fn target_size(width: u32, height: u32) -> (u32, u32) {
+    ((width / 2).max(1), (height / 2).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(camera: &Camera, point: &glm::Vec3) -> glm::Vec3 {
+        let clip = camera.projection() * camera.matrix * glm::vec4(point.x, point.y, point.z, 1.0);
+        clip.xyz() / clip.w
+    }
+
+    #[test]
+    fn mirrored_camera_sees_reflections_where_the_surface_is() {
+        let mut camera = Camera::make(800, 600, 45.0, 0.1, 1000.0);
+        camera.reposition_and_look_at(&glm::vec3(10.0, 20.0, 30.0), &glm::vec3(0.0, 2.0, 0.0));
+        let mirrored = camera.mirrored(3.0);
+
+        assert!((mirrored.position - glm::vec3(10.0, -14.0, 30.0)).norm() < 1e-4);
+
+        // a point above the water shows up where the camera sees its mirror image under it
+        let point = glm::vec3(4.0, 8.0, -5.0);
+        let image = glm::vec3(4.0, -2.0, -5.0);
+        assert!((project(&mirrored, &point) - project(&camera, &image)).norm() < 1e-4);
+
+        // points on the surface stay where they are
+        let surface = glm::vec3(-6.0, 3.0, 2.0);
+        assert!((project(&mirrored, &surface) - project(&camera, &surface)).norm() < 1e-4);
+    }
+
+    #[test]
+    fn clip_planes_split_at_the_level() {
+        let (above, below) = clip_planes(3.0);
+        let distance = |plane: &glm::Vec4, y: f32| glm::dot(plane, &glm::vec4(0.0, y, 0.0, 1.0));
+
+        assert!(distance(&above, 5.0) > 0.0);
+        assert!(distance(&above, 1.0) < 0.0);
+        assert!(distance(&below, 1.0) > 0.0);
+        assert!(distance(&below, 5.0) < 0.0);
+
+        // both keep the surface itself, so nothing goes missing along the shore
+        assert!(distance(&above, 3.0) > 0.0);
+        assert!(distance(&below, 3.0) > 0.0);
+    }
+}
@@ -36,6 +36,25 @@ impl Resources {
         })
     }
 
+    /// Resources under `root_path` as is, for when they don't live next to the executable.
+    #[allow(dead_code)]
+    pub fn from_path(root_path: &Path) -> Resources {
+        Resources { root_path: root_path.into() }
+    }
+
+    pub fn load_string(&self, resource_name: &str) -> Result<String, Error> {
+        Ok(fs::read_to_string(resource_name_to_path(&self.root_path, resource_name))?)
+    }
+
+    pub fn load_bytes(&self, resource_name: &str) -> Result<Vec<u8>, Error> {
+        Ok(fs::read(resource_name_to_path(&self.root_path, resource_name))?)
+    }
+
+    /// Where `resource_name` is on disk, for loaders that want a path.
+    pub fn path(&self, resource_name: &str) -> PathBuf {
+        resource_name_to_path(&self.root_path, resource_name)
+    }
+
     pub fn load_cstring(&self, resource_name: &str) -> Result<ffi::CString, Error> {
         let mut file = fs::File::open(
             resource_name_to_path(&self.root_path, resource_name)
@@ -64,4 +83,85 @@ fn resource_name_to_path(root_dir: &Path, location: &str) -> PathBuf {
     }
 
     path
-}
\ No newline at end of file
+}
+
+/// One line of a resource file written as `rule name=value ...`, like post-processing chains and
+/// terrain styles.
+#[derive(Debug, PartialEq)]
+pub struct Line<'a> {
+    /// counting from 1, for error messages
+    pub number: usize,
+    pub rule: &'a str,
+    pub values: Vec<(&'a str, &'a str)>,
+}
+
+/// Splits `source` into its lines of `rule name=value ...`, skipping blank lines and `#`
+/// comments. A word that isn't `name=value` fails with its line number and a message.
+pub fn parse_lines(source: &str) -> Result<Vec<Line<'_>>, (usize, String)> {
+    let mut lines = vec![];
+
+    for (i, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut words = line.split_whitespace();
+
+        let rule = match words.next() {
+            Some(rule) => rule,
+            None => continue,
+        };
+
+        let mut values = vec![];
+
+        for word in words {
+            let mut parts = word.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if !key.is_empty() && !value.is_empty() => values.push((key, value)),
+                _ => return Err((i + 1, format!("expected name=value, found {}", word))),
+            }
+        }
+
+        lines.push(Line { number: i + 1, rule, values });
+    }
+
+    Ok(lines)
+}
+
+/// The comma separated numbers of a `value`. Infinities and NaN aren't numbers here, nothing
+/// these files describe can use them.
+pub fn parse_numbers(value: &str) -> Result<Vec<f32>, String> {
+    value.split(',')
+        .map(|v| v.parse::<f32>().ok().filter(|n| n.is_finite()))
+        .collect::<Option<Vec<f32>>>()
+        .ok_or_else(|| format!("{} is not a number or vector", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_skip_comments_and_blank_lines() {
+        let lines = parse_lines("# header\n\nfirst a=1 b=x,y # note\n   second\n").unwrap();
+
+        assert_eq!(lines, vec![
+            Line { number: 3, rule: "first", values: vec![("a", "1"), ("b", "x,y")] },
+            Line { number: 4, rule: "second", values: vec![] },
+        ]);
+    }
+
+    #[test]
+    fn lines_need_name_value_words() {
+        for &word in ["a", "=1", "a=", "="].iter() {
+            let source = format!("ok\nrule {}\n", word);
+            assert_eq!(parse_lines(&source), Err((2, format!("expected name=value, found {}", word))));
+        }
+    }
+
+    #[test]
+    fn numbers_are_finite() {
+        assert_eq!(parse_numbers("1,-2.5,3e2"), Ok(vec![1.0, -2.5, 300.0]));
+
+        for &value in ["nan", "inf", "1,-inf", "1,,2", "one"].iter() {
+            assert_eq!(parse_numbers(value), Err(format!("{} is not a number or vector", value)));
+        }
+    }
+}
@@ -0,0 +1,305 @@
+extern crate nalgebra_glm as glm;
+
+use gl;
+use std::rc::Rc;
+
+use crate::resources::Resources;
+use crate::generators::chunks::{ChunkLayout};
+use crate::generators::heightfield::{Heightfield, heightfield_mesh};
+use crate::generators::mountain::{mountain_heightfield};
+use crate::generators::primitives;
+use crate::generators::streaming::{StreamingStats, StreamingTerrain};
+use crate::generators::style::{TerrainStyle};
+use crate::generators::terrain::{Terrain, TerrainSettings};
+
+use crate::render_gl;
+use crate::render_gl::buffer::{BufferUsage};
+use crate::render_gl::device::{Device, GlDevice};
+use crate::render_gl::object::{Object, Instance};
+use crate::render_gl::camera::{Camera};
+use crate::render_gl::state::{GlState};
+use crate::render_gl::debug::{DebugDraw};
+use crate::render_gl::light::{Light, LightSet};
+use crate::render_gl::material::{Material};
+use crate::render_gl::mesh::{Mesh, Shading};
+use crate::render_gl::render_target::{RenderTarget};
+use crate::render_gl::post::{PostProcess};
+use crate::render_gl::shadow::{ShadowMap};
+use crate::render_gl::water::{Water};
+
+const MAX_LIGHTS: usize = 8;
+const SHADOW_RESOLUTION: u32 = 2048;
+const SHADOW_CASCADES: usize = 3;
+const MSAA_SAMPLES: u32 = 4;
+const CLEAR_COLOR: (f32, f32, f32, f32) = (0.3, 0.3, 0.5, 1.0);
+const SEA_LEVEL: f32 = 3.0;
+const LANDSCAPE_BUDGET: usize = 400;
+const LANDSCAPE_WORKERS: usize = 2;
+
+/// The demo world and everything needed to draw a frame of it, shared by the window and the
+/// headless renderer.
+pub struct Scene {
+    pub camera: Camera,
+    pub debug_draw: DebugDraw,
+    /// the mountain's heights, for putting things on the ground
+    pub ground: Heightfield,
+    gl_state: GlState,
+    lights: LightSet,
+    shadow_map: ShadowMap,
+    pbr_program: Rc<render_gl::Program>,
+    mountain: Object,
+    square: Object,
+    rocks: Object,
+    rock_spin: f32,
+    // rolling hills around the mountain, as far as the eye can see
+    landscape: StreamingTerrain,
+    water: Water,
+    size: (u32, u32),
+    // the scene is rendered off-screen in HDR with multisampling, then post-processed
+    scene_target: RenderTarget,
+    post_process: PostProcess,
+}
+
+impl Scene {
+    pub fn new(gl: &gl::Gl, res: &Resources, width: u32, height: u32) -> Result<Scene, failure::Error> {
+        let device: Device = Rc::new(GlDevice::new(gl));
+
+        let debug_draw = DebugDraw::new(&device, res)?;
+
+        let mut lights = LightSet::new(&device, MAX_LIGHTS);
+        lights.lights.push(Light::directional(&glm::vec3(-0.4, -1.0, -0.3), &glm::vec3(1.0, 0.95, 0.85), 3.0));
+        lights.lights.push(Light::point(&glm::vec3(0.0, 50.0, 0.0), &glm::vec3(1.0, 0.5, 0.2), 3.0, 40.0));
+        lights.upload();
+
+        let shadow_map = ShadowMap::new(gl, &device, res, SHADOW_RESOLUTION, SHADOW_CASCADES)?;
+
+        let mut lit_defines = lights.defines();
+        lit_defines.extend(shadow_map.defines());
+
+        let pbr_program = Rc::new(render_gl::Program::from_res_with_defines(
+            &device, res, "shaders/pbr", &lit_defines
+        )?);
+
+        let rocks_program = Rc::new(render_gl::Program::from_res(
+            &device, res, "shaders/instanced"
+        )?);
+
+        let mut mountain_material = Material::new(pbr_program.clone());
+        mountain_material.roughness = 0.9;
+
+        let mut square_material = Material::new(pbr_program.clone());
+        square_material.metallic = 1.0;
+        square_material.roughness = 0.35;
+
+        let rocks_material = Material::new(rocks_program);
+
+        let mut camera: Camera = Camera::make(
+            width,
+            height,
+            45.0,
+            0.1,
+            1000.0,
+        );
+
+        camera.reposition_and_look_at(&glm::vec3(0.0, 80.0, 0.0), &glm::vec3(0.0, 10.0, 0.0));
+
+        let ground = mountain_heightfield(100.0, 100.0, 45.0, 20);
+        let style = TerrainStyle::from_res(res, "terrain/mountain.style")?;
+        let mut mountain_mesh = heightfield_mesh(&ground, Shading::Flat);
+        style.color_mesh(&mut mountain_mesh, (0.0, 45.0));
+        mountain_material.splat = Some(style.make_splat(gl, res, &ground, (0.0, 45.0))?);
+        let mountain: Object = Object::from_mesh(&device, Rc::new(mountain_material), mountain_mesh, BufferUsage::Static);
+
+        let square: Object = Object::from_mesh(&device, Rc::new(square_material), cube_mesh(), BufferUsage::Static);
+        let rocks: Object = Object::from_mesh(&device, Rc::new(rocks_material), cube_mesh(), BufferUsage::Static);
+
+        let mut landscape_material = Material::new(pbr_program.clone());
+        landscape_material.base_color = glm::vec4(0.55, 0.7, 0.35, 1.0);
+        landscape_material.roughness = 0.9;
+        let landscape = StreamingTerrain::new(
+            &device,
+            Rc::new(landscape_material),
+            Terrain::new(TerrainSettings { seed: 3, scale: 150.0, height: 14.0, ..TerrainSettings::default() }),
+            ChunkLayout { chunk_size: 64.0, resolution: 16, lod_count: 3, lod_distance: 128.0, view_distance: 384.0, ..ChunkLayout::default() },
+            LANDSCAPE_BUDGET,
+            LANDSCAPE_WORKERS,
+        )?;
+
+        let scene_target = RenderTarget::new(
+            gl, width, height, &[gl::RGBA16F], Some(gl::DEPTH24_STENCIL8), MSAA_SAMPLES
+        )?;
+        let post_process = PostProcess::new(gl, &device, res, "shaders/post/default.chain", width, height)?;
+        let water = Water::new(gl, &device, res, SEA_LEVEL, 400.0, width, height)?;
+
+        Ok(Scene {
+            camera,
+            debug_draw,
+            ground,
+            gl_state: GlState::new(&device),
+            lights,
+            shadow_map,
+            pbr_program,
+            mountain,
+            square,
+            rocks,
+            rock_spin: 0.0,
+            landscape,
+            water,
+            size: (width, height),
+            scene_target,
+            post_process,
+        })
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), failure::Error> {
+        self.size = (width, height);
+        self.scene_target.resize(width, height)?;
+        self.post_process.resize(width, height)?;
+        self.water.resize(width, height)?;
+        Ok(())
+    }
+
+    /// Reloads the post-processing chain; a broken chain or shader keeps the passes that were
+    /// working.
+    pub fn reload(&mut self, res: &Resources) -> Result<(), failure::Error> {
+        self.post_process.reload(res)?;
+
+        // the old pass programs are gone and their ids may be handed out again
+        self.gl_state.reset();
+        Ok(())
+    }
+
+    /// Builds all of the landscape in view right away, instead of streaming it in over the next
+    /// frames. For renders that are saved after a frame or two.
+    pub fn load_landscape(&mut self) -> Result<(), failure::Error> {
+        Ok(self.landscape.load_all(&self.camera.position)?)
+    }
+
+    pub fn landscape_stats(&self) -> StreamingStats {
+        self.landscape.stats()
+    }
+
+    /// Advances the animation by one frame, and streams in the landscape around the camera.
+    pub fn update(&mut self) {
+        // square.matrix = glm::rotate_y(&square.matrix, glm::radians(&glm::vec1(2.0)).x);
+        // camera.matrix = glm::translate(&camera.matrix, &glm::vec3(0.0, 0.0, -0.01));
+
+        self.rock_spin = self.rock_spin + 0.01;
+        self.rocks.set_instances(&rock_instances(&self.ground, self.rock_spin));
+        self.water.update(1.0 / 60.0);
+        self.landscape.update(&self.camera.position);
+    }
+
+    /// Draws a frame into the window's framebuffer, or into `output` when given.
+    pub fn render(&mut self, output: Option<&RenderTarget>) {
+        let shadow_bounds = self.shadow_bounds();
+        let state = &mut self.gl_state;
+
+        // the rocks are instanced, which the depth shader doesn't handle, so they cast no shadows
+        let mut casters = vec![&self.square, &self.mountain];
+        casters.extend(self.landscape.objects());
+
+        self.shadow_map.update(&self.camera, &self.lights.lights[0].direction, &shadow_bounds);
+        self.shadow_map.render(state, &casters);
+        self.shadow_map.bind(state, &self.pbr_program);
+
+        let mut objects = casters;
+        objects.push(&self.rocks);
+
+        self.water.render_passes(state, &self.camera, CLEAR_COLOR, &objects);
+
+        self.scene_target.bind(state);
+        self.scene_target.clear(CLEAR_COLOR);
+
+        for obj in objects.iter() {
+            self.camera.draw(state, obj);
+        }
+        self.water.draw(state, &self.camera, &self.lights.lights[0]);
+
+        self.debug_draw.draw_object(state, &self.camera, &self.square);
+        self.debug_draw.draw_object(state, &self.camera, &self.mountain);
+        self.debug_draw.draw_object(state, &self.camera, &self.rocks);
+        self.debug_draw.flush(state, &self.camera);
+
+        self.scene_target.resolve();
+        self.post_process.run_into(state, &self.scene_target, output, self.size);
+
+        state.verify();
+    }
+
+    /// Everything that can cast or catch a shadow: the mountain and the landscape in view.
+    fn shadow_bounds(&self) -> (glm::Vec3, glm::Vec3) {
+        let (min, max) = self.mountain.bounds();
+        let reach = self.landscape.layout.view_distance;
+        let position = self.camera.position;
+
+        (
+            glm::min2(&min, &glm::vec3(position.x - reach, 0.0, position.z - reach)),
+            glm::max2(&max, &glm::vec3(position.x + reach, 0.0, position.z + reach)),
+        )
+    }
+}
+
+/// The cube drawn as the square and the rocks, 0.6 across with one color per face.
+pub fn cube_mesh() -> Mesh {
+    let mut mesh = primitives::cube(0.6, 1);
+
+    mesh.colors = mesh.normals.iter().map(|&normal| match normal {
+        (_, _, z) if z < -0.5 => (1.0, 0.0, 0.0),
+        (_, _, z) if z > 0.5 => (0.0, 1.0, 0.0),
+        (x, _, _) if x < -0.5 => (0.0, 0.0, 1.0),
+        (x, _, _) if x > 0.5 => (1.0, 1.0, 0.0),
+        (_, y, _) if y < -0.5 => (1.0, 0.0, 1.0),
+        _ => (1.0, 1.0, 1.0),
+    }).collect();
+
+    mesh
+}
+
+fn rock_instances(ground: &Heightfield, spin: f32) -> Vec<Instance> {
+    const ROCK_COUNT: usize = 2000;
+
+    (0..ROCK_COUNT).filter_map(|i| {
+        // scatter the rocks on a band around the lower slopes of the mountain, inside the field
+        let angle = i as f32 * 2.399963; // golden angle, avoids visible rows
+        let distance = 25.0 + (i % 40) as f32 * 0.5;
+
+        let (x, z) = (angle.cos() * distance, angle.sin() * distance);
+        let height = ground.height_at(x, z);
+
+        // none under water
+        if height < SEA_LEVEL {
+            return None;
+        }
+
+        let position = glm::vec3(x, height + 0.3, z);
+        let model = glm::translate(&glm::identity(), &position);
+        let model = glm::rotate_y(&model, spin + angle);
+        let model = glm::scale(&model, &glm::vec3(1.0, 0.5 + (i % 3) as f32 * 0.5, 1.0));
+
+        let shade = 0.6 + (i % 5) as f32 * 0.1;
+
+        Some(Instance::new(&model, &glm::vec4(shade, shade, shade, 1.0)))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rocks_sit_on_the_mountain_above_the_water() {
+        let ground = mountain_heightfield(100.0, 100.0, 45.0, 20);
+        let rocks = rock_instances(&ground, 0.0);
+
+        assert!(!rocks.is_empty());
+
+        for rock in rocks.iter() {
+            let position = rock.model().column(3).xyz();
+
+            assert!(position.x.abs() < 50.0 && position.z.abs() < 50.0, "{:?} is off the mountain", position);
+            assert!(position.y > SEA_LEVEL, "{:?} is under water", position);
+            assert!((position.y - ground.height_at(position.x, position.z) - 0.3).abs() < 1e-4);
+        }
+    }
+}